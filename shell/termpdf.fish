@@ -0,0 +1,14 @@
+# Source this from config.fish to get `termpdfcd`, which runs termpdf and then
+# changes to the directory of the file you were reading when you quit.
+
+function termpdfcd
+    set tmp (mktemp)
+    termpdf --last-dir-path $tmp $argv
+    if test -s $tmp
+        set dir (cat $tmp)
+        if test -d "$dir"; and test "$dir" != (pwd)
+            cd $dir
+        end
+    end
+    rm -f $tmp
+end
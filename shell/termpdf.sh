@@ -0,0 +1,17 @@
+# Source this from your .bashrc or .zshrc to get `termpdfcd`, which runs
+# termpdf and then changes to the directory of the file you were reading
+# when you quit.
+#
+#     . /path/to/termpdf/shell/termpdf.sh
+
+termpdfcd() {
+    tmp="$(mktemp)"
+    termpdf --last-dir-path "$tmp" "$@"
+    if [ -s "$tmp" ]; then
+        dir="$(cat "$tmp")"
+        if [ -d "$dir" ] && [ "$dir" != "$(pwd)" ]; then
+            cd "$dir" || return
+        fi
+    fi
+    rm -f "$tmp"
+}
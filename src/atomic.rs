@@ -0,0 +1,73 @@
+//! Writing files so that a crash part way through never leaves a truncated
+//! one behind: the new contents go to a temporary file next to the old one,
+//! which is then renamed over it. Anything termpdf writes back into a
+//! document should go through here, since that may be the only copy.
+
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+
+/// `file.pdf.~1~` is the most recent backup, `file.pdf.~2~` the one before
+/// and so on, like `cp --backup=numbered`
+fn backup_name(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".~{}~", n));
+    PathBuf::from(name)
+}
+
+/// Keep the current contents of `path` as backup 1, shuffling older ones
+/// along and dropping any beyond `keep`
+fn back_up(path: &Path, keep: usize) -> Result<()> {
+    let _ = fs::remove_file(backup_name(path, keep));
+    for n in (1..keep).rev() {
+        let from = backup_name(path, n);
+        if from.exists() {
+            fs::rename(&from, backup_name(path, n + 1))?;
+        }
+    }
+    let first = backup_name(path, 1);
+    // A hard link keeps the original in place until the rename replaces it
+    if fs::hard_link(path, &first).is_err() {
+        fs::copy(path, &first)?;
+    }
+    Ok(())
+}
+
+/// Replace `path` with `contents`, keeping up to `backups` numbered copies
+/// of what was there before
+pub fn write(path: impl AsRef<Path>, contents: impl AsRef<[u8]>, backups: usize) -> Result<()> {
+    let path = path.as_ref();
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let tmp = dir.join(format!(".{}.{}.tmp", name, std::process::id()));
+
+    let written = File::create(&tmp).and_then(|mut f| {
+        f.write_all(contents.as_ref())?;
+        f.sync_all()
+    });
+    let result = written
+        .map_err(anyhow::Error::from)
+        .and_then(|_| match backups > 0 && path.exists() {
+            true => back_up(path, backups),
+            false => Ok(()),
+        })
+        .and_then(|_| Ok(fs::rename(&tmp, path)?));
+    if result.is_err() {
+        let _ = fs::remove_file(&tmp);
+    }
+    result.with_context(|| format!("Couldn't write {}", path.display()))?;
+
+    // Make the rename itself survive a crash
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+    Ok(())
+}
@@ -0,0 +1,230 @@
+//! Rendered pages kept on disk in `$XDG_CACHE_HOME/termpdf/pages`, so
+//! reopening a document (or a preview of it from a file manager) doesn't
+//! render the same pages again. Several termpdf processes can use it at once:
+//! an entry is only ever replaced whole (see `atomic`), and whoever is
+//! rendering a page holds a lock on it that the others wait for.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs::{self, File, OpenOptions};
+use std::hash::{Hash, Hasher};
+use std::io::{BufRead, Cursor, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::Result;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{DynamicImage, ImageEncoder};
+use serde_json::{json, Value};
+
+use crate::config::home_dir;
+use crate::{atomic, graphics, render};
+
+static LIMIT: OnceLock<u64> = OnceLock::new();
+
+/// How many bytes of pages to keep, 0 for none
+fn limit() -> u64 {
+    *LIMIT.get().unwrap_or(&0)
+}
+
+pub fn cache_dir() -> PathBuf {
+    match env::var_os("XDG_CACHE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("termpdf"),
+        _ => home_dir().join(".cache").join("termpdf"),
+    }
+}
+
+fn pages_dir() -> PathBuf {
+    cache_dir().join("pages")
+}
+
+/// Keep up to `megabytes` of rendered pages, and trim the cache down to that
+/// in the background
+pub fn configure(megabytes: u64) {
+    let _ = LIMIT.set(megabytes * 1024 * 1024);
+    if megabytes > 0 {
+        thread::spawn(prune);
+    }
+}
+
+/// What a rendering depends on: the file as it is now, the page and how
+/// it's rendered
+pub struct Key {
+    text: String,
+    path: PathBuf,
+}
+
+impl Key {
+    pub fn new(file: &str, page: usize, size: (u32, u32), rotate: bool) -> Option<Key> {
+        if limit() == 0 {
+            return None;
+        }
+        let path = Path::new(file).canonicalize().ok()?;
+        let metadata = fs::metadata(&path).ok()?;
+        let modified = metadata
+            .modified()
+            .ok()?
+            .duration_since(UNIX_EPOCH)
+            .ok()?
+            .as_nanos();
+        let text = format!(
+            "{}\0{}\0{}\0{}\0{}x{}\0{}\0{:?}\0{:?}\0{}\0{}\0{:?}",
+            path.display(),
+            modified,
+            metadata.len(),
+            page,
+            size.0,
+            size.1,
+            rotate,
+            render::matte(),
+            graphics::tone(),
+            render::dark(),
+            render::print_preview(),
+            render::quality()
+        );
+        let mut hasher = DefaultHasher::new();
+        text.hash(&mut hasher);
+        Some(Key {
+            path: pages_dir().join(format!("{:016x}", hasher.finish())),
+            text,
+        })
+    }
+
+    /// Hold this while rendering the page, so another process that wants it
+    /// too waits and then finds it here rather than rendering it as well
+    pub fn lock(&self) -> Option<File> {
+        fs::create_dir_all(pages_dir()).ok()?;
+        let mut name = self.path.clone().into_os_string();
+        name.push(".lock");
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(name)
+            .ok()?;
+        // SAFETY: flock only looks at the descriptor, which `file` keeps
+        // open; the lock goes when it's closed
+        match unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } {
+            0 => Some(file),
+            _ => None,
+        }
+    }
+}
+
+/// A page from the cache
+pub struct Entry {
+    pub image: DynamicImage,
+    pub page: usize,
+    pub length: usize,
+    pub text: String,
+    pub title: String,
+}
+
+/// Whether there's an entry for `key`, without reading it
+pub fn contains(key: &Key) -> bool {
+    key.path.exists()
+}
+
+/// Entries are a line of JSON followed by the page as a PNG
+pub fn get(key: &Key) -> Option<Entry> {
+    let mut reader = std::io::BufReader::new(File::open(&key.path).ok()?);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let header: Value = serde_json::from_str(&line).ok()?;
+    // Different keys can hash the same
+    if header["key"].as_str() != Some(key.text.as_str()) {
+        return None;
+    }
+    let mut png = vec![];
+    reader.read_to_end(&mut png).ok()?;
+    Some(Entry {
+        image: image::load_from_memory(&png).ok()?,
+        page: header["page"].as_u64()? as usize,
+        length: header["length"].as_u64()? as usize,
+        text: header["text"].as_str()?.to_string(),
+        title: header["title"].as_str().unwrap_or_default().to_string(),
+    })
+}
+
+fn encode(key: &Key, entry: &Entry) -> Result<Vec<u8>> {
+    let header = json!({
+        "key": key.text,
+        "page": entry.page,
+        "length": entry.length,
+        "text": entry.text,
+        "title": entry.title,
+    });
+    let mut data = format!("{}\n", header).into_bytes();
+    let rgba = entry.image.to_rgba8();
+    let mut png = Cursor::new(vec![]);
+    PngEncoder::new_with_quality(&mut png, CompressionType::Fast, FilterType::Sub).write_image(
+        rgba.as_raw(),
+        rgba.width(),
+        rgba.height(),
+        image::ColorType::Rgba8,
+    )?;
+    data.extend(png.into_inner());
+    Ok(data)
+}
+
+/// Store a page, keeping hold of `lock` (from `Key::lock`) until it's
+/// written. Compressing it takes a while, so that happens in the background.
+pub fn put(key: Key, entry: Entry, lock: Option<File>) {
+    thread::spawn(move || {
+        if let Ok(data) = encode(&key, &entry) {
+            let _ = atomic::write(&key.path, data, 0);
+        }
+        drop(lock);
+    });
+}
+
+/// How long a lock is left without its page before it's taken to be from a
+/// render that never finished, rather than one going on now
+const STALE_LOCK: Duration = Duration::from_secs(60);
+
+/// Remove the least recently used pages until the cache fits its limit, and
+/// the locks of pages that were never written
+fn prune() {
+    let entries = match fs::read_dir(pages_dir()) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    let (locks, pages): (Vec<PathBuf>, Vec<PathBuf>) = entries
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_none_or(|ext| ext == "lock"))
+        .partition(|path| path.extension().is_some());
+    for lock in locks {
+        let old = fs::metadata(&lock)
+            .and_then(|m| m.modified())
+            .ok()
+            .and_then(|modified| modified.elapsed().ok())
+            .is_some_and(|age| age > STALE_LOCK);
+        if old && !lock.with_extension("").exists() {
+            let _ = fs::remove_file(&lock);
+        }
+    }
+
+    let mut pages: Vec<(std::time::SystemTime, u64, PathBuf)> = pages
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            let used = metadata.accessed().or_else(|_| metadata.modified()).ok()?;
+            Some((used, metadata.len(), path))
+        })
+        .collect();
+    let mut total: u64 = pages.iter().map(|(_, size, _)| size).sum();
+    pages.sort();
+    for (_, size, path) in pages {
+        if total <= limit() {
+            break;
+        }
+        let _ = fs::remove_file(&path);
+        let mut lock = path.into_os_string();
+        lock.push(".lock");
+        let _ = fs::remove_file(lock);
+        total -= size;
+    }
+}
@@ -0,0 +1,137 @@
+//! `termpdf --check`: what this terminal can do, asked afresh, and what
+//! termpdf will do without what it can't. For working out why pages come up
+//! blank.
+
+use std::env;
+
+use crate::config::Config;
+use crate::graphics::{self, Protocol};
+use crate::{clipboard, external, probe};
+
+fn yes_no(answer: Option<bool>) -> &'static str {
+    match answer {
+        Some(true) => "yes",
+        Some(false) => "no",
+        None => "no answer",
+    }
+}
+
+pub fn run(config: &Config) {
+    if !termion::is_tty(&std::io::stdin()) || !termion::is_tty(&std::io::stdout()) {
+        println!("Not running in a terminal, so there's nothing to ask.");
+        return;
+    }
+    let capabilities = probe::probe();
+    graphics::configure(config, capabilities.protocol);
+    let mut degraded = vec![];
+
+    let var = |name| env::var(name).unwrap_or_default();
+    println!(
+        "Terminal:       TERM={} TERM_PROGRAM={}",
+        var("TERM"),
+        var("TERM_PROGRAM")
+    );
+    if env::var_os("TMUX").is_some() {
+        println!("                inside tmux");
+    }
+    match &capabilities.name {
+        Some(name) => println!("Says it is:     {}", name),
+        None => println!("Says it is:     (no answer)"),
+    }
+
+    let (cols, rows) = termion::terminal_size().unwrap_or((0, 0));
+    let (width, height) = termion::terminal_size_pixels().unwrap_or((0, 0));
+    match (width, height) {
+        (0, _) | (_, 0) => println!("Window:         {}×{} cells, no size in pixels", cols, rows),
+        _ => println!(
+            "Window:         {}×{} cells, {}×{} pixels",
+            cols, rows, width, height
+        ),
+    }
+    match capabilities.cell {
+        Some((w, h)) => println!("Cell size:      {}×{} pixels", w, h),
+        None => println!("Cell size:      no answer"),
+    }
+    if let Some((w, h)) = config.cell_pixels {
+        println!("                taken as {}×{} pixels, as set", w, h);
+    }
+    if width == 0 && capabilities.cell.is_none() && config.cell_pixels.is_none() {
+        degraded.push("Page sizes are guessed, assuming cells twice as tall as they are wide");
+    }
+
+    let kitty = capabilities.protocol.map(|p| p == Protocol::Kitty);
+    let sixel = match capabilities.protocol {
+        Some(Protocol::Kitty) | None => None,
+        Some(p) => Some(p == Protocol::Sixel),
+    };
+    println!("Kitty graphics: {}", yes_no(kitty));
+    println!("Sixels:         {}", yes_no(sixel));
+    println!("SGR mouse:      {}", yes_no(capabilities.mouse));
+
+    let protocol = graphics::protocol();
+    let why = if config.graphics.is_some() {
+        "set by the graphics setting or --graphics"
+    } else if Protocol::detect().is_some() {
+        "going by $TERM and $TERM_PROGRAM"
+    } else if capabilities.protocol.is_some() {
+        "going by the terminal's answers"
+    } else {
+        "nothing else to go on"
+    };
+    println!("Pages drawn as: {} ({})", protocol.name(), why);
+    if matches!(protocol, Protocol::Iterm | Protocol::Kitty) {
+        match graphics::format() {
+            graphics::Format::Jpeg => println!(
+                "                sent as jpeg, quality {}",
+                graphics::jpeg_quality()
+            ),
+            format => println!("                sent as {}", format.name()),
+        }
+    }
+    if graphics::low_bandwidth() {
+        println!("                rendered at half size, for a slow connection");
+    }
+    let quirks = graphics::quirks();
+    if quirks.prescale {
+        println!("                scaled to fit before they're sent");
+    }
+    if quirks.multipart && protocol == Protocol::Iterm {
+        println!("                sent in parts");
+    }
+    if quirks.files && protocol == Protocol::Kitty {
+        println!("                left in temporary files for the terminal to read");
+    }
+    match protocol {
+        Protocol::Text => degraded.push("No graphics: pages are drawn with coloured blocks"),
+        Protocol::Iterm if config.graphics.is_none() && Protocol::detect().is_none() => degraded
+            .push(
+            "The terminal didn't answer; if pages are blank, try --graphics kitty, sixel or text",
+        ),
+        _ => {}
+    }
+
+    let (text, image) = clipboard::commands(config);
+    let installed = |command: &str| match external::installed(command) {
+        true => "found",
+        false => "not found",
+    };
+    println!("Copying text:   {} ({})", text, installed(text));
+    println!("Copying images: {} ({})", image, installed(image));
+    if !external::installed(text) {
+        degraded.push("Text is copied with OSC 52, which the terminal may refuse (in tmux it needs set-clipboard on)");
+    }
+    if !external::installed(image) {
+        degraded.push("Screenshots can be saved but not copied");
+    }
+
+    println!();
+    match degraded.is_empty() {
+        true => println!("Everything should work."),
+        false => {
+            println!("Degraded:");
+            for d in degraded {
+                println!("  - {}", d);
+            }
+        }
+    }
+}
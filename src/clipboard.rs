@@ -0,0 +1,88 @@
+//! Putting things on the system clipboard with whatever tool the platform
+//! has, or a configured command.
+
+use std::env;
+use std::io::Write;
+use std::process::Stdio;
+
+use anyhow::{bail, Result};
+use base64::engine::general_purpose;
+use base64::Engine as _;
+
+use crate::config::Config;
+use crate::external;
+use crate::temp::TempDir;
+use crate::term;
+
+fn default_text_command() -> &'static str {
+    if cfg!(target_os = "macos") {
+        "pbcopy"
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        "wl-copy"
+    } else {
+        "xclip -selection clipboard"
+    }
+}
+
+fn default_image_command() -> &'static str {
+    if cfg!(target_os = "macos") {
+        r#"osascript -e 'set the clipboard to (read (POSIX file "{file}") as «class PNGf»)'"#
+    } else if env::var_os("WAYLAND_DISPLAY").is_some() {
+        "wl-copy --type image/png"
+    } else {
+        "xclip -selection clipboard -t image/png"
+    }
+}
+
+/// Run `template` with `data`, either written to a temporary file named by
+/// `{file}` or, if the template doesn't mention one, on standard input
+fn run(template: &str, data: &[u8], extension: &str) -> Result<()> {
+    let status = if template.contains("{file}") {
+        let dir = TempDir::new("termpdf-clipboard")?;
+        let path = dir.write(&format!("clipboard.{}", extension), data)?;
+        let path_str = path.to_string_lossy().to_string();
+        external::command(template, &[("file", &path_str)])?
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()?
+    } else {
+        let mut child = external::command(template, &[])?
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(data)?;
+        }
+        child.wait()?
+    };
+    if !status.success() {
+        bail!("'{}' exited with {}", template, status);
+    }
+    Ok(())
+}
+
+/// The commands that copy text and images
+pub fn commands(config: &Config) -> (&str, &str) {
+    let text = config.clipboard_command.as_deref();
+    let image = config.image_clipboard_command.as_deref();
+    (
+        text.unwrap_or(default_text_command()),
+        image.unwrap_or(default_image_command()),
+    )
+}
+
+pub fn copy_image(png: &[u8], config: &Config) -> Result<()> {
+    run(commands(config).1, png, "png")
+}
+
+/// Copy text with the clipboard command, falling back to asking the
+/// terminal to do it (OSC 52), which also works over SSH
+pub fn copy_text(text: &str, config: &Config) -> Result<()> {
+    if run(commands(config).0, text.as_bytes(), "txt").is_ok() {
+        return Ok(());
+    }
+    // Sent from background jobs too, so it mustn't land inside a page
+    let sequence = format!("\x1b]52;c;{}\x07", general_purpose::STANDARD.encode(text));
+    term::write_sequence(sequence.as_bytes())
+}
@@ -0,0 +1,198 @@
+//! Finding the body text on a page, to zoom in to it with `T`: the column
+//! (or columns) most of the text is in, leaving out the margins and what's
+//! in them, like marginal notes, running heads and page numbers.
+//!
+//! Where it is is kept as fractions of the page the right way up, as poster
+//! mode has it.
+
+use anyhow::Result;
+use pdfium_render::prelude::*;
+use serde_json::Value;
+
+use crate::layout::View;
+use crate::query::Query;
+use crate::render::{self, Fractions};
+
+/// How finely the page is divided up, each way, to see where the text is
+const BINS: usize = 200;
+/// Text across the page less than this part of the biggest block of it is
+/// taken to be notes in the margin
+const SIDE_NOTES: f64 = 0.3;
+/// Text down the page less than this part of the biggest block of it is a
+/// running head, a footer or a page number
+const HEADS: f64 = 0.1;
+/// How far apart (in bins) text down the page is to be a block of its own,
+/// rather than the next paragraph
+const HEAD_GAP: usize = 6;
+/// Left either side of the text, as a part of the page's width
+const PAD: f64 = 0.02;
+
+pub struct Column {
+    /// Where the text is on the page
+    rect: Fractions,
+    /// The page's size in points
+    page: (f64, f64),
+}
+
+/// The first and last bin of the blocks of them with at least `keep` times
+/// as much in as the biggest block, blocks being split by runs of at least
+/// `gap` empty bins
+fn band(weights: &[f64], gap: usize, keep: f64) -> Option<(usize, usize)> {
+    let mut blocks: Vec<(usize, usize, f64)> = vec![];
+    let mut empty = gap;
+    for (i, &w) in weights.iter().enumerate() {
+        if w <= 0.0 {
+            empty += 1;
+            continue;
+        }
+        match blocks.last_mut() {
+            Some(block) if empty < gap => {
+                block.1 = i;
+                block.2 += w;
+            }
+            _ => blocks.push((i, i, w)),
+        }
+        empty = 0;
+    }
+    let most = blocks.iter().map(|b| b.2).fold(0.0, f64::max);
+    let kept: Vec<_> = blocks.iter().filter(|b| b.2 >= most * keep).collect();
+    Some((kept.first()?.0, kept.last()?.1))
+}
+
+/// Count `from..to` (fractions of the page) in each of the bins it covers
+fn spread(bins: &mut [f64], (from, to): (f64, f64)) {
+    let first = ((from * BINS as f64) as usize).min(BINS - 1);
+    let last = ((to * BINS as f64) as usize).clamp(first, BINS - 1);
+    for bin in &mut bins[first..=last] {
+        *bin += 1.0;
+    }
+}
+
+/// Where the body text is on page `p` of `file`, None if there's no text
+pub fn on_page(file: &str, p: usize) -> Result<Option<Column>> {
+    let answer = render::ask(file, &Query::Column { page: p })?;
+    if answer.is_null() {
+        return Ok(None);
+    }
+    let (rect, page) = serde_json::from_value(answer)?;
+    Ok(Some(Column { rect, page }))
+}
+
+/// `on_page`, for the renderer
+pub fn column_in(document: &PdfDocument, p: usize) -> Result<Option<Column>> {
+    let page = document.pages().get(p as u16)?;
+    let (width, height) = (page.width().value as f64, page.height().value as f64);
+    if width <= 0.0 || height <= 0.0 {
+        return Ok(None);
+    }
+
+    // Each letter's box, as fractions of the page: left, top, right, bottom
+    let text = page.text()?;
+    let mut boxes = vec![];
+    for c in text.chars().iter() {
+        if c.unicode_char().is_none_or(char::is_whitespace) {
+            continue;
+        }
+        if let Ok(bounds) = c.loose_bounds() {
+            // PDF coordinates go up from the bottom of the page
+            let left = (bounds.left.value as f64 / width).clamp(0.0, 1.0);
+            let right = (bounds.right.value as f64 / width).clamp(0.0, 1.0);
+            let top = (1.0 - bounds.top.value as f64 / height).clamp(0.0, 1.0);
+            let bottom = (1.0 - bounds.bottom.value as f64 / height).clamp(0.0, 1.0);
+            boxes.push((left, top, right, bottom));
+        }
+    }
+
+    let mut across = vec![0.0; BINS];
+    for &(left, _, right, _) in &boxes {
+        spread(&mut across, (left, right));
+    }
+    let (first, last) = match band(&across, 1, SIDE_NOTES) {
+        Some(band) => band,
+        None => return Ok(None),
+    };
+    let (left, right) = (first as f64 / BINS as f64, (last + 1) as f64 / BINS as f64);
+
+    let mut down = vec![0.0; BINS];
+    let body = boxes
+        .iter()
+        .filter(|b| (b.0 + b.2) / 2.0 >= left && (b.0 + b.2) / 2.0 <= right);
+    for &(_, top, _, bottom) in body {
+        spread(&mut down, (top, bottom));
+    }
+    let (first, last) = match band(&down, HEAD_GAP, HEADS) {
+        Some(band) => band,
+        None => return Ok(None),
+    };
+    let (top, bottom) = (first as f64 / BINS as f64, (last + 1) as f64 / BINS as f64);
+
+    Ok(Some(Column {
+        rect: ((left - PAD).max(0.0), top, (right + PAD).min(1.0), bottom),
+        page: (width, height),
+    }))
+}
+
+impl Column {
+    pub fn to_json(&self) -> Value {
+        serde_json::to_value((self.rect, self.page)).unwrap_or_default()
+    }
+
+    /// The zoom at which the text is as wide as `area` (pixels), and the
+    /// spot to look at to see the top of it
+    pub fn fit(&self, area: (u32, u32)) -> (f64, (f64, f64)) {
+        let (left, top, right, _) = self.rect;
+        let page = (self.page.0.round() as u32, self.page.1.round() as u32);
+        let zoom = View::Width.zoom(page, area) / (right - left).max(0.01);
+        // How much of the page's height the window shows at that zoom
+        let fit = (area.0 as f64 / self.page.0).min(area.1 as f64 / self.page.1);
+        let shown = area.1 as f64 / (self.page.1 * fit * zoom);
+        (zoom, ((left + right) / 2.0, top + shown / 2.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn small_blocks_are_left_out() {
+        let weights = [0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 5.0, 5.0, 0.0];
+        assert_eq!(band(&weights, 2, 0.3), Some((6, 7)));
+        assert_eq!(band(&weights, 2, 0.1), Some((1, 7)));
+    }
+
+    #[test]
+    fn short_gaps_dont_split_blocks() {
+        let weights = [0.0, 1.0, 1.0, 0.0, 0.0, 0.0, 5.0, 5.0, 0.0];
+        // The gap of three is part of the block, so there's only one
+        assert_eq!(band(&weights, 4, 0.3), Some((1, 7)));
+    }
+
+    #[test]
+    fn no_text_is_no_band() {
+        assert_eq!(band(&[0.0; 10], 1, 0.3), None);
+        assert_eq!(band(&[], 1, 0.3), None);
+    }
+
+    #[test]
+    fn spread_covers_the_bins_in_range() {
+        let mut bins = vec![0.0; BINS];
+        spread(&mut bins, (0.1, 0.2));
+        assert_eq!(bins.iter().sum::<f64>(), 21.0);
+        assert_eq!(
+            (bins[19], bins[20], bins[40], bins[41]),
+            (0.0, 1.0, 1.0, 0.0)
+        );
+    }
+
+    #[test]
+    fn spread_stays_on_the_page() {
+        let mut bins = vec![0.0; BINS];
+        spread(&mut bins, (0.995, 1.0));
+        assert_eq!(bins[BINS - 1], 1.0);
+        // Backwards, as a letter with broken bounds might be
+        spread(&mut bins, (0.5, 0.4));
+        assert_eq!(bins[100], 1.0);
+        assert_eq!(bins.iter().sum::<f64>(), 2.0);
+    }
+}
@@ -0,0 +1,86 @@
+//! Parsing the commands typed at the `:` prompt.
+
+use anyhow::{bail, Result};
+
+use crate::watch::AutoRefresh;
+use crate::{filter, Msg};
+
+fn words(args: &str) -> Vec<String> {
+    args.split_whitespace().map(String::from).collect()
+}
+
+pub fn parse(line: &str) -> Result<Msg> {
+    let line = line.trim();
+    let (name, args) = match line.split_once(char::is_whitespace) {
+        Some((name, args)) => (name, args.trim()),
+        None => (line, ""),
+    };
+
+    match name {
+        "" => Ok(Msg::None),
+        _ if name.chars().all(|c| c.is_ascii_digit()) => Ok(Msg::Goto(name.parse()?)),
+        "q" | "quit" => Ok(Msg::Quit),
+        "trash" => Ok(Msg::Trash),
+        "rename" if args.is_empty() => Ok(Msg::SuggestName),
+        "rename" => Ok(Msg::Rename(args.to_string())),
+        "tag" => Ok(Msg::Tag(words(args))),
+        "untag" if args.is_empty() => bail!("Usage: :untag TAG..."),
+        "untag" => Ok(Msg::Untag(words(args))),
+        "filter" => Ok(Msg::Filter(args.to_string())),
+        "queue" => Ok(Msg::Queue(args.to_string())),
+        "outline" => Ok(Msg::Outline(args.to_string())),
+        "review" => Ok(Msg::Review(args.to_string())),
+        "flashcards" => Ok(Msg::Flashcards(args.to_string())),
+        "latex" => Ok(Msg::Select(crate::select::Purpose::Latex)),
+        "summarize" | "summarise" => Ok(Msg::Summarize(args.to_string())),
+        "lookup" | "define" => Ok(Msg::Lookup(args.to_string())),
+        "dwell" if args.is_empty() => bail!("Usage: :dwell FILE.csv|FILE.json"),
+        "dwell" => Ok(Msg::ExportDwell(args.to_string())),
+        "find" if args.is_empty() => bail!("Usage: :find TEXT"),
+        "find" => Ok(Msg::Find(args.to_string())),
+        "noh" | "nohlsearch" => Ok(Msg::NoHighlight),
+        "compare" if args.is_empty() => bail!("Usage: :compare PAGE | :compare FILE [PAGE]"),
+        "compare" => Ok(Msg::Compare(args.to_string())),
+        "speak" => Ok(Msg::Speak),
+        "where" => Ok(Msg::Announce),
+        "profile" => Ok(Msg::Profile(args.to_string())),
+        "set" => match args {
+            "hlsearch" | "hls" => Ok(Msg::HlSearch(true)),
+            "nohlsearch" | "nohls" => Ok(Msg::HlSearch(false)),
+            "darkmode" | "dark" => Ok(Msg::Dark(true)),
+            "autorefresh" => Ok(Msg::AutoRefresh(AutoRefresh::On)),
+            "noautorefresh" => Ok(Msg::AutoRefresh(AutoRefresh::Off)),
+            "nodarkmode" | "nodark" => Ok(Msg::Dark(false)),
+            "" => bail!("Usage: :set [no]hlsearch | :set [no]darkmode | :set autorefresh on|notify|off | :set zoom page|global | :set SETTING=VALUE"),
+            _ if args.starts_with("autorefresh") => {
+                let value = args["autorefresh".len()..].trim_start_matches([' ', '=']);
+                match AutoRefresh::parse(value.trim()) {
+                    Some(a) => Ok(Msg::AutoRefresh(a)),
+                    None => bail!("Usage: :set autorefresh on|notify|off"),
+                }
+            }
+            _ if args.starts_with("zoom") => {
+                match args["zoom".len()..].trim_start_matches([' ', '=']).trim() {
+                    "page" => Ok(Msg::ZoomPerPage(true)),
+                    "global" => Ok(Msg::ZoomPerPage(false)),
+                    _ => bail!("Usage: :set zoom page|global"),
+                }
+            }
+            _ => match args.split_once('=') {
+                Some((key, value)) if filter::is_setting(key.trim()) => Ok(Msg::SetColor(
+                    key.trim().to_string(),
+                    value.trim().to_string(),
+                )),
+                _ => bail!("Can't set {} here", args),
+            },
+        },
+        "goto" | "page" if args.is_empty() => bail!("Usage: :goto NUMBER|LABEL"),
+        "goto" | "page" => match args.parse() {
+            Ok(number) => Ok(Msg::Goto(number)),
+            Err(_) => Ok(Msg::GotoLabel(args.to_string())),
+        },
+        // Anything else may be a page label, like `:iv` or `:A-3`
+        _ if args.is_empty() => Ok(Msg::GotoLabel(name.to_string())),
+        _ => bail!("Not a command: {}", name),
+    }
+}
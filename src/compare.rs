@@ -0,0 +1,128 @@
+//! Comparing the page on screen with another, for spotting what changed
+//! between two revisions of a document: either with the differences picked
+//! out in colour, or with the two laid over each other.
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use termion::event::Key;
+
+pub enum CompareResult {
+    /// The mode changed, so it has to be drawn again
+    Redraw,
+    Pending,
+    Close,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /// Ink only on this page in red, ink only on the other in blue, and
+    /// what they share faded
+    Diff,
+    /// Half of each
+    Blend,
+}
+
+/// How far apart two pixels' greys (out of 255) can be and still count as
+/// the same, so anti-aliasing that came out a little differently doesn't
+const THRESHOLD: i32 = 48;
+
+const ADDED: Rgba<u8> = Rgba([220, 30, 30, 255]);
+const REMOVED: Rgba<u8> = Rgba([30, 80, 230, 255]);
+
+pub struct Comparison {
+    pub mode: Mode,
+    /// The other page, the size of the one on screen
+    other: RgbaImage,
+    /// What the other page is, like "p. 4" or "old.pdf p. 4"
+    pub label: String,
+}
+
+fn grey(pixel: &Rgba<u8>) -> i32 {
+    let [r, g, b, _] = pixel.0;
+    (299 * r as i32 + 587 * g as i32 + 114 * b as i32) / 1000
+}
+
+impl Comparison {
+    /// Compare with `other`, scaled to the size of the page it's compared
+    /// with if it isn't already
+    pub fn new(other: &DynamicImage, (width, height): (u32, u32), label: &str) -> Comparison {
+        let other = match (other.width(), other.height()) == (width, height) {
+            true => other.to_rgba8(),
+            false => imageops::resize(other, width, height, imageops::FilterType::Triangle),
+        };
+        Comparison {
+            mode: Mode::Diff,
+            other,
+            label: label.to_string(),
+        }
+    }
+
+    pub fn help(&self, page: &DynamicImage) -> String {
+        let how = match self.mode {
+            Mode::Diff => "red only here, blue only there",
+            Mode::Blend => "blended",
+        };
+        format!(
+            "comparing with {} ({}), {}: Tab diff/blend  Esc close",
+            self.label,
+            self.differs(page),
+            how
+        )
+    }
+
+    pub fn key(&mut self, key: Key) -> CompareResult {
+        match key {
+            Key::Char('\t') | Key::Char(' ') => {
+                self.mode = match self.mode {
+                    Mode::Diff => Mode::Blend,
+                    Mode::Blend => Mode::Diff,
+                };
+                CompareResult::Redraw
+            }
+            Key::Esc | Key::Char('q') => CompareResult::Close,
+            _ => CompareResult::Pending,
+        }
+    }
+
+    /// How much of `page` differs from the other page, in words
+    fn differs(&self, page: &DynamicImage) -> String {
+        let page = page.to_rgba8();
+        let total = self.other.pixels().len().max(1);
+        let changed = page
+            .pixels()
+            .zip(self.other.pixels())
+            .filter(|(a, b)| (grey(a) - grey(b)).abs() > THRESHOLD)
+            .count();
+        match changed {
+            0 => "no differences".to_string(),
+            // Less than a hundredth of a percent is still worth knowing about
+            n if n * 10_000 < total => "a few pixels differ".to_string(),
+            n => format!("{:.2}% differs", n as f64 * 100.0 / total as f64),
+        }
+    }
+
+    /// `page` compared with the other page, as the mode has it
+    pub fn draw(&self, page: &DynamicImage) -> DynamicImage {
+        let mut image = page.to_rgba8();
+        for (here, there) in image.pixels_mut().zip(self.other.pixels()) {
+            let (a, b) = (grey(here), grey(there));
+            *here = match self.mode {
+                Mode::Diff if a + THRESHOLD < b => ADDED,
+                Mode::Diff if b + THRESHOLD < a => REMOVED,
+                // Faded most of the way to white, to leave the colours to
+                // the changes
+                Mode::Diff => {
+                    let faded = (255 - (255 - a) / 4) as u8;
+                    Rgba([faded, faded, faded, 255])
+                }
+                Mode::Blend => {
+                    let mut mixed = [0; 4];
+                    for (i, channel) in mixed.iter_mut().enumerate() {
+                        *channel = ((here.0[i] as u16 + there.0[i] as u16) / 2) as u8;
+                    }
+                    Rgba(mixed)
+                }
+            };
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+}
@@ -0,0 +1,561 @@
+//! User settings, read from `$XDG_CONFIG_HOME/termpdf/config`.
+//!
+//! The file is a list of `key = value` lines. Blank lines and lines starting
+//! with `#` are ignored. Lines after a `[name]` line are the settings of
+//! that profile, only used when it's picked.
+
+use std::env;
+use std::path::PathBuf;
+
+use anyhow::{bail, Result};
+
+use crate::dither::Dither;
+use crate::filter::{self, Filter};
+use crate::graphics::{Format, Protocol, Tone};
+use crate::layout::View;
+use crate::render::{Isolation, Quality};
+use crate::watch::AutoRefresh;
+use crate::Rotation;
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub rotation: Rotation,
+    /// Command used to reveal the current file. `{file}` and `{dir}` are
+    /// replaced with the file and its directory; without either the file is
+    /// appended.
+    pub file_manager: String,
+    /// Whether the file manager runs inside this terminal (lf, ranger,
+    /// yazi), in which case the viewer steps aside until it exits.
+    pub file_manager_in_terminal: bool,
+    /// Command links to web pages are opened with, `{url}` being replaced
+    /// with the address, or else appended
+    pub browser_command: String,
+    /// Whether `j` on the last page goes on to the next file (and `k` on the
+    /// first back to the end of the previous one), for books split into
+    /// several PDFs
+    pub cross_documents: bool,
+    /// Whether what `/` or `:find` found stays highlighted on every page, until
+    /// `:noh`
+    pub hlsearch: bool,
+    /// Whether each page keeps its own zoom, rather than the zoom following
+    /// from page to page
+    pub zoom_per_page: bool,
+    /// What's done when a document changes on disk, unless `:set
+    /// autorefresh` says otherwise for it
+    pub autorefresh: AutoRefresh,
+    /// How many numbered backups (`file.~1~`...) to keep of files termpdf
+    /// overwrites
+    pub backups: usize,
+    /// Whether pages are rendered in a separate process, which survives
+    /// pdfium crashing on a broken file
+    pub render_isolation: Isolation,
+    /// How smooth text and lines are drawn, and whether forms and
+    /// annotations are
+    pub render_quality: Quality,
+    /// Megabytes of rendered pages to keep on disk, shared with other
+    /// termpdf processes; 0 turns the cache off
+    pub cache_size: u64,
+    /// How images are sent to the terminal, None to guess from `$TERM`
+    pub graphics: Option<Protocol>,
+    /// How pages are encoded for iTerm2's protocol or kitty's, None for
+    /// each one's own choice
+    pub image_format: Option<Format>,
+    /// Quality of JPEG pages out of 100, None for 90 (60 in low-bandwidth
+    /// mode)
+    pub jpeg_quality: Option<u8>,
+    /// Whether pages are rendered smaller and sent as JPEG, for slow
+    /// connections, None to do it over ssh
+    pub low_bandwidth: Option<bool>,
+    /// Most colours in a sixel page's palette, from 16 to 256
+    pub sixel_colors: usize,
+    /// How sixel pages make up for colours that aren't in the palette
+    pub sixel_dither: Dither,
+    /// How many pixels wide and high terminal cells are, or None to go by
+    /// what the terminal reports
+    pub cell_pixels: Option<(u16, u16)>,
+    /// How many times taller than wide terminal cells are, or None to go
+    /// by the pixel size the terminal reports
+    pub cell_aspect: Option<f64>,
+    /// Cells kept clear around the page
+    pub margin: u16,
+    /// Call documents by their metadata title rather than their file name
+    pub title_names: bool,
+    /// Whether the page is centred in the window
+    pub center: bool,
+    /// Whether thumbnails of the pages around the one shown are kept down
+    /// the right, None to do it in windows wide enough
+    pub thumbnail_strip: Option<bool>,
+    /// How big pages are shown to start with: all of the page, or as wide
+    /// or as tall as the window
+    pub view: View,
+    /// Kilobytes of image sent for a page at most, 0 for no limit. Bigger
+    /// pages are compressed harder, then scaled down.
+    pub max_image_size: usize,
+    /// Whether pages are scaled to the pixels they cover before they're
+    /// sent, None to do it for terminals that need it
+    pub prescale_images: Option<bool>,
+    /// Whether big iTerm2 images are sent in parts, None to do it in iTerm2
+    pub iterm_multipart: Option<bool>,
+    /// Whether kitty reads pages from temporary files rather than being
+    /// sent them through the terminal, None to do it when it's local
+    pub kitty_files: Option<bool>,
+    /// Whether kitty images are shown with Unicode placeholders, or None
+    /// to use them inside tmux
+    pub kitty_placeholders: Option<bool>,
+    /// Whether pages drawn as text use 24-bit colour (rather than the 256
+    /// colour palette), None to go by `$COLORTERM`
+    pub text_truecolor: Option<bool>,
+    /// How pages drawn as text in 256 colours make up for the rest
+    pub text_dither: Dither,
+    /// Most page turns and reloads drawn per second, 0 for no limit
+    pub max_fps: u32,
+    /// Seconds between looks at the file when it can't be watched for
+    /// changes (on NFS, say), 0 to not look
+    pub poll_interval: f64,
+    /// Record time spent per page and write it here (CSV, or JSON for a
+    /// `.json` file) on exit
+    pub dwell_log: Option<String>,
+    /// Where `v` saves the part of the page it picks
+    pub screenshot_dir: String,
+    /// How much sharper than the page on screen saved selections are
+    pub screenshot_scale: f32,
+    /// Whether saved and copied selections have the file name and page
+    /// number stamped underneath
+    pub stamp_exports: bool,
+    /// Text laid across every page shown and every selection saved, with
+    /// `{user}` for the user's name, None for none
+    pub watermark: Option<String>,
+    /// How pages are recoloured before they're shown
+    pub filter: Filter,
+    /// Whether a page turned to is shown at once at a low resolution, then
+    /// again in full once it's rendered
+    pub progressive_render: bool,
+    /// The colour pages are rendered on, which shows through where they're
+    /// transparent
+    pub matte: [u8; 3],
+    /// Whether pages start out inverted
+    pub dark_mode: bool,
+    /// Whether pages start out in colour, grey or black and white
+    pub grayscale: Tone,
+    /// Whether the text of each page turned to is written out for a screen
+    /// reader to speak
+    pub screen_reader: bool,
+    /// Command that puts a PNG on the clipboard, given on standard input or
+    /// as `{file}`. The default depends on the platform.
+    pub image_clipboard_command: Option<String>,
+    /// Command that puts text on the clipboard, given on standard input
+    pub clipboard_command: Option<String>,
+    /// Command that turns a picture of an equation (`{file}`) into LaTeX
+    /// on standard output, like pix2tex
+    pub latex_ocr_command: String,
+    /// Command given text on standard input by `:summarize`, whose output is
+    /// shown as it arrives
+    pub summarize_command: String,
+    /// Command that prints the definition of `{word}`
+    pub dictionary_command: String,
+    /// Command that writes `{file}` with the outline in `{data}` (pdftk's
+    /// `update_info` format) to `{output}`
+    pub outline_command: String,
+    /// Command that's given `{text}`, where `:where` says this is, to speak
+    /// it; empty for none
+    pub announce_command: String,
+    /// The profiles there are, built in and from the file
+    pub profiles: Vec<String>,
+}
+
+/// The profiles that come with termpdf, for the usual places to read. A
+/// section of the same name in the file goes on top.
+const PROFILES: &[(&str, &[(&str, &str)])] = &[
+    (
+        "reading",
+        &[
+            ("view", "width"),
+            ("margin", "2"),
+            ("display_name", "title"),
+        ],
+    ),
+    (
+        "presentation",
+        &[
+            ("view", "page"),
+            ("margin", "0"),
+            ("rotate", "never"),
+            ("matte", "black"),
+        ],
+    ),
+    (
+        "ssh",
+        &[
+            ("low_bandwidth", "true"),
+            ("kitty_transfer", "tty"),
+            ("progressive_render", "false"),
+            ("max_fps", "10"),
+        ],
+    ),
+    (
+        "scanned-book",
+        &[("grayscale", "true"), ("contrast", "1.4"), ("gamma", "0.8")],
+    ),
+];
+
+impl Default for Config {
+    fn default() -> Self {
+        let file_manager = if cfg!(target_os = "macos") {
+            "open -R {file}"
+        } else {
+            "xdg-open {dir}"
+        };
+        let browser = if cfg!(target_os = "macos") {
+            "open {url}"
+        } else {
+            "xdg-open {url}"
+        };
+        Config {
+            rotation: Rotation::Auto,
+            file_manager: file_manager.to_string(),
+            file_manager_in_terminal: false,
+            browser_command: browser.to_string(),
+            cross_documents: false,
+            hlsearch: false,
+            zoom_per_page: true,
+            autorefresh: AutoRefresh::On,
+            backups: 1,
+            render_isolation: Isolation::None,
+            render_quality: Quality::default(),
+            cache_size: 256,
+            graphics: None,
+            image_format: None,
+            jpeg_quality: None,
+            low_bandwidth: None,
+            sixel_colors: 256,
+            sixel_dither: Dither::Auto,
+            cell_pixels: None,
+            cell_aspect: None,
+            margin: 1,
+            title_names: false,
+            center: true,
+            thumbnail_strip: Some(false),
+            view: View::Page,
+            max_image_size: 0,
+            prescale_images: None,
+            iterm_multipart: None,
+            kitty_files: None,
+            kitty_placeholders: None,
+            text_truecolor: None,
+            text_dither: Dither::Auto,
+            max_fps: 30,
+            poll_interval: 2.0,
+            dwell_log: None,
+            screenshot_dir: ".".to_string(),
+            screenshot_scale: 2.0,
+            stamp_exports: false,
+            watermark: None,
+            filter: Filter::default(),
+            progressive_render: true,
+            matte: [255, 255, 255],
+            dark_mode: false,
+            grayscale: Tone::Color,
+            screen_reader: false,
+            image_clipboard_command: None,
+            clipboard_command: None,
+            latex_ocr_command: "pix2tex {file}".to_string(),
+            summarize_command: "llm -s 'Summarize this text.'".to_string(),
+            dictionary_command: "dict {word}".to_string(),
+            outline_command: "pdftk {file} update_info_utf8 {data} output {output}".to_string(),
+            announce_command: String::new(),
+            profiles: PROFILES.iter().map(|(name, _)| name.to_string()).collect(),
+        }
+    }
+}
+
+pub fn config_dir() -> PathBuf {
+    match env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("termpdf"),
+        _ => home_dir().join(".config").join("termpdf"),
+    }
+}
+
+/// The config file, which needn't exist
+pub fn path() -> PathBuf {
+    config_dir().join("config")
+}
+
+pub fn home_dir() -> PathBuf {
+    env::var_os("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+}
+
+pub fn parse_bool(value: &str) -> Result<bool> {
+    match value {
+        "true" | "on" | "yes" | "1" => Ok(true),
+        "false" | "off" | "no" | "0" => Ok(false),
+        _ => bail!("expected true or false, got '{}'", value),
+    }
+}
+
+/// A width and height like `10x20`
+pub fn parse_size(value: &str) -> Option<(u16, u16)> {
+    let (width, height) = value.split_once(['x', '×'])?;
+    match (width.trim().parse(), height.trim().parse()) {
+        (Ok(width @ 1..), Ok(height @ 1..)) => Some((width, height)),
+        _ => None,
+    }
+}
+
+/// `white`, `black`, or `#rrggbb` (the `#` optional)
+pub fn parse_color(value: &str) -> Option<[u8; 3]> {
+    match value {
+        "white" => return Some([255, 255, 255]),
+        "black" => return Some([0, 0, 0]),
+        _ => {}
+    }
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    if hex.len() != 6 || !hex.is_ascii() {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    Some([channel(0)?, channel(2)?, channel(4)?])
+}
+
+impl Config {
+    /// Read the config file, falling back to the defaults if there isn't one,
+    /// with `profile`'s settings on top of the rest if one's picked: the
+    /// built-in ones, then the file's.
+    pub fn load(profile: Option<&str>) -> Result<Config> {
+        let path = path();
+        let mut config = Config::default();
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => bail!("Couldn't read {}: {}", path.display(), e),
+        };
+
+        let mut section: Option<&str> = None;
+        let mut picked = vec![];
+        for (n, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let name = name.trim();
+                if !config.profiles.iter().any(|p| p == name) {
+                    config.profiles.push(name.to_string());
+                }
+                section = Some(name);
+                continue;
+            }
+            let (key, value) = match line.split_once('=') {
+                Some((k, v)) => (k.trim(), v.trim()),
+                None => bail!("{}:{}: expected key = value", path.display(), n + 1),
+            };
+            // Other profiles' settings are still checked, on a config
+            // that's thrown away
+            let checked = match section {
+                None => config.set(key, value),
+                Some(name) if Some(name) == profile => {
+                    picked.push((n, key, value));
+                    Config::default().set(key, value)
+                }
+                Some(_) => Config::default().set(key, value),
+            };
+            if let Err(e) = checked {
+                bail!("{}:{}: {}", path.display(), n + 1, e);
+            }
+        }
+
+        let profile = match profile {
+            Some(profile) => profile,
+            None => return Ok(config),
+        };
+        if !config.profiles.iter().any(|p| p == profile) {
+            bail!(
+                "No profile called {} (there's {})",
+                profile,
+                config.profiles.join(", ")
+            );
+        }
+        let built_in = PROFILES.iter().find(|(name, _)| *name == profile);
+        for (key, value) in built_in.map_or(&[][..], |(_, settings)| settings) {
+            config.set(key, value)?;
+        }
+        for (n, key, value) in picked {
+            if let Err(e) = config.set(key, value) {
+                bail!("{}:{}: {}", path.display(), n + 1, e);
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        match key {
+            "rotate" => match Rotation::parse(value) {
+                Some(r) => self.rotation = r,
+                None => bail!("rotate must be auto, always or never"),
+            },
+            "file_manager" => self.file_manager = value.to_string(),
+            "file_manager_in_terminal" => self.file_manager_in_terminal = parse_bool(value)?,
+            "browser_command" => self.browser_command = value.to_string(),
+            "cross_documents" => self.cross_documents = parse_bool(value)?,
+            "hlsearch" => self.hlsearch = parse_bool(value)?,
+            "zoom_memory" => match value {
+                "page" => self.zoom_per_page = true,
+                "global" => self.zoom_per_page = false,
+                _ => bail!("zoom_memory must be page or global"),
+            },
+            "autorefresh" => match AutoRefresh::parse(value) {
+                Some(a) => self.autorefresh = a,
+                None => bail!("autorefresh must be on, notify or off"),
+            },
+            "render_isolation" => match Isolation::parse(value) {
+                Some(i) => self.render_isolation = i,
+                None => bail!("render_isolation must be none, process or sandbox"),
+            },
+            "antialias" => self.render_quality.antialias = parse_bool(value)?,
+            "lcd_text" => self.render_quality.lcd_text = parse_bool(value)?,
+            "native_text" => self.render_quality.native_text = parse_bool(value)?,
+            "render_forms" => self.render_quality.forms = parse_bool(value)?,
+            "render_annotations" => self.render_quality.annotations = parse_bool(value)?,
+            "cache_size" => match value.parse() {
+                Ok(n) => self.cache_size = n,
+                Err(_) => bail!("cache_size must be a number of megabytes"),
+            },
+            "image_format" => match Format::parse(value) {
+                Some(f) => self.image_format = f,
+                None => bail!("image_format must be auto, png, jpeg, tiff or raw"),
+            },
+            "jpeg_quality" => match value {
+                "auto" => self.jpeg_quality = None,
+                value => match value.parse() {
+                    Ok(n @ 1..=100) => self.jpeg_quality = Some(n),
+                    _ => bail!("jpeg_quality must be auto or from 1 to 100"),
+                },
+            },
+            "low_bandwidth" => match value {
+                "auto" => self.low_bandwidth = None,
+                value => self.low_bandwidth = Some(parse_bool(value)?),
+            },
+            "graphics" => match Protocol::parse(value) {
+                Some(p) => self.graphics = p,
+                None => bail!("graphics must be auto, iterm, kitty, sixel or text"),
+            },
+            "sixel_colors" => match value.parse() {
+                Ok(n @ 16..=256) => self.sixel_colors = n,
+                _ => bail!("sixel_colors must be from 16 to 256"),
+            },
+            "sixel_dither" => match Dither::parse(value) {
+                Some(d) => self.sixel_dither = d,
+                None => bail!("sixel_dither must be auto, none, ordered or floyd-steinberg"),
+            },
+            "cell_pixels" => match value {
+                "auto" => self.cell_pixels = None,
+                value => match parse_size(value) {
+                    Some(size) => self.cell_pixels = Some(size),
+                    None => bail!("cell_pixels must be auto or a size like 10x20"),
+                },
+            },
+            "cell_aspect" => match value {
+                "auto" => self.cell_aspect = None,
+                value => match value.parse() {
+                    Ok(n) if (0.5..=4.0).contains(&n) => self.cell_aspect = Some(n),
+                    _ => bail!("cell_aspect must be auto or from 0.5 to 4"),
+                },
+            },
+            "margin" => match value.parse() {
+                Ok(n @ 0..=20) => self.margin = n,
+                _ => bail!("margin must be a number of cells from 0 to 20"),
+            },
+            "center" => self.center = parse_bool(value)?,
+            "thumbnail_strip" => match value {
+                "auto" => self.thumbnail_strip = None,
+                value => self.thumbnail_strip = Some(parse_bool(value)?),
+            },
+            "view" => match View::parse(value) {
+                Some(v) => self.view = v,
+                None => bail!("view must be page, width or height"),
+            },
+            "display_name" => match value {
+                "file" => self.title_names = false,
+                "title" => self.title_names = true,
+                _ => bail!("display_name must be file or title"),
+            },
+            "max_image_size" => match value.parse() {
+                Ok(n) => self.max_image_size = n,
+                Err(_) => bail!("max_image_size must be a number of kilobytes"),
+            },
+            "prescale_images" => match value {
+                "auto" => self.prescale_images = None,
+                value => self.prescale_images = Some(parse_bool(value)?),
+            },
+            "iterm_multipart" => match value {
+                "auto" => self.iterm_multipart = None,
+                value => self.iterm_multipart = Some(parse_bool(value)?),
+            },
+            "kitty_transfer" => match value {
+                "auto" => self.kitty_files = None,
+                "file" => self.kitty_files = Some(true),
+                "tty" => self.kitty_files = Some(false),
+                _ => bail!("kitty_transfer must be auto, file or tty"),
+            },
+            "kitty_placeholders" => match value {
+                "auto" => self.kitty_placeholders = None,
+                value => self.kitty_placeholders = Some(parse_bool(value)?),
+            },
+            "text_colors" => match value {
+                "auto" => self.text_truecolor = None,
+                "truecolor" => self.text_truecolor = Some(true),
+                "256" => self.text_truecolor = Some(false),
+                _ => bail!("text_colors must be auto, truecolor or 256"),
+            },
+            "text_dither" => match Dither::parse(value) {
+                Some(d) => self.text_dither = d,
+                None => bail!("text_dither must be auto, none, ordered or floyd-steinberg"),
+            },
+            "max_fps" => match value.parse() {
+                Ok(n) => self.max_fps = n,
+                Err(_) => bail!("max_fps must be a number"),
+            },
+            "poll_interval" => match value.parse::<f64>() {
+                Ok(n) if n >= 0.0 && n.is_finite() => self.poll_interval = n,
+                _ => bail!("poll_interval must be a number of seconds"),
+            },
+            "backups" => match value.parse() {
+                Ok(n) => self.backups = n,
+                Err(_) => bail!("backups must be a number"),
+            },
+            "dwell_log" => self.dwell_log = Some(value.to_string()).filter(|v| !v.is_empty()),
+            "screenshot_dir" => self.screenshot_dir = value.to_string(),
+            "screen_reader" => self.screen_reader = parse_bool(value)?,
+            "matte" => match parse_color(value) {
+                Some(c) => self.matte = c,
+                None => bail!("matte must be white, black or a colour like #1e1e2e"),
+            },
+            "dark_mode" => self.dark_mode = parse_bool(value)?,
+            "grayscale" => match Tone::parse(value) {
+                Some(t) => self.grayscale = t,
+                None => bail!("grayscale must be true, false or 1bit"),
+            },
+            "progressive_render" => self.progressive_render = parse_bool(value)?,
+            "stamp_exports" => self.stamp_exports = parse_bool(value)?,
+            key if filter::is_setting(key) => self.filter.set(key, value)?,
+            "watermark" => match value {
+                "" => self.watermark = None,
+                value => self.watermark = Some(value.to_string()),
+            },
+            "screenshot_scale" => match value.parse::<f32>() {
+                Ok(v) if v > 0.0 && v <= 8.0 => self.screenshot_scale = v,
+                _ => bail!("screenshot_scale must be a number between 0 and 8"),
+            },
+            "image_clipboard_command" => self.image_clipboard_command = Some(value.to_string()),
+            "clipboard_command" => self.clipboard_command = Some(value.to_string()),
+            "latex_ocr_command" => self.latex_ocr_command = value.to_string(),
+            "summarize_command" => self.summarize_command = value.to_string(),
+            "announce_command" => self.announce_command = value.to_string(),
+            "dictionary_command" => self.dictionary_command = value.to_string(),
+            "outline_command" => self.outline_command = value.to_string(),
+            _ => bail!("unknown setting '{}'", key),
+        }
+        Ok(())
+    }
+}
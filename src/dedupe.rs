@@ -0,0 +1,160 @@
+//! `termpdf dedupe DIR...`: finding the same paper downloaded twice. Files
+//! with the same bytes are the same, and ones whose first pages look alike
+//! (going by a perceptual hash of a small render) are likely to be, say an
+//! arXiv version and the published one.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::fs;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+
+use anyhow::Result;
+use image::imageops::FilterType;
+use pdfium_render::prelude::*;
+
+use crate::{bind_pdfium, stats};
+
+struct Fingerprint {
+    file: String,
+    bytes: u64,
+    /// Of the whole file
+    content: u64,
+    pages: usize,
+    /// A difference hash of the first page: whether each pixel of a tiny
+    /// grey render is darker than the one to its right
+    look: u64,
+}
+
+/// First pages whose hashes differ in at most this many of their 64 bits
+/// look alike: enough to allow for a different stamp in the margin, too few
+/// for two papers from the same template to match
+const ALIKE: u32 = 6;
+
+fn fingerprint(pdfium: &Pdfium, file: &str) -> Result<Fingerprint> {
+    let data = fs::read(file)?;
+    let mut hasher = DefaultHasher::new();
+    data.hash(&mut hasher);
+    let content = hasher.finish();
+
+    let document = pdfium.load_pdf_from_byte_slice(&data, None)?;
+    let page = document.pages().get(0)?;
+    let config = PdfRenderConfig::new()
+        .set_target_width(64)
+        .use_grayscale_rendering(true);
+    let small = page.render_with_config(&config)?.as_image();
+    let grey = small.resize_exact(9, 8, FilterType::Triangle).to_luma8();
+    let mut look = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            look <<= 1;
+            if grey.get_pixel(x, y).0[0] < grey.get_pixel(x + 1, y).0[0] {
+                look |= 1;
+            }
+        }
+    }
+    Ok(Fingerprint {
+        file: file.to_string(),
+        bytes: data.len() as u64,
+        content,
+        pages: document.pages().len() as usize,
+        look,
+    })
+}
+
+/// The PDFs in and under each of `paths`, or the paths themselves if they
+/// aren't directories
+fn documents(paths: &[String]) -> Vec<String> {
+    let mut files = vec![];
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let pattern = format!("{}/**/*.pdf", glob::Pattern::escape(path));
+        if let Ok(found) = glob::glob(&pattern) {
+            files.extend(found.flatten().map(|p| p.to_string_lossy().to_string()));
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// The file `i` is grouped under, shortening the way there as it goes
+fn root(group: &mut [usize], mut i: usize) -> usize {
+    while group[i] != i {
+        group[i] = group[group[i]];
+        i = group[i];
+    }
+    i
+}
+
+/// Groups of more than one of `prints` that are `related`, each in order,
+/// the groups in the order of their first files
+fn groups<F: Fn(&Fingerprint, &Fingerprint) -> bool>(
+    prints: &[Fingerprint],
+    related: F,
+) -> Vec<Vec<usize>> {
+    // Each file's group is the lowest file it's related to, through others
+    // if need be
+    let mut group: Vec<usize> = (0..prints.len()).collect();
+    for i in 0..prints.len() {
+        for j in i + 1..prints.len() {
+            if related(&prints[i], &prints[j]) {
+                let (a, b) = (root(&mut group, i), root(&mut group, j));
+                group[a.max(b)] = a.min(b);
+            }
+        }
+    }
+    let mut members: HashMap<usize, Vec<usize>> = HashMap::new();
+    for i in 0..prints.len() {
+        let r = root(&mut group, i);
+        members.entry(r).or_default().push(i);
+    }
+    let mut groups: Vec<Vec<usize>> = members.into_values().filter(|g| g.len() > 1).collect();
+    groups.sort();
+    groups
+}
+
+fn describe(print: &Fingerprint) -> String {
+    let pages = stats::count(print.pages, "page");
+    format!("  {} ({}, {})", print.file, pages, stats::size(print.bytes))
+}
+
+/// Report the duplicates among the PDFs in `paths`
+pub fn run(paths: &[String]) -> Result<()> {
+    let pdfium = bind_pdfium()?;
+    let mut prints = vec![];
+    for file in documents(paths) {
+        match fingerprint(&pdfium, &file) {
+            Ok(print) => prints.push(print),
+            Err(e) => eprintln!("{}: {}", file, e),
+        }
+    }
+
+    let identical = groups(&prints, |a, b| a.bytes == b.bytes && a.content == b.content);
+    let alike = groups(&prints, |a, b| {
+        a.content != b.content && (a.look ^ b.look).count_ones() <= ALIKE
+    });
+    if identical.is_empty() && alike.is_empty() {
+        println!(
+            "No duplicates among {}",
+            stats::count(prints.len(), "document")
+        );
+        return Ok(());
+    }
+    for group in &identical {
+        println!("Identical:");
+        for &i in group {
+            println!("{}", describe(&prints[i]));
+        }
+    }
+    for group in &alike {
+        println!("Likely the same (first pages look alike):");
+        for &i in group {
+            println!("{}", describe(&prints[i]));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,110 @@
+//! Making up for a palette without the colours a page needs, for the sixel
+//! and 256 colour text backends.
+
+/// How colours between palette entries are made up for
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dither {
+    /// Each pixel is the nearest colour in the palette: quickest, and
+    /// sharpest for text, but photos come out banded
+    None,
+    /// A regular pattern of nearby colours, which is quick and doesn't
+    /// crawl when the page is redrawn
+    Ordered,
+    /// Floyd–Steinberg: each pixel's error is passed on to the ones after
+    /// it, for the smoothest gradients
+    FloydSteinberg,
+    /// Floyd–Steinberg for what's in colour, and the nearest grey for
+    /// what's grey, so pictures are smooth but text isn't speckled
+    Auto,
+}
+
+impl Dither {
+    pub fn parse(s: &str) -> Option<Dither> {
+        match s {
+            "none" => Some(Dither::None),
+            "ordered" => Some(Dither::Ordered),
+            "floyd-steinberg" | "fs" => Some(Dither::FloydSteinberg),
+            "auto" => Some(Dither::Auto),
+            _ => None,
+        }
+    }
+}
+
+/// A palette to dither to
+pub trait Colours {
+    /// The entry closest to a colour
+    fn index(&self, rgb: [i32; 3]) -> usize;
+    /// An entry's colour, from 0 to 255
+    fn rgb(&self, index: usize) -> [i32; 3];
+    /// About how far apart the entries are, in each channel
+    fn step(&self) -> i32;
+}
+
+/// Near enough a grey that the palette's greys do for it, which are finer
+/// than its colours
+pub fn greyish([r, g, b]: [i32; 3]) -> bool {
+    r.max(g).max(b) - r.min(g).min(b) < 16
+}
+
+/// A 4×4 Bayer matrix, the thresholds for ordered dithering
+const BAYER: [[i32; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+/// The palette entry for each of `pixels`, `width` to a row
+pub fn indices<C: Colours>(
+    pixels: &[[i32; 3]],
+    width: usize,
+    palette: &C,
+    dither: Dither,
+) -> Vec<usize> {
+    let width = width.max(1);
+    match dither {
+        Dither::None => pixels.iter().map(|&p| palette.index(p)).collect(),
+        Dither::Ordered => {
+            // Nudged by up to half a step either way
+            let step = palette.step();
+            pixels
+                .iter()
+                .enumerate()
+                .map(|(i, &p)| {
+                    let (x, y) = (i % width, i / width);
+                    let nudge = (BAYER[y % 4][x % 4] * 2 - 15) * step / 32;
+                    palette.index(p.map(|c| c + nudge))
+                })
+                .collect()
+        }
+        Dither::FloydSteinberg | Dither::Auto => {
+            let height = pixels.len() / width;
+            let mut colours = pixels.to_vec();
+            let mut out = Vec::with_capacity(colours.len());
+            for y in 0..height {
+                for x in 0..width {
+                    let wanted = colours[y * width + x];
+                    // Greys take no error and pass none on, so text on a
+                    // white page stays as it would be undithered
+                    if dither == Dither::Auto && greyish(pixels[y * width + x]) {
+                        out.push(palette.index(pixels[y * width + x]));
+                        continue;
+                    }
+                    let i = palette.index(wanted);
+                    out.push(i);
+                    let got = palette.rgb(i);
+                    let error: Vec<i32> = (0..3).map(|c| wanted[c] - got[c]).collect();
+                    let mut spread = |x: usize, y: usize, sixteenths: i32| {
+                        if x < width && y < height {
+                            for (c, e) in colours[y * width + x].iter_mut().zip(&error) {
+                                *c += e * sixteenths / 16;
+                            }
+                        }
+                    };
+                    spread(x + 1, y, 7);
+                    if x > 0 {
+                        spread(x - 1, y + 1, 3);
+                    }
+                    spread(x, y + 1, 5);
+                    spread(x + 1, y + 1, 1);
+                }
+            }
+            out
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Recording how long is spent on each page, for authors and teachers who
+//! want to see where readers of a draft or course notes get stuck.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+
+use crate::atomic;
+
+#[derive(Debug, Default)]
+pub struct Dwell {
+    /// Time spent per file and (zero based) page
+    times: BTreeMap<(String, usize), Duration>,
+    visits: BTreeMap<(String, usize), usize>,
+    current: Option<(String, usize, Instant)>,
+}
+
+impl Dwell {
+    /// Note that `page` of `file` is on screen, charging the time since the
+    /// last call to whatever was on screen before
+    pub fn observe(&mut self, file: &str, page: usize) {
+        if let Some((f, p, _)) = &self.current {
+            if f == file && *p == page {
+                return;
+            }
+        }
+        self.stop();
+        *self.visits.entry((file.to_string(), page)).or_default() += 1;
+        self.current = Some((file.to_string(), page, Instant::now()));
+    }
+
+    /// Stop the clock, for when nothing is being read anymore
+    pub fn stop(&mut self) {
+        if let Some((file, page, since)) = self.current.take() {
+            *self.times.entry((file, page)).or_default() += since.elapsed();
+        }
+    }
+
+    /// Write the times out as JSON if `path` ends in `.json`, and CSV
+    /// otherwise. Pages are numbered from one.
+    pub fn export(&mut self, path: &str, backups: usize) -> Result<()> {
+        // Include the page being read right now
+        let current = self.current.take();
+        if let Some((file, page, since)) = &current {
+            *self.times.entry((file.clone(), *page)).or_default() += since.elapsed();
+        }
+
+        let json = Path::new(path)
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+        let contents = if json {
+            let rows: Vec<serde_json::Value> = self
+                .times
+                .iter()
+                .map(|((file, page), time)| {
+                    serde_json::json!({
+                        "file": file,
+                        "page": page + 1,
+                        "seconds": time.as_secs_f64(),
+                        "visits": self.visits.get(&(file.clone(), *page)).copied().unwrap_or(0),
+                    })
+                })
+                .collect();
+            serde_json::to_string_pretty(&rows)?
+        } else {
+            let mut csv = String::from("file,page,seconds,visits\n");
+            for ((file, page), time) in &self.times {
+                csv.push_str(&format!(
+                    "{},{},{:.3},{}\n",
+                    csv_field(file),
+                    page + 1,
+                    time.as_secs_f64(),
+                    self.visits
+                        .get(&(file.clone(), *page))
+                        .copied()
+                        .unwrap_or(0)
+                ));
+            }
+            csv
+        };
+
+        // Carry on timing the current page, without counting it twice
+        if let Some((file, page, _)) = current {
+            self.current = Some((file, page, Instant::now()));
+        }
+
+        atomic::write(path, contents, backups)
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains(',') || value.contains('"') || value.contains('\n') {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
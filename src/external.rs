@@ -0,0 +1,227 @@
+//! Running the external programs that users configure as command templates,
+//! like `open -R {file}` or `lf {file}`.
+
+use std::env;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::Sender;
+use std::thread;
+
+use anyhow::{bail, Result};
+
+use crate::Msg;
+
+/// Split a command line into words, honouring single and double quotes and
+/// backslash escapes like a (very small) shell would.
+pub fn split(line: &str) -> Result<Vec<String>> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut in_word = false;
+    let mut quote: Option<char> = None;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some('"') if c == '\\' => match chars.next() {
+                Some(c) => word.push(c),
+                None => bail!("trailing backslash in '{}'", line),
+            },
+            Some(_) => word.push(c),
+            None => match c {
+                '\'' | '"' => {
+                    quote = Some(c);
+                    in_word = true;
+                }
+                '\\' => match chars.next() {
+                    Some(c) => {
+                        word.push(c);
+                        in_word = true;
+                    }
+                    None => bail!("trailing backslash in '{}'", line),
+                },
+                c if c.is_whitespace() => {
+                    if in_word {
+                        words.push(std::mem::take(&mut word));
+                        in_word = false;
+                    }
+                }
+                c => {
+                    word.push(c);
+                    in_word = true;
+                }
+            },
+        }
+    }
+    if quote.is_some() {
+        bail!("unterminated quote in '{}'", line);
+    }
+    if in_word {
+        words.push(word);
+    }
+    Ok(words)
+}
+
+/// Build a command from a template, replacing `{name}` placeholders with the
+/// given values. If the template doesn't use any of them, the first value is
+/// appended as the last argument, so `lf` works as well as `lf {file}`.
+pub fn command(template: &str, values: &[(&str, &str)]) -> Result<Command> {
+    let words = split(template)?;
+    if words.is_empty() {
+        bail!("empty command");
+    }
+
+    let mut used = false;
+    let args: Vec<String> = words
+        .into_iter()
+        .map(|word| {
+            let mut word = word;
+            for (name, value) in values {
+                let placeholder = format!("{{{}}}", name);
+                if word.contains(&placeholder) {
+                    word = word.replace(&placeholder, value);
+                    used = true;
+                }
+            }
+            word
+        })
+        .collect();
+
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+    if !used {
+        if let Some((_, value)) = values.first() {
+            command.arg(value);
+        }
+    }
+    Ok(command)
+}
+
+/// Whether the program `template` runs can be found on `$PATH`
+pub fn installed(template: &str) -> bool {
+    let program = match split(template).ok().and_then(|w| w.into_iter().next()) {
+        Some(program) => program,
+        None => return false,
+    };
+    if program.contains('/') {
+        return Path::new(&program).is_file();
+    }
+    env::var_os("PATH")
+        .map(|path| env::split_paths(&path).any(|dir| dir.join(&program).is_file()))
+        .unwrap_or(false)
+}
+
+/// Start `command` with `input` on its standard input, sending what it
+/// prints back as `Msg::Output` as it arrives and `Msg::OutputEnd` when it
+/// is done. The caller should kill the child if it stops listening.
+pub fn stream(mut command: Command, input: String, tx: Sender<Msg>) -> Result<Child> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+
+    // Written from its own thread so a command that prints before it has
+    // read everything can't deadlock with us
+    let mut stdin = child.stdin.take().unwrap();
+    thread::spawn(move || {
+        let _ = stdin.write_all(input.as_bytes());
+    });
+
+    // Likewise read, so a command with a lot to complain about can't fill
+    // the pipe and stop before it gets to the end of what it prints
+    let mut stderr = child.stderr.take().unwrap();
+    let errors = thread::spawn(move || {
+        let mut errors = String::new();
+        let _ = stderr.read_to_string(&mut errors);
+        errors
+    });
+
+    let mut stdout = child.stdout.take().unwrap();
+    thread::spawn(move || {
+        let mut buf = [0; 4096];
+        let mut pending = vec![];
+        while let Ok(n) = stdout.read(&mut buf) {
+            if n == 0 {
+                break;
+            }
+            pending.extend_from_slice(&buf[..n]);
+            // Don't split a UTF-8 character across messages
+            let valid = match std::str::from_utf8(&pending) {
+                Ok(_) => pending.len(),
+                Err(e) if e.error_len().is_none() => e.valid_up_to(),
+                Err(_) => pending.len(),
+            };
+            let text = String::from_utf8_lossy(&pending[..valid]).to_string();
+            pending.drain(..valid);
+            if tx.send(Msg::Output(text)).is_err() {
+                return;
+            }
+        }
+        let errors = errors.join().unwrap_or_default();
+        if !errors.trim().is_empty() {
+            let _ = tx.send(Msg::Output(errors));
+        }
+        let _ = tx.send(Msg::OutputEnd);
+    });
+
+    Ok(child)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(command: &Command) -> Vec<String> {
+        let program = command.get_program().to_string_lossy().to_string();
+        let args = command.get_args().map(|a| a.to_string_lossy().to_string());
+        std::iter::once(program).chain(args).collect()
+    }
+
+    #[test]
+    fn split_honours_quotes_and_escapes() {
+        let words = split(r#"open -a "Preview Beta" it\'s 'a "b"'"#).unwrap();
+        assert_eq!(words, ["open", "-a", "Preview Beta", "it's", r#"a "b""#]);
+    }
+
+    #[test]
+    fn split_keeps_empty_quoted_words() {
+        assert_eq!(split(r#"echo "" x"#).unwrap(), ["echo", "", "x"]);
+    }
+
+    #[test]
+    fn split_refuses_unterminated_quotes() {
+        assert!(split("echo 'oops").is_err());
+        assert!(split("echo oops\\").is_err());
+    }
+
+    #[test]
+    fn command_fills_in_placeholders() {
+        let command = command(
+            "pdftk {file} output {output}",
+            &[("file", "a b.pdf"), ("output", "c.pdf")],
+        );
+        assert_eq!(
+            args(&command.unwrap()),
+            ["pdftk", "a b.pdf", "output", "c.pdf"]
+        );
+    }
+
+    #[test]
+    fn command_appends_the_first_value_without_placeholders() {
+        let command = command("lf", &[("file", "a.pdf"), ("dir", "/docs")]);
+        assert_eq!(args(&command.unwrap()), ["lf", "a.pdf"]);
+    }
+
+    #[test]
+    fn command_appends_nothing_when_another_placeholder_is_used() {
+        let command = command("xdg-open {dir}", &[("file", "a.pdf"), ("dir", "/docs")]);
+        assert_eq!(args(&command.unwrap()), ["xdg-open", "/docs"]);
+    }
+
+    #[test]
+    fn command_refuses_an_empty_template() {
+        assert!(command("  ", &[("file", "a.pdf")]).is_err());
+    }
+}
@@ -0,0 +1,342 @@
+//! Colour filters applied to pages before they're shown, for readers who
+//! find them hard to see as they are: high contrast, charts recoloured for
+//! red-green colour blindness, and brightness, contrast and gamma; and
+//! recolouring, which draws the page in two colours of the reader's choosing
+//! as zathura does; and a warm night light, on at the hours it's set for.
+//! Saved selections are left as the document has them.
+
+use std::sync::Mutex;
+
+use anyhow::{bail, Result};
+use image::{DynamicImage, Rgba};
+
+use crate::config::{parse_bool, parse_color};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Profile {
+    None,
+    /// Grey, with everything pushed towards black or white
+    HighContrast,
+    /// Reds and greens told apart by blue, for those missing red cones
+    Protanopia,
+    /// The same for those missing green cones, the commonest kind
+    Deuteranopia,
+}
+
+impl Profile {
+    fn parse(s: &str) -> Option<Profile> {
+        match s {
+            "none" => Some(Profile::None),
+            "high-contrast" => Some(Profile::HighContrast),
+            "protanopia" => Some(Profile::Protanopia),
+            "deuteranopia" => Some(Profile::Deuteranopia),
+            _ => None,
+        }
+    }
+}
+
+/// When pages are tinted warm
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NightLight {
+    Off,
+    On,
+    /// From one minute of the day until another, in local time, going
+    /// past midnight if the second is earlier
+    Between(u32, u32),
+}
+
+impl NightLight {
+    fn parse(s: &str) -> Option<NightLight> {
+        if let Ok(on) = parse_bool(s) {
+            return Some(if on { NightLight::On } else { NightLight::Off });
+        }
+        let minute = |t: &str| {
+            let (h, m) = t.trim().split_once(':')?;
+            let (h, m): (u32, u32) = (h.parse().ok()?, m.parse().ok()?);
+            (h < 24 && m < 60).then_some(h * 60 + m)
+        };
+        let (from, to) = s.split_once('-')?;
+        Some(NightLight::Between(minute(from)?, minute(to)?))
+    }
+
+    /// Whether it's on now
+    fn lit(&self) -> bool {
+        match *self {
+            NightLight::Off => false,
+            NightLight::On => true,
+            NightLight::Between(from, to) => {
+                let now = minute_of_day();
+                match from <= to {
+                    true => (from..to).contains(&now),
+                    false => now >= from || now < to,
+                }
+            }
+        }
+    }
+}
+
+/// Minutes since midnight, local time
+fn minute_of_day() -> u32 {
+    // SAFETY: localtime_r only writes to the tm it's given
+    let tm = unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        libc::localtime_r(&now, &mut tm);
+        tm
+    };
+    (tm.tm_hour * 60 + tm.tm_min) as u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Filter {
+    pub profile: Profile,
+    /// Added to every channel, from -1 to 1
+    pub brightness: f32,
+    /// How far channels are pushed away from the middle, 1 leaving them be
+    pub contrast: f32,
+    /// Above 1 lightens the mid-tones, below 1 darkens them
+    pub gamma: f32,
+    /// Draw the page in `darkcolor` and `lightcolor` rather than its own
+    /// colours
+    pub recolor: bool,
+    /// What black (the text, usually) becomes
+    pub darkcolor: [u8; 3],
+    /// What white (the paper) becomes
+    pub lightcolor: [u8; 3],
+    pub night_light: NightLight,
+    /// How warm the night light is, from 0 (not at all) to 1 (candlelight)
+    pub warmth: f32,
+}
+
+impl Default for Filter {
+    fn default() -> Filter {
+        Filter {
+            profile: Profile::None,
+            brightness: 0.0,
+            contrast: 1.0,
+            gamma: 1.0,
+            recolor: false,
+            // zathura's: light text on black
+            darkcolor: [255, 255, 255],
+            lightcolor: [0, 0, 0],
+            night_light: NightLight::Off,
+            warmth: 0.5,
+        }
+    }
+}
+
+static FILTER: Mutex<Option<Filter>> = Mutex::new(None);
+
+/// Filter pages shown from now on with `filter`
+pub fn configure(filter: Filter) {
+    *FILTER.lock().unwrap_or_else(|e| e.into_inner()) = Some(filter);
+}
+
+/// Change one setting of the filter pages are shown with, as `:set` does
+pub fn set(key: &str, value: &str) -> Result<()> {
+    let mut current = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    let mut filter = current.unwrap_or_default();
+    filter.set(key, value)?;
+    *current = Some(filter);
+    Ok(())
+}
+
+/// `image` through the filter given to `configure`, with the night light
+/// if it's time for it
+pub fn filtered(image: DynamicImage) -> DynamicImage {
+    let filter = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    let mut filter = match *filter {
+        Some(filter) => filter,
+        None => return image,
+    };
+    filter.night_light = match filter.night_light.lit() {
+        true => NightLight::On,
+        false => NightLight::Off,
+    };
+    match filter != Filter::default() {
+        true => filter.apply(&image),
+        false => image,
+    }
+}
+
+/// Turn the night light off if it's on now and on if it isn't, whatever
+/// the hours it was set for. Returns whether it's on.
+pub fn toggle_night_light() -> bool {
+    let mut current = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    let mut filter = current.unwrap_or_default();
+    let lit = !filter.night_light.lit();
+    filter.night_light = if lit { NightLight::On } else { NightLight::Off };
+    *current = Some(filter);
+    lit
+}
+
+/// Whether pages are being recoloured, for `Ctrl-R` to turn it the other
+/// way
+pub fn recoloring() -> bool {
+    let filter = FILTER.lock().unwrap_or_else(|e| e.into_inner());
+    filter.is_some_and(|f| f.recolor)
+}
+
+/// Whether `key` is one of the filter's settings
+pub fn is_setting(key: &str) -> bool {
+    matches!(
+        key,
+        "color_filter"
+            | "brightness"
+            | "contrast"
+            | "gamma"
+            | "recolor"
+            | "recolor_darkcolor"
+            | "recolor_lightcolor"
+            | "night_light"
+            | "night_light_warmth"
+    )
+}
+
+type Matrix = [[f32; 3]; 3];
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, cell) in row.iter_mut().enumerate() {
+            *cell = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+// Colour blindness as simulated by Viénot, Brettel and Mollon, in the LMS
+// space of the cones
+const RGB_TO_LMS: Matrix = [
+    [17.8824, 43.5161, 4.11935],
+    [3.45565, 27.1554, 3.86714],
+    [0.0299566, 0.184309, 1.46709],
+];
+const LMS_TO_RGB: Matrix = [
+    [0.080_944_45, -0.130_504_41, 0.116_721_07],
+    [-0.010_248_533, 0.054_019_33, -0.113_614_71],
+    [-0.000_365_297, -0.004_121_615, 0.693_511_4],
+];
+const PROTANOPIA: Matrix = [[0.0, 2.02344, -2.52581], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+const DEUTERANOPIA: Matrix = [[1.0, 0.0, 0.0], [0.494207, 0.0, 1.24827], [0.0, 0.0, 1.0]];
+/// Where the difference that can't be seen is moved to: into green and
+/// blue, which can
+const SHIFT: Matrix = [[0.0, 0.0, 0.0], [0.7, 1.0, 0.0], [0.7, 0.0, 1.0]];
+
+/// The matrix that daltonises colours for a kind of colour blindness: each
+/// colour plus the part of it that can't be seen, moved to where it can
+fn daltonise(simulate: &Matrix) -> Matrix {
+    let seen = multiply(&LMS_TO_RGB, &multiply(simulate, &RGB_TO_LMS));
+    let mut lost = seen.map(|row| row.map(|v| -v));
+    for (i, row) in lost.iter_mut().enumerate() {
+        row[i] += 1.0;
+    }
+    let mut m = multiply(&SHIFT, &lost);
+    for (i, row) in m.iter_mut().enumerate() {
+        row[i] += 1.0;
+    }
+    m
+}
+
+impl Filter {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<()> {
+        let number = |min: f32, max: f32| match value.parse::<f32>() {
+            Ok(n) if (min..=max).contains(&n) => Ok(n),
+            _ => bail!("{} must be a number from {} to {}", key, min, max),
+        };
+        match key {
+            "color_filter" => match Profile::parse(value) {
+                Some(p) => self.profile = p,
+                None => {
+                    bail!("color_filter must be none, high-contrast, protanopia or deuteranopia")
+                }
+            },
+            "brightness" => self.brightness = number(-1.0, 1.0)?,
+            "contrast" => self.contrast = number(0.0, 10.0)?,
+            "gamma" => self.gamma = number(0.1, 10.0)?,
+            "recolor" => self.recolor = parse_bool(value)?,
+            "night_light" => match NightLight::parse(value) {
+                Some(n) => self.night_light = n,
+                None => bail!("night_light must be true, false or hours like 21:00-07:00"),
+            },
+            "night_light_warmth" => self.warmth = number(0.0, 1.0)?,
+            "recolor_darkcolor" | "recolor_lightcolor" => {
+                let colour = match parse_color(value) {
+                    Some(c) => c,
+                    None => bail!("{} must be white, black or a colour like #1e1e2e", key),
+                };
+                match key {
+                    "recolor_darkcolor" => self.darkcolor = colour,
+                    _ => self.lightcolor = colour,
+                }
+            }
+            _ => bail!("Not a color setting: {}", key),
+        }
+        Ok(())
+    }
+
+    /// What each channel value becomes with the brightness, contrast and
+    /// gamma applied
+    fn table(&self) -> [u8; 256] {
+        let mut table = [0; 256];
+        for (i, out) in table.iter_mut().enumerate() {
+            let v = i as f32 / 255.0;
+            let v = ((v - 0.5) * self.contrast + 0.5 + self.brightness).clamp(0.0, 1.0);
+            *out = (v.powf(1.0 / self.gamma) * 255.0).round() as u8;
+        }
+        table
+    }
+
+    fn apply(&self, image: &DynamicImage) -> DynamicImage {
+        let table = self.table();
+        let matrix = match self.profile {
+            Profile::Protanopia => Some(daltonise(&PROTANOPIA)),
+            Profile::Deuteranopia => Some(daltonise(&DEUTERANOPIA)),
+            Profile::None | Profile::HighContrast => None,
+        };
+        let mut image = image.to_rgba8();
+        for pixel in image.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let mut rgb = [r as f32, g as f32, b as f32];
+            if let Some(m) = &matrix {
+                let [r, g, b] = rgb;
+                for (out, row) in rgb.iter_mut().zip(m) {
+                    *out = row[0] * r + row[1] * g + row[2] * b;
+                }
+            }
+            if self.profile == Profile::HighContrast {
+                let grey = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+                // A steep S-curve: paper goes white and faint text dark,
+                // while anti-aliased edges stay smooth
+                let t = ((grey / 255.0 - 0.5) * 3.0 + 0.5).clamp(0.0, 1.0);
+                let curved = t * t * (3.0 - 2.0 * t);
+                rgb = [curved * 255.0; 3];
+            }
+            let [r, g, b] = rgb.map(|v| table[v.round().clamp(0.0, 255.0) as usize]);
+            let [r, g, b] = match self.recolor {
+                // How light it is, from `darkcolor` to `lightcolor`
+                true => {
+                    let t = (0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32) / 255.0;
+                    let mix = |i: usize| {
+                        let (dark, light) = (self.darkcolor[i] as f32, self.lightcolor[i] as f32);
+                        (dark + (light - dark) * t).round() as u8
+                    };
+                    [mix(0), mix(1), mix(2)]
+                }
+                false => [r, g, b],
+            };
+            *pixel = match self.night_light {
+                // Less blue, and a little less green, as light from a lamp
+                // rather than the sky has
+                NightLight::On => Rgba([
+                    r,
+                    (g as f32 * (1.0 - 0.2 * self.warmth)) as u8,
+                    (b as f32 * (1.0 - 0.6 * self.warmth)) as u8,
+                    a,
+                ]),
+                _ => Rgba([r, g, b, a]),
+            };
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+}
@@ -0,0 +1,92 @@
+//! Turning highlights into flashcards: an Anki-importable TSV with the
+//! highlighted text on the front, the highlight's note on the back and where
+//! it came from as a third field.
+
+use std::path::Path;
+
+use anyhow::Result;
+use pdfium_render::prelude::*;
+
+use crate::query::Query;
+use crate::{atomic, render};
+
+/// Anki reads these header lines to pick the separator and to treat fields
+/// as HTML, which lets notes keep their line breaks as `<br>`
+const HEADER: &str = "#separator:tab\n#html:true\n#columns:Front\tBack\tSource\n";
+
+fn field(text: &str) -> String {
+    text.trim()
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace("\r\n", "\n")
+        .replace(['\r', '\n'], "<br>")
+        .replace('\t', " ")
+}
+
+fn is_highlight(annotation: &PdfPageAnnotation) -> bool {
+    matches!(
+        annotation.annotation_type(),
+        PdfPageAnnotationType::Highlight
+            | PdfPageAnnotationType::Underline
+            | PdfPageAnnotationType::Squiggly
+            | PdfPageAnnotationType::Strikeout
+    )
+}
+
+/// The text under each highlight on page `p` that's over some, and the
+/// highlight's note, for the renderer
+pub fn highlights_in(document: &PdfDocument, p: usize) -> Result<Vec<(String, String)>> {
+    let page = document.pages().get(p as u16)?;
+    let text = page.text()?;
+    let mut highlights = vec![];
+    for annotation in page.annotations().iter().filter(is_highlight) {
+        let front = text.for_annotation(&annotation).unwrap_or_default();
+        if front.trim().is_empty() {
+            continue;
+        }
+        highlights.push((front, annotation.contents().unwrap_or_default()));
+    }
+    Ok(highlights)
+}
+
+/// Write a flashcard for every highlight in `file` to `out`, returning how
+/// many there were. `progress` is told each page as it's reached, and how
+/// many there are.
+pub fn export(
+    file: &str,
+    out: &str,
+    backups: usize,
+    mut progress: impl FnMut(usize, usize),
+) -> Result<usize> {
+    let labels = render::labels(file)?;
+    let name = Path::new(file)
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| file.to_string());
+
+    let mut tsv = String::from(HEADER);
+    let mut count = 0;
+    let pages = labels.len();
+    for (index, label) in labels.iter().enumerate() {
+        progress(index + 1, pages);
+        let answer = render::ask(file, &Query::Highlights { page: index })?;
+        let highlights: Vec<(String, String)> = serde_json::from_value(answer)?;
+        for (front, back) in highlights {
+            let source = match label.is_empty() {
+                true => format!("{} p. {}", name, index + 1),
+                false => format!("{} p. {}", name, label),
+            };
+            tsv.push_str(&format!(
+                "{}\t{}\t{}\n",
+                field(&front),
+                field(&back),
+                field(&source)
+            ));
+            count += 1;
+        }
+    }
+
+    atomic::write(out, tsv, backups)?;
+    Ok(count)
+}
@@ -0,0 +1,289 @@
+//! `termpdf gallery DIR...`: the first page of every PDF in a folder, laid
+//! out in a grid to pick one from by how it looks rather than what it's
+//! called. Enter opens it, with the rest of the folder a page turn away.
+
+use std::io::{stdin, stdout, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use anyhow::Result;
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use termion::event::Key;
+use termion::input::TermRead;
+use termion::raw::IntoRawMode;
+
+use crate::layout::Window;
+use crate::{render, term, Page};
+
+/// About how wide a thumbnail is, in pixels, before the window's width is
+/// shared out between however many fit
+const THUMB_WIDTH: u32 = 220;
+/// Between thumbnails, and around the edge
+const GAP: u32 = 16;
+/// How much taller than wide a thumbnail's space is: an A4 page's shape,
+/// which most documents are near enough
+const THUMB_ASPECT: f64 = 1.414;
+
+const BACKGROUND: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const SELECTED: Rgba<u8> = Rgba([40, 90, 220, 255]);
+const MISSING: Rgba<u8> = Rgba([200, 200, 200, 255]);
+
+/// The PDFs directly in each of `paths`, or the paths themselves if they
+/// aren't directories
+fn documents(paths: &[String]) -> Vec<String> {
+    let mut files = vec![];
+    for path in paths {
+        if !Path::new(path).is_dir() {
+            files.push(path.clone());
+            continue;
+        }
+        let pattern = format!("{}/*.pdf", glob::Pattern::escape(path));
+        if let Ok(found) = glob::glob(&pattern) {
+            files.extend(found.flatten().map(|p| p.to_string_lossy().to_string()));
+        }
+    }
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// The window's usable area in pixels, guessing at the cell size as the
+/// sixel output does when the terminal doesn't say
+fn area() -> (u32, u32) {
+    let window = Window::query().unwrap_or(Window {
+        cols: 80,
+        rows: 24,
+        width_px: 0,
+        height_px: 0,
+        cell_aspect: None,
+        margin: 1,
+        center: true,
+        strip: 0,
+    });
+    let (cell_w, cell_h) = match (window.width_px, window.cell_size()) {
+        (0, (w, h)) => (10.0, 10.0 * h / w),
+        (_, size) => size,
+    };
+    let (cols, rows) = window.usable_cells();
+    (
+        ((cols as f64 * cell_w) as u32).max(THUMB_WIDTH),
+        ((rows as f64 * cell_h) as u32).max(THUMB_WIDTH),
+    )
+}
+
+struct Grid {
+    columns: usize,
+    rows: usize,
+    /// The space each thumbnail has, not counting the gaps
+    cell: (u32, u32),
+}
+
+impl Grid {
+    fn new((width, height): (u32, u32)) -> Grid {
+        let columns = (width / THUMB_WIDTH).max(1);
+        let cell_w = (width.saturating_sub(GAP * (columns + 1)) / columns).max(1);
+        let cell_h = (cell_w as f64 * THUMB_ASPECT) as u32;
+        let rows = (height.saturating_sub(GAP) / (cell_h + GAP)).max(1);
+        // Shrunk to fit a single row that's too tall for the window
+        let cell_h = cell_h.min(height.saturating_sub(GAP * 2).max(1));
+        Grid {
+            columns: columns as usize,
+            rows: rows as usize,
+            cell: (cell_w, cell_h),
+        }
+    }
+
+    fn per_screen(&self) -> usize {
+        self.columns * self.rows
+    }
+
+    /// Where the thumbnail in `column` and `row` goes
+    fn origin(&self, column: usize, row: usize) -> (u32, u32) {
+        (
+            GAP + column as u32 * (self.cell.0 + GAP),
+            GAP + row as u32 * (self.cell.1 + GAP),
+        )
+    }
+}
+
+/// The first page of `file`, shrunk to fit in `size`, and its title
+fn thumbnail(file: &str, size: (u32, u32)) -> Result<(DynamicImage, String)> {
+    let rendered = render::thumbnail(file, size)?;
+    match rendered.image {
+        Some(image) => Ok((image, rendered.title)),
+        None => anyhow::bail!("Couldn't render {}", file),
+    }
+}
+
+/// The screen of thumbnails `selected` is on, with it picked out
+fn draw(
+    thumbnails: &[Option<DynamicImage>],
+    grid: &Grid,
+    size: (u32, u32),
+    selected: usize,
+) -> DynamicImage {
+    let mut canvas = RgbaImage::from_pixel(size.0, size.1, BACKGROUND);
+    let first = selected / grid.per_screen() * grid.per_screen();
+    let shown = thumbnails
+        .iter()
+        .enumerate()
+        .skip(first)
+        .take(grid.per_screen());
+    for (i, thumbnail) in shown {
+        let (column, row) = ((i - first) % grid.columns, (i - first) / grid.columns);
+        let (x, y) = grid.origin(column, row);
+        let (x, y, w, h) = match thumbnail {
+            // Centred along the bottom of its space, so pages of different
+            // shapes sit on the same line
+            Some(image) => {
+                let (w, h) = (image.width(), image.height());
+                let (x, y) = (
+                    x + grid.cell.0.saturating_sub(w) / 2,
+                    y + grid.cell.1 - h.min(grid.cell.1),
+                );
+                imageops::overlay(&mut canvas, &image.to_rgba8(), x as i64, y as i64);
+                (x, y, w, h)
+            }
+            None => {
+                let (w, h) = grid.cell;
+                let blank = RgbaImage::from_pixel(w, h, MISSING);
+                imageops::overlay(&mut canvas, &blank, x as i64, y as i64);
+                (x, y, w, h)
+            }
+        };
+        if i == selected {
+            frame(&mut canvas, (x, y, w, h));
+        }
+    }
+    DynamicImage::ImageRgba8(canvas)
+}
+
+/// A border just outside `x, y, w, h`, in the gap around it
+fn frame(canvas: &mut RgbaImage, (x, y, w, h): (u32, u32, u32, u32)) {
+    let border = GAP / 3;
+    let (left, top) = (x.saturating_sub(border), y.saturating_sub(border));
+    let right = (x + w + border).min(canvas.width());
+    let bottom = (y + h + border).min(canvas.height());
+    for py in top..bottom {
+        for px in left..right {
+            if px < x || px >= x + w || py < y || py >= y + h {
+                canvas.put_pixel(px, py, SELECTED);
+            }
+        }
+    }
+}
+
+/// The next key pressed. Read straight from the terminal, rather than
+/// through a buffered reader, so nothing typed ahead is left behind where
+/// the viewer's own reader can't see it.
+fn key() -> Result<Option<Key>> {
+    let fd = stdin().as_raw_fd();
+    let mut buf = [0u8; 64];
+    loop {
+        // SAFETY: buf is valid for buf.len() bytes
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+        if n < 0 && std::io::Error::last_os_error().kind() == std::io::ErrorKind::Interrupted {
+            continue;
+        }
+        if n <= 0 {
+            return Ok(None);
+        }
+        // Only the first key of a burst counts, so a held key moves one
+        // thumbnail at a time
+        if let Some(key) = (&buf[..n as usize]).keys().flatten().next() {
+            return Ok(Some(key));
+        }
+    }
+}
+
+/// Show the gallery of the PDFs in `paths`, called by their titles if
+/// `by_title`, until one is opened or it's closed. Returns the files and
+/// which of them was opened, if one was.
+pub fn run(paths: &[String], by_title: bool) -> Result<Option<(Vec<String>, usize)>> {
+    let files = documents(paths);
+    if files.is_empty() {
+        anyhow::bail!("Couldn't find pdf files");
+    }
+    let mut stdout = stdout().into_raw_mode()?;
+    let mut thumbnails: Vec<Option<DynamicImage>> = vec![None; files.len()];
+    let mut titles = vec![String::new(); files.len()];
+    let mut rendered = vec![false; files.len()];
+    let mut selected = 0;
+    let mut size = (0, 0);
+    let mut grid = Grid::new((THUMB_WIDTH, THUMB_WIDTH));
+    loop {
+        // The window may have been resized since last time
+        if area() != size {
+            size = area();
+            grid = Grid::new(size);
+            rendered.iter_mut().for_each(|r| *r = false);
+            write!(
+                stdout,
+                "{}{}",
+                termion::cursor::Goto(1, 1),
+                termion::clear::All
+            )?;
+            stdout.flush()?;
+            term::set_scroll_region(termion::terminal_size()?.1)?;
+        }
+        let first = selected / grid.per_screen() * grid.per_screen();
+        let last = (first + grid.per_screen()).min(files.len());
+        for i in first..last {
+            if !rendered[i] {
+                term::status_line(&format!("Rendering {} of {}…", i + 1, files.len()))?;
+                match thumbnail(&files[i], grid.cell) {
+                    Ok((image, title)) => (thumbnails[i], titles[i]) = (Some(image), title),
+                    Err(_) => thumbnails[i] = None,
+                }
+                rendered[i] = true;
+            }
+        }
+        Page::from_image(draw(&thumbnails, &grid, size, selected))?.display()?;
+        let name = match by_title && !titles[selected].is_empty() {
+            true => titles[selected].clone(),
+            false => Path::new(&files[selected])
+                .file_name()
+                .map(|n| n.to_string_lossy().to_string())
+                .unwrap_or_default(),
+        };
+        let broken = match thumbnails[selected] {
+            Some(_) => "",
+            None => " (couldn't be read)",
+        };
+        term::status_line(&format!(
+            "{}{} ({}/{})  hjkl move  Enter open  q quit",
+            name,
+            broken,
+            selected + 1,
+            files.len()
+        ))?;
+
+        let columns = grid.columns;
+        selected = match key()? {
+            Some(Key::Char('h')) | Some(Key::Left) => selected.saturating_sub(1),
+            Some(Key::Char('l')) | Some(Key::Right) => selected + 1,
+            Some(Key::Char('k')) | Some(Key::Up) => selected.saturating_sub(columns),
+            Some(Key::Char('j')) | Some(Key::Down) => selected + columns,
+            Some(Key::PageUp) => selected.saturating_sub(grid.per_screen()),
+            Some(Key::PageDown) | Some(Key::Char(' ')) => selected + grid.per_screen(),
+            Some(Key::Char('g')) | Some(Key::Home) => 0,
+            Some(Key::Char('G')) | Some(Key::End) => files.len() - 1,
+            Some(Key::Char('\n')) | Some(Key::Char('\r')) => {
+                term::reset_scroll_region()?;
+                return Ok(Some((files, selected)));
+            }
+            Some(Key::Char('q')) | Some(Key::Esc) | Some(Key::Ctrl('c')) | None => {
+                write!(
+                    stdout,
+                    "{}{}",
+                    termion::clear::All,
+                    termion::cursor::Goto(1, 1)
+                )?;
+                term::reset_scroll_region()?;
+                return Ok(None);
+            }
+            Some(_) => selected,
+        }
+        .min(files.len() - 1);
+    }
+}
@@ -0,0 +1,965 @@
+//! The escape sequences that put a page on the screen. Terminals disagree
+//! about these: iTerm2's inline images (also understood by WezTerm and
+//! others), kitty's graphics protocol and sixels are supported, and coloured
+//! half blocks where none of them are.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::env;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::Result;
+use base64::engine::general_purpose;
+use base64::Engine as _;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::png::{CompressionType, FilterType, PngEncoder};
+use image::{imageops, DynamicImage, ImageEncoder};
+
+use crate::config::Config;
+use crate::dither::{self, Colours, Dither};
+use crate::layout::{self, Window};
+use crate::sixel;
+use crate::temp::TempDir;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Protocol {
+    /// `OSC 1337 ; File=...`
+    Iterm,
+    /// kitty's `APC G` commands
+    Kitty,
+    /// `DCS q`, drawn pixel for pixel
+    Sixel,
+    /// `▀` in the page's colours, two pixels to a cell
+    Text,
+}
+
+impl Protocol {
+    /// `auto` is None: work it out from the environment
+    pub fn parse(s: &str) -> Option<Option<Protocol>> {
+        match s {
+            "auto" => Some(None),
+            "iterm" => Some(Some(Protocol::Iterm)),
+            "kitty" => Some(Some(Protocol::Kitty)),
+            "sixel" => Some(Some(Protocol::Sixel)),
+            "text" => Some(Some(Protocol::Text)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Protocol::Iterm => "iterm",
+            Protocol::Kitty => "kitty",
+            Protocol::Sixel => "sixel",
+            Protocol::Text => "text",
+        }
+    }
+
+    /// kitty, and terminals that only speak its protocol, say so in the
+    /// environment, as do the ones known to do iTerm2's well
+    pub fn detect() -> Option<Protocol> {
+        let term = env::var("TERM").unwrap_or_default();
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || term == "xterm-ghostty"
+            || program == "ghostty"
+        {
+            Some(Protocol::Kitty)
+        } else if ["WezTerm", "iTerm.app", "vscode", "mintty"].contains(&program.as_str()) {
+            Some(Protocol::Iterm)
+        } else {
+            None
+        }
+    }
+}
+
+/// How page images are encoded for the protocols that send image files
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Format {
+    /// Compressed without loss, and understood everywhere
+    Png,
+    /// Much smaller for scans and photos, a little blurry round text
+    Jpeg,
+    /// Uncompressed, so quick to make but several times bigger
+    Tiff,
+    /// kitty's own: the pixels as they are, zlib-compressed, which it can
+    /// draw without decoding a file first. Sent with the image's width and
+    /// height in front, 4 bytes each, which kitty is told separately.
+    Raw,
+}
+
+impl Format {
+    /// `auto` is None: the protocol's choice
+    pub fn parse(s: &str) -> Option<Option<Format>> {
+        match s {
+            "auto" => Some(None),
+            "png" => Some(Some(Format::Png)),
+            "jpeg" | "jpg" => Some(Some(Format::Jpeg)),
+            "tiff" => Some(Some(Format::Tiff)),
+            "raw" => Some(Some(Format::Raw)),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Jpeg => "jpeg",
+            Format::Tiff => "tiff",
+            Format::Raw => "raw",
+        }
+    }
+}
+
+/// What colours pages are shown in
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Tone {
+    Color,
+    /// Shades of grey, rendered that way by pdfium and sent with one
+    /// channel instead of four
+    Gray,
+    /// Black and white, dithered, for e-ink and the slowest links
+    Mono,
+}
+
+impl Tone {
+    pub fn parse(s: &str) -> Option<Tone> {
+        match s {
+            "1bit" | "1-bit" => Some(Tone::Mono),
+            s => match crate::config::parse_bool(s) {
+                Ok(true) => Some(Tone::Gray),
+                Ok(false) => Some(Tone::Color),
+                Err(_) => None,
+            },
+        }
+    }
+
+    /// Colour, then grey, then black and white, and round again
+    pub fn next(self) -> Tone {
+        match self {
+            Tone::Color => Tone::Gray,
+            Tone::Gray => Tone::Mono,
+            Tone::Mono => Tone::Color,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Tone::Color => "color",
+            Tone::Gray => "grayscale",
+            Tone::Mono => "1-bit",
+        }
+    }
+}
+
+/// Where terminals that understand a protocol still draw it differently
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Quirks {
+    /// Send the page scaled to exactly the pixels it's shown at and let the
+    /// terminal draw it as it is. WezTerm rounds iTerm2 images given in cells
+    /// to its own idea of the cell size and resamples them blurrily, and
+    /// Konsole's kitty images ignore the cell size asked for.
+    pub prescale: bool,
+    /// Only the plain kitty delete works (`a=d`, not `d=A` or `d=I`), so
+    /// images can't be kept to show again: Konsole
+    pub plain_delete: bool,
+    /// Big iTerm2 images can be sent in parts (`MultipartFile`), which
+    /// iTerm2 itself has done since 3.5
+    pub multipart: bool,
+    /// kitty can read pages from files we leave it, because it's running
+    /// on this machine, rather than having them sent through the terminal
+    pub files: bool,
+    /// kitty images are drawn where placeholder characters are written,
+    /// rather than at the cursor, so a multiplexer that redraws the screen
+    /// from its own copy of the text doesn't lose them
+    pub placeholders: bool,
+    /// Escapes tmux wouldn't pass on are wrapped so that it does
+    pub tmux: bool,
+    /// TIFF images are read. The VSCode terminal's are only PNG, JPEG and
+    /// GIF, and it shows nothing for anything else.
+    pub tiff: bool,
+    /// Most bytes of image the terminal takes in one escape, if it has a
+    /// limit of its own: the VSCode terminal drops bigger ones
+    pub max_payload: usize,
+    /// Most pixels an image can have, if there's a limit: past 4096×4096
+    /// the VSCode terminal drops it
+    pub max_pixels: u32,
+}
+
+impl Quirks {
+    fn detect() -> Quirks {
+        let program = env::var("TERM_PROGRAM").unwrap_or_default();
+        let konsole = env::var_os("KONSOLE_VERSION").is_some();
+        let vscode = program == "vscode";
+        // Over ssh its files are on another machine, and tmux only passes
+        // kitty's escapes on when asked
+        let remote = ["SSH_CONNECTION", "SSH_TTY", "TMUX"]
+            .iter()
+            .any(|v| env::var_os(v).is_some());
+        Quirks {
+            prescale: program == "WezTerm" || konsole,
+            plain_delete: konsole,
+            multipart: program == "iTerm.app",
+            files: Protocol::detect() == Some(Protocol::Kitty) && !remote,
+            placeholders: env::var_os("TMUX").is_some(),
+            tmux: env::var_os("TMUX").is_some(),
+            tiff: !vscode,
+            max_payload: match vscode {
+                true => VSCODE_PAYLOAD,
+                false => 0,
+            },
+            max_pixels: match vscode {
+                true => VSCODE_PIXELS,
+                false => 0,
+            },
+        }
+    }
+}
+
+/// The VSCode terminal's limits (xterm.js's `iipSizeLimit` and
+/// `pixelLimit`), a little under in the case of the bytes, which it counts
+/// after decoding
+const VSCODE_PAYLOAD: usize = 18 * 1024 * 1024;
+const VSCODE_PIXELS: u32 = 4096 * 4096;
+
+static PROTOCOL: OnceLock<Protocol> = OnceLock::new();
+static FORMAT: OnceLock<Format> = OnceLock::new();
+static QUIRKS: OnceLock<Quirks> = OnceLock::new();
+static LIMIT: OnceLock<usize> = OnceLock::new();
+static TRUECOLOR: OnceLock<bool> = OnceLock::new();
+static TEXT_DITHER: OnceLock<Dither> = OnceLock::new();
+static LOW_BANDWIDTH: OnceLock<bool> = OnceLock::new();
+static JPEG_QUALITY: OnceLock<u8> = OnceLock::new();
+/// Changed with `m` while reading, so not set once
+static TONE: Mutex<Tone> = Mutex::new(Tone::Color);
+
+/// Terminals with 24-bit colour mostly say so in `$COLORTERM`
+fn truecolor() -> bool {
+    let colorterm = env::var("COLORTERM").unwrap_or_default();
+    let term = env::var("TERM").unwrap_or_default();
+    colorterm == "truecolor" || colorterm == "24bit" || term.ends_with("-direct")
+}
+
+/// Choose how pages are sent to the terminal, before any are. Without a
+/// protocol set, it's the one the environment suggests, then the one the
+/// terminal answered to (see `probe`), then iTerm2's. The quirks are the
+/// terminal's, less any the config overrides.
+pub fn configure(config: &Config, probed: Option<Protocol>) {
+    let protocol = config.graphics.or_else(Protocol::detect).or(probed);
+    let _ = PROTOCOL.set(protocol.unwrap_or(Protocol::Iterm));
+    let low_bandwidth = config
+        .low_bandwidth
+        .unwrap_or_else(|| env::var_os("SSH_TTY").is_some());
+    let _ = LOW_BANDWIDTH.set(low_bandwidth);
+    let _ = JPEG_QUALITY.set(config.jpeg_quality.unwrap_or(match low_bandwidth {
+        true => LOW_BANDWIDTH_QUALITY,
+        false => DEFAULT_QUALITY,
+    }));
+    let mut quirks = Quirks::detect();
+    quirks.prescale = config.prescale_images.unwrap_or(quirks.prescale);
+    quirks.multipart = config.iterm_multipart.unwrap_or(quirks.multipart);
+    quirks.files = config.kitty_files.unwrap_or(quirks.files);
+    quirks.placeholders = config.kitty_placeholders.unwrap_or(quirks.placeholders);
+    let _ = QUIRKS.set(quirks);
+    // kitty only takes PNG or raw pixels, which it's quickest to draw but
+    // which PNG's filtering makes smaller, for a slow link. Raw pixels are
+    // kitty's alone.
+    let format = match (self::protocol(), config.image_format) {
+        (Protocol::Kitty, Some(Format::Png)) => Format::Png,
+        (Protocol::Kitty, _) if low_bandwidth => Format::Png,
+        (Protocol::Kitty, _) => Format::Raw,
+        (_, Some(Format::Raw)) => Format::Png,
+        (_, Some(format)) => format,
+        (_, None) if low_bandwidth => Format::Jpeg,
+        (_, None) => Format::Png,
+    };
+    let format = match format {
+        Format::Tiff if !quirks.tiff => Format::Png,
+        format => format,
+    };
+    let _ = FORMAT.set(format);
+    // Whichever is smaller, of the one asked for and the terminal's own
+    let limit = match (config.max_image_size * 1024, quirks.max_payload) {
+        (0, limit) | (limit, 0) => limit,
+        (asked, limit) => asked.min(limit),
+    };
+    let _ = LIMIT.set(limit);
+    let _ = TRUECOLOR.set(config.text_truecolor.unwrap_or_else(truecolor));
+    let _ = TEXT_DITHER.set(config.text_dither);
+    sixel::configure(config.sixel_colors, config.sixel_dither);
+    set_tone(config.grayscale);
+}
+
+pub fn tone() -> Tone {
+    *TONE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Show pages in `tone` from now on
+pub fn set_tone(tone: Tone) {
+    *TONE.lock().unwrap_or_else(|e| e.into_inner()) = tone;
+}
+
+/// `image` in the tone pages are shown in: as it is, in grey, or dithered
+/// to black and white (Floyd–Steinberg, so grey areas and anti-aliased text
+/// keep their shape)
+pub fn toned(image: DynamicImage) -> DynamicImage {
+    let mut grey = match tone() {
+        Tone::Color => return image,
+        Tone::Gray => return DynamicImage::ImageLuma8(image.to_luma8()),
+        Tone::Mono => image.to_luma8(),
+    };
+    let (width, height) = (grey.width() as usize, grey.height() as usize);
+    let mut error = vec![0i16; width * height];
+    for (i, pixel) in grey.pixels_mut().enumerate() {
+        let value = pixel.0[0] as i16 + error[i];
+        let out = if value < 128 { 0 } else { 255 };
+        pixel.0[0] = out as u8;
+        let spill = value - out;
+        let (x, y) = (i % width, i / width);
+        let mut spread = |dx: isize, dy: usize, sixteenths: i16| {
+            let nx = x as isize + dx;
+            if nx >= 0 && (nx as usize) < width && y + dy < height {
+                error[(y + dy) * width + nx as usize] += spill * sixteenths / 16;
+            }
+        };
+        spread(1, 0, 7);
+        spread(-1, 1, 3);
+        spread(0, 1, 5);
+        spread(1, 1, 1);
+    }
+    DynamicImage::ImageLuma8(grey)
+}
+
+pub fn protocol() -> Protocol {
+    *PROTOCOL.get().unwrap_or(&Protocol::Iterm)
+}
+
+pub fn format() -> Format {
+    *FORMAT.get().unwrap_or(&Format::Png)
+}
+
+/// Whether pages are kept small for a slow connection: rendered at a
+/// lower resolution and, where the terminal takes it, sent as JPEG
+pub fn low_bandwidth() -> bool {
+    *LOW_BANDWIDTH.get().unwrap_or(&false)
+}
+
+/// Quality of JPEG pages, out of 100, unless `jpeg_quality` says otherwise
+const DEFAULT_QUALITY: u8 = 90;
+/// Low enough to make a page a fraction of the size, still readable
+const LOW_BANDWIDTH_QUALITY: u8 = 60;
+
+pub fn jpeg_quality() -> u8 {
+    *JPEG_QUALITY.get().unwrap_or(&DEFAULT_QUALITY)
+}
+
+fn jpeg(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(vec![]);
+    if let DynamicImage::ImageLuma8(grey) = image {
+        JpegEncoder::new_with_quality(&mut buffer, jpeg_quality()).write_image(
+            grey,
+            grey.width(),
+            grey.height(),
+            image::ColorType::L8,
+        )?;
+        return Ok(buffer.into_inner());
+    }
+    // JPEG has no transparency
+    let rgb = image.to_rgb8();
+    JpegEncoder::new_with_quality(&mut buffer, jpeg_quality()).write_image(
+        &rgb,
+        rgb.width(),
+        rgb.height(),
+        image::ColorType::Rgb8,
+    )?;
+    Ok(buffer.into_inner())
+}
+
+fn in_format(image: &DynamicImage, format: Format) -> Result<Vec<u8>> {
+    match format {
+        Format::Png => png(image),
+        Format::Jpeg => jpeg(image),
+        Format::Raw => raw(image),
+        Format::Tiff => {
+            let mut buffer = Cursor::new(vec![]);
+            image.write_to(&mut buffer, image::ImageFormat::Tiff)?;
+            Ok(buffer.into_inner())
+        }
+    }
+}
+
+/// The image's width, height and RGB pixels, the pixels compressed (quickly,
+/// as for PNG)
+fn raw(image: &DynamicImage) -> Result<Vec<u8>> {
+    let rgb = image.to_rgb8();
+    let mut data = vec![];
+    data.extend(rgb.width().to_be_bytes());
+    data.extend(rgb.height().to_be_bytes());
+    let mut encoder = ZlibEncoder::new(data, Compression::fast());
+    encoder.write_all(rgb.as_raw())?;
+    Ok(encoder.finish()?)
+}
+
+/// The width and height at the front of a `raw` image, and the compressed
+/// pixels after them
+fn unraw(data: &[u8]) -> Option<((u32, u32), &[u8])> {
+    let (width, rest) = data.split_first_chunk::<4>()?;
+    let (height, pixels) = rest.split_first_chunk::<4>()?;
+    Some((
+        (u32::from_be_bytes(*width), u32::from_be_bytes(*height)),
+        pixels,
+    ))
+}
+
+fn png(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut buffer = Cursor::new(vec![]);
+    let encoder = PngEncoder::new_with_quality(&mut buffer, CompressionType::Fast, FilterType::Sub);
+    if let DynamicImage::ImageLuma8(grey) = image {
+        encoder.write_image(grey, grey.width(), grey.height(), image::ColorType::L8)?;
+        return Ok(buffer.into_inner());
+    }
+    let rgba = image.to_rgba8();
+    encoder.write_image(
+        rgba.as_raw(),
+        rgba.width(),
+        rgba.height(),
+        image::ColorType::Rgba8,
+    )?;
+    Ok(buffer.into_inner())
+}
+
+/// The image in the format the terminal wants it in, made smaller until it
+/// fits the limit given to `configure` and any the terminal has. Sixels and
+/// text depend on the size of the window, so they're made by `sequence`
+/// instead.
+pub fn encode(image: &DynamicImage) -> Result<Vec<u8>> {
+    if let Protocol::Sixel | Protocol::Text = protocol() {
+        return Ok(vec![]);
+    }
+    let max_pixels = quirks().max_pixels as f64;
+    let pixels = image.width() as f64 * image.height() as f64;
+    let shrunk = match max_pixels > 0.0 && pixels > max_pixels {
+        true => {
+            let scale = (max_pixels / pixels).sqrt();
+            let width = ((image.width() as f64 * scale) as u32).max(1);
+            let height = ((image.height() as f64 * scale) as u32).max(1);
+            Some(image.resize_exact(width, height, imageops::FilterType::Triangle))
+        }
+        false => None,
+    };
+    let image = shrunk.as_ref().unwrap_or(image);
+    let data = in_format(image, format())?;
+    let limit = *LIMIT.get().unwrap_or(&0);
+    if limit == 0 || data.len() <= limit {
+        return Ok(data);
+    }
+    // Compressing may be enough: TIFF isn't compressed at all, and raw
+    // pixels aren't filtered to compress better
+    let format = match format() {
+        Format::Tiff | Format::Raw => Format::Png,
+        format => format,
+    };
+    let mut data = match format == self::format() {
+        true => data,
+        false => in_format(image, format)?,
+    };
+    let mut scaled = image.clone();
+    while data.len() > limit && scaled.width() > MIN_WIDTH {
+        // Size goes roughly with the area, so shrink both sides by the
+        // square root of how far over it is, and a bit more to avoid
+        // going round again
+        let scale = (limit as f64 / data.len() as f64).sqrt() * 0.9;
+        let width = ((scaled.width() as f64 * scale) as u32).max(MIN_WIDTH);
+        let height = ((scaled.height() as f64 * scale) as u32).max(1);
+        scaled = image.resize_exact(width, height, imageops::FilterType::Triangle);
+        data = in_format(&scaled, format)?;
+    }
+    Ok(data)
+}
+
+/// Smaller than this and the page can't be read anyway, so it's sent over
+/// the limit instead
+const MIN_WIDTH: u32 = 200;
+
+/// kitty wants the data in pieces no bigger than this
+const KITTY_CHUNK: usize = 4096;
+
+/// The size of the parts of iTerm2 images, when they're sent in parts
+const ITERM_CHUNK: usize = 256 * 1024;
+
+/// Bytes of pixels to leave with kitty at most. It keeps 320MB of images by
+/// default and quietly drops the oldest past that, which would leave us
+/// placing images it no longer has
+const KITTY_STORAGE: usize = 128 * 1024 * 1024;
+
+/// The images kitty has been sent and still has, so going back to a page
+/// places it again instead of sending it all over again. They're known by
+/// the data sent, which is the same for the same page at the same size.
+struct Sent {
+    /// Hash of the data, image ID and bytes of pixels, oldest first
+    images: VecDeque<(u64, u32, usize)>,
+    bytes: usize,
+    next_id: u32,
+}
+
+static SENT: Mutex<Sent> = Mutex::new(Sent {
+    images: VecDeque::new(),
+    bytes: 0,
+    next_id: 1,
+});
+
+impl Sent {
+    /// The ID of the image with this hash, if kitty has it
+    fn get(&mut self, hash: u64) -> Option<u32> {
+        let i = self.images.iter().position(|&(h, _, _)| h == hash)?;
+        // Now the most recently used
+        let image = self.images.remove(i)?;
+        self.images.push_back(image);
+        Some(image.1)
+    }
+
+    /// An ID for a new image, and the IDs of old ones to delete to make
+    /// room for it
+    fn add(&mut self, hash: u64, bytes: usize) -> (u32, Vec<u32>) {
+        let mut old = vec![];
+        while self.bytes + bytes > KITTY_STORAGE && !self.images.is_empty() {
+            if let Some((_, id, size)) = self.images.pop_front() {
+                self.bytes -= size;
+                old.push(id);
+            }
+        }
+        let id = self.next_id;
+        self.next_id = self.next_id.checked_add(1).unwrap_or(1);
+        self.images.push_back((hash, id, bytes));
+        self.bytes += bytes;
+        (id, old)
+    }
+}
+
+pub fn quirks() -> Quirks {
+    *QUIRKS.get().unwrap_or(&Quirks::default())
+}
+
+/// Any terminal doing sixels is likely to have cells about this wide
+const SIXEL_CELL: f64 = 10.0;
+/// Half blocks show a pixel across and two down in each cell, so twice that
+/// leaves some to average for smoother edges
+const TEXT_CELL: f64 = 2.0;
+/// Terminals that scale images themselves can have big cells on a high
+/// density screen, and it's better to scale down than up
+const IMAGE_CELL: f64 = 16.0;
+
+/// How many pixels wide to render for each cell when the terminal doesn't
+/// say how big they are, or always for half blocks, which are the same
+/// however big the cells are
+pub fn cell_width() -> f64 {
+    match protocol() {
+        Protocol::Sixel => SIXEL_CELL,
+        Protocol::Text => TEXT_CELL,
+        Protocol::Iterm | Protocol::Kitty => IMAGE_CELL,
+    }
+}
+
+/// `image` scaled to fit `cols` by `rows` cells of `window`
+fn fit(image: &DynamicImage, window: &Window, cols: u16, rows: u16) -> DynamicImage {
+    let (cell_w, cell_h) = match (window.width_px, window.cell_size()) {
+        (0, (w, h)) => (SIXEL_CELL, SIXEL_CELL * h / w),
+        (_, size) => size,
+    };
+    let (width, height) = (cols as f64 * cell_w, rows as f64 * cell_h);
+    image.resize(width as u32, height as u32, imageops::FilterType::Triangle)
+}
+
+/// The escape sequence that draws the page, `data` being `image` from
+/// `encode`, `cols` by `rows` cells from the top left of `window`
+pub fn sequence(
+    data: &[u8],
+    image: &DynamicImage,
+    window: &Window,
+    cols: u16,
+    rows: u16,
+) -> Result<String> {
+    let origin = layout::origin((cols, rows), window);
+    draw(data, image, window, (cols, rows), origin, false)
+}
+
+/// The escape sequence that draws `image` in `cols` by `rows` cells at
+/// `origin`, like `sequence`, but leaving alone whatever's already on
+/// screen: for the thumbnail strip beside the page
+pub fn beside(
+    data: &[u8],
+    image: &DynamicImage,
+    window: &Window,
+    (cols, rows): (u16, u16),
+    origin: (u16, u16),
+) -> Result<String> {
+    draw(data, image, window, (cols, rows), origin, true)
+}
+
+fn draw(
+    data: &[u8],
+    image: &DynamicImage,
+    window: &Window,
+    (cols, rows): (u16, u16),
+    origin: (u16, u16),
+    beside: bool,
+) -> Result<String> {
+    let quirks = quirks();
+    // Clearing goes from the cursor to the end of the screen
+    let clear = match beside {
+        true => "",
+        false => "\x1b[J",
+    };
+    // Without the window's size in pixels there's nothing to scale to
+    let fitted = match quirks.prescale && window.width_px > 0 {
+        true => Some(fit(image, window, cols, rows)),
+        false => None,
+    };
+    let prescaled = match &fitted {
+        Some(fitted) => Some(encode(fitted)?),
+        None => None,
+    };
+    let data = prescaled.as_deref().unwrap_or(data);
+    Ok(match protocol() {
+        // Both only cover what they draw, so clear what the last page left
+        Protocol::Sixel => {
+            let scaled = fit(image, window, cols, rows);
+            format!("{}{}", clear, sixel::encode(&scaled.to_rgba8()))
+        }
+        Protocol::Text => format!("{}{}", clear, blocks(image, cols, rows, origin)),
+        Protocol::Iterm => {
+            let encoded = general_purpose::STANDARD.encode(data);
+            // Prescaled pages are drawn as they are
+            let mut args = format!("inline=1;preserveAspectRatio=1;size={}", data.len());
+            if prescaled.is_none() {
+                args.push_str(&format!(";width={};height={}", cols, rows));
+            }
+            match quirks.multipart && encoded.len() > ITERM_CHUNK {
+                // A single escape of several megabytes stalls some links
+                // and multiplexers, and parts can be passed on as they come
+                true => {
+                    let mut sequence = format!("\x1b]1337;MultipartFile={}\x07", args);
+                    for chunk in encoded.as_bytes().chunks(ITERM_CHUNK) {
+                        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+                        sequence.push_str(&format!("\x1b]1337;FilePart={}\x07", chunk));
+                    }
+                    sequence.push_str("\x1b]1337;FileEnd\x07");
+                    sequence
+                }
+                false => format!("\x1b]1337;File={}:{}\x07", args, encoded),
+            }
+        }
+        Protocol::Kitty => {
+            let (commands, id) = kitty(
+                data,
+                image,
+                fitted.as_ref(),
+                prescaled.is_some(),
+                cols,
+                rows,
+            );
+            // The first takes down what's shown
+            let skip = beside as usize;
+            let mut sequence: String = commands.iter().skip(skip).map(|c| passthrough(c)).collect();
+            if let (true, Some(id)) = (quirks.placeholders, id) {
+                sequence.push_str(&placeholders(id, cols, rows, origin));
+            }
+            sequence
+        }
+    })
+}
+
+/// kitty's commands to show `image` (`data` encoded, `fitted` if it was
+/// prescaled), one per escape sequence, and the id it's shown with
+fn kitty(
+    data: &[u8],
+    image: &DynamicImage,
+    fitted: Option<&DynamicImage>,
+    prescaled: bool,
+    cols: u16,
+    rows: u16,
+) -> (Vec<String>, Option<u32>) {
+    // Placements stay up until they're deleted, and the next page may
+    // not cover all of this one. Keep the images themselves, to
+    // place again if we come back.
+    let quirks = quirks();
+    let mut sequence = vec![match quirks.plain_delete {
+        true => "\x1b_Ga=d,q=2\x1b\\".to_string(),
+        false => "\x1b_Ga=d,d=a,q=2\x1b\\".to_string(),
+    }];
+    // q=2: no replies, which would turn up as keypresses. C=1: leave
+    // the cursor where it is, so the image can't scroll. U=1: a virtual
+    // placement, shown wherever its placeholders are written.
+    let place = match (quirks.placeholders, prescaled) {
+        (true, _) => format!("U=1,c={},r={},q=2", cols, rows),
+        (false, true) => "C=1,q=2".to_string(),
+        (false, false) => format!("c={},r={},C=1,q=2", cols, rows),
+    };
+    // Placeholders say which image they're of
+    let id = match quirks.plain_delete && !quirks.placeholders {
+        true => None,
+        false => {
+            let mut hasher = DefaultHasher::new();
+            data.hash(&mut hasher);
+            let hash = hasher.finish();
+            let mut sent = SENT.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(id) = sent.get(hash) {
+                sequence.push(format!("\x1b_Ga=p,i={},{}\x1b\\", id, place));
+                return (sequence, Some(id));
+            }
+            let shown = fitted.unwrap_or(image);
+            let bytes = shown.width() as usize * shown.height() as usize * 4;
+            let (id, old) = sent.add(hash, bytes);
+            for old in old {
+                sequence.push(format!("\x1b_Ga=d,d=I,i={},q=2\x1b\\", old));
+            }
+            Some(id)
+        }
+    };
+    // f=24 is RGB pixels, o=z compressed, and s and v say how many. Pages
+    // made smaller to fit `max_image_size` are PNG whatever the format.
+    let png = data.starts_with(b"\x89PNG");
+    let (format, suffix, data) = match unraw(data).filter(|_| !png) {
+        Some(((width, height), pixels)) => (
+            format!("f=24,s={},v={},o=z", width, height),
+            "rgb.z",
+            pixels,
+        ),
+        None => ("f=100".to_string(), "png", data),
+    };
+    let first = match id {
+        Some(id) => format!("a=T,{},i={},{}", format, id, place),
+        None => format!("a=T,{},{}", format, place),
+    };
+    // A few bytes of file name instead of megabytes of base64
+    if quirks.files {
+        if let Ok(path) = temporary(data, suffix) {
+            let path = general_purpose::STANDARD.encode(path.as_os_str().as_bytes());
+            sequence.push(format!("\x1b_G{},t=t;{}\x1b\\", first, path));
+            return (sequence, id);
+        }
+    }
+    let encoded = general_purpose::STANDARD.encode(data);
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = (i + 1 < chunks.len()) as u8;
+        let control = match i {
+            0 => format!("{},m={}", first, more),
+            _ => format!("m={}", more),
+        };
+        // Base64 is ASCII, so the chunks are too
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        sequence.push(format!("\x1b_G{};{}\x1b\\", control, chunk));
+    }
+    (sequence, id)
+}
+
+/// `command` as tmux passes it on to the terminal outside, when we're in
+/// tmux: wrapped up, with its escapes doubled. tmux only does this with
+/// `allow-passthrough on`.
+fn passthrough(command: &str) -> String {
+    match quirks().tmux {
+        true => format!("\x1bPtmux;{}\x1b\\", command.replace('\x1b', "\x1b\x1b")),
+        false => command.to_string(),
+    }
+}
+
+/// kitty's combining characters for numbering the rows and columns of
+/// placeholders, as ranges: the first is 0, and so on
+#[rustfmt::skip]
+const DIACRITICS: [(u32, u32); 86] = [
+    (0x0305, 0x0305), (0x030D, 0x030E), (0x0310, 0x0310), (0x0312, 0x0312), (0x033D, 0x033F),
+    (0x0346, 0x0346), (0x034A, 0x034C), (0x0350, 0x0352), (0x0357, 0x0357), (0x035B, 0x035B),
+    (0x0363, 0x036F), (0x0483, 0x0487), (0x0592, 0x0595), (0x0597, 0x0599), (0x059C, 0x05A1),
+    (0x05A8, 0x05A9), (0x05AB, 0x05AC), (0x05AF, 0x05AF), (0x05C4, 0x05C4), (0x0610, 0x0617),
+    (0x0657, 0x065B), (0x065D, 0x065E), (0x06D6, 0x06DC), (0x06DF, 0x06E2), (0x06E4, 0x06E4),
+    (0x06E7, 0x06E8), (0x06EB, 0x06EC), (0x0730, 0x0730), (0x0732, 0x0733), (0x0735, 0x0736),
+    (0x073A, 0x073A), (0x073D, 0x073D), (0x073F, 0x0741), (0x0743, 0x0743), (0x0745, 0x0745),
+    (0x0747, 0x0747), (0x0749, 0x074A), (0x07EB, 0x07F1), (0x07F3, 0x07F3), (0x0816, 0x0819),
+    (0x081B, 0x0823), (0x0825, 0x0827), (0x0829, 0x082D), (0x0951, 0x0951), (0x0953, 0x0954),
+    (0x0F82, 0x0F83), (0x0F86, 0x0F87), (0x135D, 0x135F), (0x17DD, 0x17DD), (0x193A, 0x193A),
+    (0x1A17, 0x1A17), (0x1A75, 0x1A7C), (0x1B6B, 0x1B6B), (0x1B6D, 0x1B73), (0x1CD0, 0x1CD2),
+    (0x1CDA, 0x1CDB), (0x1CE0, 0x1CE0), (0x1DC0, 0x1DC1), (0x1DC3, 0x1DC9), (0x1DCB, 0x1DCC),
+    (0x1DD1, 0x1DE6), (0x1DFE, 0x1DFE), (0x20D0, 0x20D1), (0x20D4, 0x20D7), (0x20DB, 0x20DC),
+    (0x20E1, 0x20E1), (0x20E7, 0x20E7), (0x20E9, 0x20E9), (0x20F0, 0x20F0), (0x2CEF, 0x2CF1),
+    (0x2DE0, 0x2DFF), (0xA66F, 0xA66F), (0xA67C, 0xA67D), (0xA6F0, 0xA6F1), (0xA8E0, 0xA8F1),
+    (0xAAB0, 0xAAB0), (0xAAB2, 0xAAB3), (0xAAB7, 0xAAB8), (0xAABE, 0xAABF), (0xAAC1, 0xAAC1),
+    (0xFE20, 0xFE26), (0x10A0F, 0x10A0F), (0x10A38, 0x10A38), (0x1D185, 0x1D189),
+    (0x1D1AA, 0x1D1AD), (0x1D242, 0x1D244),
+];
+
+/// The diacritic for row or column `n`, if there are that many
+fn diacritic(mut n: u32) -> Option<char> {
+    for &(first, last) in &DIACRITICS {
+        let count = last - first + 1;
+        if n < count {
+            return char::from_u32(first + n);
+        }
+        n -= count;
+    }
+    None
+}
+
+/// Where the image with `id` goes, as text: a grid of kitty's placeholder
+/// character, `cols` by `rows` from `origin`, its colour the image's id.
+/// Being text, it's kept and redrawn by tmux like the rest of the screen.
+fn placeholders(id: u32, cols: u16, rows: u16, (x, y): (u16, u16)) -> String {
+    const PLACEHOLDER: char = '\u{10EEEE}';
+    let [high, r, g, b] = id.to_be_bytes();
+    let mut out = format!("\x1b[38;2;{};{};{}m", r, g, b);
+    for row in 0..rows {
+        out.push_str(&format!("{}", termion::cursor::Goto(x, y + row)));
+        // Only the first of a row needs numbering: the rest take the next
+        // column along, and the same row and top byte of the id
+        out.push(PLACEHOLDER);
+        out.extend(diacritic(row as u32));
+        out.extend(diacritic(0));
+        if high > 0 {
+            out.extend(diacritic(high as u32));
+        }
+        out.extend(std::iter::repeat_n(
+            PLACEHOLDER,
+            cols.saturating_sub(1) as usize,
+        ));
+    }
+    out.push_str("\x1b[39m");
+    out
+}
+
+/// The directory images are left in for kitty, made the first time one is
+static FILES: Mutex<Option<TempDir>> = Mutex::new(None);
+
+/// Leave `data` in a file for kitty, which deletes it once it's read it.
+/// kitty only does that for files in a temporary directory with
+/// `tty-graphics-protocol` in their path; `/dev/shm` is in memory. The
+/// directory is our own, so nobody else can see the pages or put something
+/// where they'll be written.
+fn temporary(data: &[u8], suffix: &str) -> Result<PathBuf> {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let mut files = FILES.lock().unwrap_or_else(|e| e.into_inner());
+    let dir = match files.as_ref() {
+        Some(dir) => dir,
+        None => {
+            let shm = Path::new("/dev/shm");
+            let parent = match shm.is_dir() {
+                true => shm.to_path_buf(),
+                false => env::temp_dir(),
+            };
+            files.insert(TempDir::new_in(&parent, "termpdf-tty-graphics-protocol")?)
+        }
+    };
+    let n = COUNT.fetch_add(1, Ordering::Relaxed);
+    dir.write(&format!("{}.{}", n, suffix), data)
+}
+
+/// Remove the files left for kitty, and their directory, once termpdf is
+/// done
+pub fn finish() {
+    FILES.lock().unwrap_or_else(|e| e.into_inner()).take();
+}
+
+/// The image as upper half blocks, the top pixel of each cell in the
+/// foreground colour and the bottom one in the background, from `origin`
+fn blocks(image: &DynamicImage, cols: u16, rows: u16, (x, y): (u16, u16)) -> String {
+    let scaled = image
+        .resize_exact(cols as u32, rows as u32 * 2, imageops::FilterType::Triangle)
+        .to_rgb8();
+    let truecolor = *TRUECOLOR.get().unwrap_or(&true);
+    // Palette entries, dithered to make up for the colours the 256 don't
+    // have, unless they're not needed
+    let palette = match truecolor {
+        true => vec![],
+        false => {
+            let pixels: Vec<[i32; 3]> = scaled.pixels().map(|p| p.0.map(|c| c as i32)).collect();
+            let dither = *TEXT_DITHER.get().unwrap_or(&Dither::Auto);
+            dither::indices(&pixels, cols as usize, &Xterm256, dither)
+        }
+    };
+    let mut out = String::new();
+    for row in 0..rows as u32 {
+        out.push_str(&format!("{}", termion::cursor::Goto(x, y + row as u16)));
+        let mut last = None;
+        for col in 0..cols as u32 {
+            let top = scaled.get_pixel(col, row * 2).0;
+            let bottom = scaled.get_pixel(col, row * 2 + 1).0;
+            let sgr = match truecolor {
+                true => format!(
+                    "\x1b[38;2;{};{};{};48;2;{};{};{}m",
+                    top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+                ),
+                false => {
+                    let at = |y: u32| palette[(y * cols as u32 + col) as usize];
+                    format!("\x1b[38;5;{};48;5;{}m", at(row * 2), at(row * 2 + 1))
+                }
+            };
+            // Runs of the same colours (most of a page is white) share one
+            // escape sequence
+            if last.as_ref() != Some(&sgr) {
+                out.push_str(&sgr);
+                last = Some(sgr);
+            }
+            out.push('▀');
+        }
+        out.push_str("\x1b[0m");
+    }
+    out
+}
+
+/// The levels of each channel in the 6×6×6 cube of the 256 colours
+const LEVELS: [i32; 6] = [0, 95, 135, 175, 215, 255];
+
+/// The 256 colours every terminal has, to dither to
+struct Xterm256;
+
+impl Colours for Xterm256 {
+    fn index(&self, rgb: [i32; 3]) -> usize {
+        xterm256(rgb.map(|c| c.clamp(0, 255) as u8)) as usize
+    }
+
+    fn rgb(&self, index: usize) -> [i32; 3] {
+        match index {
+            232.. => [8 + (index as i32 - 232) * 10; 3],
+            16.. => {
+                let i = index - 16;
+                [LEVELS[i / 36], LEVELS[i / 6 % 6], LEVELS[i % 6]]
+            }
+            // The 16 system colours are whatever the terminal's theme says;
+            // they're never picked
+            _ => [0; 3],
+        }
+    }
+
+    fn step(&self) -> i32 {
+        40
+    }
+}
+
+/// The nearest of the 256 colours every terminal has: a 6×6×6 cube from 16
+/// and 24 greys from 232
+fn xterm256([r, g, b]: [u8; 3]) -> u8 {
+    let nearest = |c: u8| {
+        (0..6)
+            .min_by_key(|&i| (LEVELS[i] - c as i32).abs())
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest(r), nearest(g), nearest(b));
+    let cube = (LEVELS[ri], LEVELS[gi], LEVELS[bi]);
+    let average = (r as i32 + g as i32 + b as i32) / 3;
+    let grey_index = ((average - 8) / 10).clamp(0, 23);
+    let grey = 8 + grey_index * 10;
+    let distance = |(cr, cg, cb): (i32, i32, i32)| {
+        (cr - r as i32).pow(2) + (cg - g as i32).pow(2) + (cb - b as i32).pow(2)
+    };
+    match distance((grey, grey, grey)) < distance(cube) {
+        true => 232 + grey_index as u8,
+        false => 16 + (36 * ri + 6 * gi + bi) as u8,
+    }
+}
@@ -0,0 +1,108 @@
+//! Following links from the keyboard: each link on the page is framed and
+//! given a label of a letter or two from the home row, and typing a label
+//! follows that link.
+
+use anyhow::Result;
+use image::{imageops, DynamicImage};
+use termion::event::Key;
+
+use crate::links::{Link, Target};
+use crate::{search, stamp};
+
+pub enum HintResult {
+    Pending,
+    /// Fewer labels match what's been typed, so they need drawing again
+    Redraw,
+    Follow(Target),
+    Close,
+}
+
+/// The letters labels are made of, the easiest to reach first
+const LETTERS: &str = "asdfghjkl";
+
+pub struct Hints {
+    links: Vec<Link>,
+    labels: Vec<String>,
+    typed: String,
+}
+
+/// `n` labels, all as long as each other, as short as they can be
+fn labels(n: usize) -> Vec<String> {
+    let letters: Vec<char> = LETTERS.chars().collect();
+    let mut length = 1;
+    while letters.len().pow(length) < n {
+        length += 1;
+    }
+    (0..n)
+        .map(|mut i| {
+            let mut label = vec![' '; length as usize];
+            for c in label.iter_mut().rev() {
+                *c = letters[i % letters.len()];
+                i /= letters.len();
+            }
+            label.into_iter().collect()
+        })
+        .collect()
+}
+
+impl Hints {
+    pub fn new(links: Vec<Link>) -> Hints {
+        Hints {
+            labels: labels(links.len()),
+            links,
+            typed: String::new(),
+        }
+    }
+
+    pub fn help(&self) -> String {
+        format!(
+            "follow: type a label  {}  Backspace undo  Esc cancel",
+            self.typed
+        )
+    }
+
+    pub fn key(&mut self, key: Key) -> HintResult {
+        match key {
+            Key::Esc | Key::Char('q') => HintResult::Close,
+            Key::Backspace if self.typed.pop().is_some() => HintResult::Redraw,
+            Key::Char(c) if LETTERS.contains(c) => {
+                self.typed.push(c);
+                let matching: Vec<usize> = (0..self.labels.len())
+                    .filter(|&i| self.labels[i].starts_with(&self.typed))
+                    .collect();
+                match matching[..] {
+                    // Not a label: as if it hadn't been typed
+                    [] => {
+                        self.typed.pop();
+                        HintResult::Pending
+                    }
+                    [i] if self.labels[i] == self.typed => {
+                        HintResult::Follow(self.links[i].target.clone())
+                    }
+                    _ => HintResult::Redraw,
+                }
+            }
+            _ => HintResult::Pending,
+        }
+    }
+
+    /// `page` with the links whose labels start with what's been typed
+    /// framed and labelled
+    pub fn draw(&self, page: &DynamicImage) -> Result<DynamicImage> {
+        let shown: Vec<usize> = (0..self.links.len())
+            .filter(|&i| self.labels[i].starts_with(&self.typed))
+            .collect();
+        let frames: Vec<search::Match> = shown.iter().map(|&i| vec![self.links[i].rect]).collect();
+        let mut image = search::outline(page, &frames).to_rgba8();
+        let (width, height) = (image.width() as f64, image.height() as f64);
+        let size = (height as f32 / 50.0).clamp(12.0, 32.0);
+        for &i in &shown {
+            let badge = stamp::badge(&self.labels[i][self.typed.len()..], size)?;
+            let (x, y, _, _) = self.links[i].rect;
+            let x = (x * width) as i64 - badge.width() as i64 / 2;
+            let y = (y * height) as i64 - badge.height() as i64 / 2;
+            imageops::overlay(&mut image, &badge, x.max(0), y.max(0));
+        }
+        Ok(DynamicImage::ImageRgba8(image))
+    }
+}
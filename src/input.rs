@@ -0,0 +1,86 @@
+//! Reading key presses on a background thread.
+//!
+//! Keys are read with `poll` rather than a blocking read so that the thread
+//! can be paused while another program (a terminal file manager, say) owns
+//! the terminal. Otherwise the two would fight over every key press.
+//!
+//! The same thread sends `Msg::Resize` when the window changes size, which
+//! it notices by looking each time it wakes, rather than by catching
+//! SIGWINCH: the signal goes to whichever thread it likes, and most of ours
+//! can't be told to ignore it.
+
+use std::io::stdin;
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use termion::input::TermRead;
+
+use crate::Msg;
+
+#[derive(Clone)]
+pub struct Input {
+    paused: Arc<AtomicBool>,
+}
+
+impl Input {
+    pub fn spawn(tx: Sender<Msg>) -> Input {
+        let paused = Arc::new(AtomicBool::new(false));
+        let input = Input {
+            paused: paused.clone(),
+        };
+
+        thread::spawn(move || {
+            let fd = stdin().as_raw_fd();
+            let mut buf = [0u8; 1024];
+            let mut size = termion::terminal_size().ok();
+            loop {
+                if paused.load(Ordering::SeqCst) {
+                    thread::sleep(Duration::from_millis(20));
+                    continue;
+                }
+                let now = termion::terminal_size().ok();
+                if now != size {
+                    size = now;
+                    if tx.send(Msg::Resize).is_err() {
+                        return;
+                    }
+                }
+                let mut pfd = libc::pollfd {
+                    fd,
+                    events: libc::POLLIN,
+                    revents: 0,
+                };
+                let ready = unsafe { libc::poll(&mut pfd, 1, 50) };
+                // Check again, as we may have been paused while waiting
+                if ready <= 0 || paused.load(Ordering::SeqCst) {
+                    continue;
+                }
+                let n = unsafe { libc::read(fd, buf.as_mut_ptr() as *mut libc::c_void, buf.len()) };
+                if n <= 0 {
+                    break;
+                }
+                for key in (&buf[..n as usize]).keys().flatten() {
+                    if tx.send(Msg::Key(key)).is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+
+        input
+    }
+
+    pub fn pause(&self) {
+        self.paused.store(true, Ordering::SeqCst);
+        // Let a read that's already under way finish
+        thread::sleep(Duration::from_millis(60));
+    }
+
+    pub fn resume(&self) {
+        self.paused.store(false, Ordering::SeqCst);
+    }
+}
@@ -0,0 +1,81 @@
+//! Keeping track of work done in the background, so the status line can
+//! say how it's going and quitting can wait for it.
+
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+
+use crate::Msg;
+
+/// The jobs still running
+#[derive(Clone, Default)]
+pub struct Jobs {
+    running: Arc<Mutex<Vec<(usize, String)>>>,
+    next: Arc<Mutex<usize>>,
+}
+
+/// One job, which is over when this is dropped
+pub struct Job {
+    id: usize,
+    name: String,
+    jobs: Jobs,
+    tx: Sender<Msg>,
+}
+
+impl Jobs {
+    pub fn start(&self, name: &str, tx: Sender<Msg>) -> Job {
+        let id = {
+            let mut next = self.next.lock().unwrap();
+            *next += 1;
+            *next
+        };
+        self.running.lock().unwrap().push((id, name.to_string()));
+        let _ = tx.send(Msg::Notice(format!("{}...", name)));
+        Job {
+            id,
+            name: name.to_string(),
+            jobs: self.clone(),
+            tx,
+        }
+    }
+
+    /// What's still going, like "exporting flashcards, converting to LaTeX"
+    pub fn describe(&self) -> Option<String> {
+        let running = self.running.lock().unwrap();
+        match running.is_empty() {
+            true => None,
+            false => Some(
+                running
+                    .iter()
+                    .map(|(_, name)| name.to_lowercase())
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ),
+        }
+    }
+}
+
+impl Job {
+    /// Say how far along the job is, like "page 3/40"
+    pub fn progress(&self, how_far: &str) {
+        let _ = self
+            .tx
+            .send(Msg::Notice(format!("{}... {}", self.name, how_far)));
+    }
+
+    /// Report how it went, which also ends the job
+    pub fn finish(self, message: String) {
+        let tx = self.tx.clone();
+        drop(self);
+        let _ = tx.send(Msg::Notice(message));
+    }
+}
+
+impl Drop for Job {
+    fn drop(&mut self) {
+        self.jobs
+            .running
+            .lock()
+            .unwrap()
+            .retain(|(id, _)| *id != self.id);
+    }
+}
@@ -0,0 +1,53 @@
+//! The jump list: the pages `G`, `:N`, a mark or a search jumped away from,
+//! for `Ctrl-O` to go back to and `Ctrl-I` to come forward again, as in vim.
+
+/// The most pages remembered, the oldest being forgotten first
+const MAX: usize = 100;
+
+#[derive(Debug, Default)]
+pub struct Jumps {
+    pages: Vec<usize>,
+    /// Where in `pages` the page on screen is, or `pages.len()` when it's
+    /// past the end, not yet in the list
+    at: usize,
+}
+
+impl Jumps {
+    /// Remember `from`, the page being jumped away from. Anything gone back
+    /// past is forgotten.
+    pub fn push(&mut self, from: usize) {
+        self.pages.truncate(self.at);
+        if self.pages.last() != Some(&from) {
+            self.pages.push(from);
+        }
+        if self.pages.len() > MAX {
+            self.pages.remove(0);
+        }
+        self.at = self.pages.len();
+    }
+
+    /// The page jumped from before, if there is one, coming from `here`
+    pub fn back(&mut self, here: usize) -> Option<usize> {
+        if self.at == self.pages.len() {
+            // Remember where this is, to come forward to again
+            if self.pages.last() != Some(&here) {
+                self.pages.push(here);
+            }
+            self.at = self.pages.len() - 1;
+        }
+        if self.at == 0 {
+            return None;
+        }
+        self.at -= 1;
+        Some(self.pages[self.at])
+    }
+
+    /// The page gone back from, if there is one
+    pub fn forward(&mut self) -> Option<usize> {
+        if self.at + 1 >= self.pages.len() {
+            return None;
+        }
+        self.at += 1;
+        Some(self.pages[self.at])
+    }
+}
@@ -0,0 +1,45 @@
+//! Transcribing equations by handing a picture of them to an OCR-to-LaTeX
+//! tool such as pix2tex.
+
+use std::process::Stdio;
+
+use anyhow::{bail, Result};
+
+use crate::config::Config;
+use crate::temp::TempDir;
+use crate::{clipboard, external};
+
+/// Run the configured tool on `png`, put its answer on the clipboard and
+/// return it
+pub fn convert(png: &[u8], config: &Config) -> Result<String> {
+    let dir = TempDir::new("termpdf-equation")?;
+    let path = dir.write("equation.png", png)?;
+    let path_str = path.to_string_lossy().to_string();
+
+    let output = external::command(&config.latex_ocr_command, &[("file", &path_str)])?
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        bail!(
+            "'{}' exited with {}",
+            config.latex_ocr_command,
+            output.status
+        );
+    }
+
+    let text = String::from_utf8_lossy(&output.stdout);
+    // pix2tex prefixes its answer with the name of the file it read
+    let latex = text
+        .trim()
+        .strip_prefix(&format!("{}:", path_str))
+        .unwrap_or(text.trim())
+        .trim()
+        .to_string();
+    if latex.is_empty() {
+        bail!("'{}' didn't recognise anything", config.latex_ocr_command);
+    }
+
+    clipboard::copy_text(&latex, config)?;
+    Ok(latex)
+}
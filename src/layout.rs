@@ -0,0 +1,485 @@
+//! Working out how a rendered page fits in the terminal window.
+
+use std::sync::Mutex;
+
+/// The `cell_aspect`, `margin` and `center` settings
+static SETTINGS: Mutex<(Option<f64>, u16, bool)> = Mutex::new((None, 1, true));
+/// The `cell_pixels` setting
+static CELL_PIXELS: Mutex<Option<(u16, u16)>> = Mutex::new(None);
+/// The `thumbnail_strip` setting
+static STRIP: Mutex<Option<bool>> = Mutex::new(Some(false));
+
+/// Windows at least this many cells wide get the thumbnail strip when it's
+/// left to the width
+const WIDE: u16 = 160;
+
+/// Take cells to be `pixels` big, if given, and as `aspect` times as tall
+/// as they're wide, if given, rather than as the terminal says; keep
+/// `margin` cells clear all round the page; and put it in the middle of the
+/// rest if `center`
+pub fn configure(pixels: Option<(u16, u16)>, aspect: Option<f64>, margin: u16, center: bool) {
+    *CELL_PIXELS.lock().unwrap_or_else(|e| e.into_inner()) = pixels;
+    *SETTINGS.lock().unwrap_or_else(|e| e.into_inner()) = (aspect, margin, center);
+}
+
+/// Show the thumbnail strip, or not, or (None) in wide windows only
+pub fn configure_strip(strip: Option<bool>) {
+    *STRIP.lock().unwrap_or_else(|e| e.into_inner()) = strip;
+}
+
+/// How many cells wide the thumbnail strip is in a window `cols` wide: an
+/// eighth of it, within reason, and never more than a third
+fn strip_cols(cols: u16, strip: Option<bool>) -> u16 {
+    match strip {
+        Some(false) => 0,
+        None if cols < WIDE => 0,
+        _ => (cols / 8).clamp(12, 30).min(cols / 3),
+    }
+}
+
+/// The terminal dimension a page runs into first when it's scaled up to fill
+/// the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fit {
+    Width,
+    Height,
+}
+
+/// How big a page is shown: all of it, as wide or as tall as the window
+/// whatever that leaves off, or zoomed in by hand.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum View {
+    Page,
+    Width,
+    Height,
+    /// This many times the size the whole page fits the window at
+    Zoom(f64),
+}
+
+impl View {
+    pub fn parse(s: &str) -> Option<View> {
+        match s {
+            "page" => Some(View::Page),
+            "width" => Some(View::Width),
+            "height" => Some(View::Height),
+            _ => None,
+        }
+    }
+
+    /// The view after this one, going round fit page, fit width, fit height
+    /// and the `custom` zoom
+    pub fn next(self, custom: f64) -> View {
+        match self {
+            View::Page => View::Width,
+            View::Width => View::Height,
+            View::Height => View::Zoom(custom),
+            View::Zoom(_) => View::Page,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            View::Page => "fit page".to_string(),
+            View::Width => "fit width".to_string(),
+            View::Height => "fit height".to_string(),
+            View::Zoom(zoom) => format!("zoom ×{:.1}", zoom),
+        }
+    }
+
+    /// How many times the size that fits a page of `page` pixels in `area`
+    /// the page is shown at: 1 when it's all in view
+    pub fn zoom(self, page: (u32, u32), area: (u32, u32)) -> f64 {
+        if page.0 == 0 || page.1 == 0 || area.0 == 0 || area.1 == 0 {
+            return 1.0;
+        }
+        let across = area.0 as f64 / page.0 as f64;
+        let down = area.1 as f64 / page.1 as f64;
+        match self {
+            View::Page => 1.0,
+            View::Width => across / across.min(down),
+            View::Height => down / across.min(down),
+            View::Zoom(zoom) => zoom.max(1.0),
+        }
+    }
+
+    /// Where on the page, as fractions of it, a page turned to in this view
+    /// is first looked at: the top, or for fit height the left
+    pub fn start(self) -> (f64, f64) {
+        match self {
+            View::Height => (0.0, 0.5),
+            _ => (0.5, 0.0),
+        }
+    }
+}
+
+/// The terminal's size in cells and, when the terminal reports it, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Window {
+    pub cols: u16,
+    pub rows: u16,
+    pub width_px: u16,
+    pub height_px: u16,
+    /// How many times taller than wide a cell is, when that's been set
+    /// rather than left to the pixel size
+    pub cell_aspect: Option<f64>,
+    /// Cells left clear on every side of the page
+    pub margin: u16,
+    /// Whether the page goes in the middle of the space it has, rather than
+    /// its top left corner
+    pub center: bool,
+    /// Cells down the right kept for the thumbnail strip, 0 for none
+    pub strip: u16,
+}
+
+impl Window {
+    pub fn query() -> std::io::Result<Window> {
+        let (cols, rows) = termion::terminal_size()?;
+        let (cell_aspect, margin, center) = *SETTINGS.lock().unwrap_or_else(|e| e.into_inner());
+        let (mut width_px, mut height_px) = termion::terminal_size_pixels().unwrap_or((0, 0));
+        // tmux and some terminals don't say, but the cell size from an
+        // earlier probe gives it
+        if let (0, Some((cell_w, cell_h))) = (width_px.min(height_px), crate::probe::cell_size()) {
+            width_px = (cell_w * cols as f64) as u16;
+            height_px = (cell_h * rows as f64) as u16;
+        }
+        // Others say wrongly, over ssh or from inside a multiplexer, and
+        // a size that's set goes over whatever they said
+        if let Some((cell_w, cell_h)) = *CELL_PIXELS.lock().unwrap_or_else(|e| e.into_inner()) {
+            width_px = cell_w.saturating_mul(cols);
+            height_px = cell_h.saturating_mul(rows);
+        }
+        Ok(Window {
+            cols,
+            rows,
+            width_px,
+            height_px,
+            cell_aspect,
+            margin,
+            center,
+            strip: strip_cols(cols, *STRIP.lock().unwrap_or_else(|e| e.into_inner())),
+        })
+    }
+
+    /// Width and height of a single cell in pixels. Terminals that don't
+    /// report a pixel size get the common 1:2 cell shape, or the one set.
+    /// A shape that's set wins over the pixel size too, for terminals that
+    /// get it wrong, keeping the reported width.
+    pub fn cell_size(&self) -> (f64, f64) {
+        if self.width_px == 0 || self.height_px == 0 || self.cols == 0 || self.rows == 0 {
+            return (1.0, self.cell_aspect.unwrap_or(2.0));
+        }
+        let width = self.width_px as f64 / self.cols as f64;
+        match self.cell_aspect {
+            Some(aspect) => (width, width * aspect),
+            None => (width, self.height_px as f64 / self.rows as f64),
+        }
+    }
+
+    /// The cells available to the page, leaving the margin all round and
+    /// the thumbnail strip. The status line is in the bottom one.
+    pub fn usable_cells(&self) -> (u16, u16) {
+        let margin = self.margin.saturating_mul(2);
+        (
+            self.cols.saturating_sub(margin).saturating_sub(self.strip),
+            self.rows.saturating_sub(margin.max(1)),
+        )
+    }
+}
+
+/// Decide whether a page of `page` pixels should be sized by width or by
+/// height. The comparison is done in pixels, so non-square cells are taken
+/// into account.
+pub fn fit(page: (u32, u32), window: &Window) -> Fit {
+    let (cell_w, cell_h) = window.cell_size();
+    let (cols, rows) = window.usable_cells();
+    let area_w = cols as f64 * cell_w;
+    let area_h = rows as f64 * cell_h;
+
+    if page.0 == 0 || page.1 == 0 || area_w == 0.0 || area_h == 0.0 {
+        return Fit::Width;
+    }
+
+    let page_aspect = page.0 as f64 / page.1 as f64;
+    let area_aspect = area_w / area_h;
+
+    if page_aspect >= area_aspect {
+        Fit::Width
+    } else {
+        Fit::Height
+    }
+}
+
+/// The size in cells of the box the page is drawn into: as large as fits in
+/// the usable area while keeping the page's aspect ratio.
+pub fn placement(page: (u32, u32), window: &Window) -> (u16, u16) {
+    let (cell_w, cell_h) = window.cell_size();
+    let (cols, rows) = window.usable_cells();
+
+    if page.0 == 0 || page.1 == 0 {
+        return (cols.max(1), rows.max(1));
+    }
+
+    let page_aspect = page.0 as f64 / page.1 as f64;
+
+    match fit(page, window) {
+        Fit::Width => {
+            let height_px = cols as f64 * cell_w / page_aspect;
+            let rows = ((height_px / cell_h).round() as u16).clamp(1, rows.max(1));
+            (cols.max(1), rows)
+        }
+        Fit::Height => {
+            let width_px = rows as f64 * cell_h * page_aspect;
+            let cols = ((width_px / cell_w).round() as u16).clamp(1, cols.max(1));
+            (cols, rows.max(1))
+        }
+    }
+}
+
+/// The cell (counting from 1, as the terminal does) where the top left of
+/// a page `size` cells big goes: inside the margin, and in the middle of
+/// the usable area if it's centred.
+pub fn origin((cols, rows): (u16, u16), window: &Window) -> (u16, u16) {
+    let (usable_cols, usable_rows) = window.usable_cells();
+    let (x, y) = match window.center {
+        true => (
+            usable_cols.saturating_sub(cols) / 2,
+            usable_rows.saturating_sub(rows) / 2,
+        ),
+        false => (0, 0),
+    };
+    (1 + window.margin + x, 1 + window.margin + y)
+}
+
+/// The size in pixels of the usable area, which a page rendered to fit in
+/// it is shown at exactly. None when the terminal doesn't say how big it is
+/// in pixels.
+pub fn render_size(window: &Window) -> Option<(u32, u32)> {
+    if window.width_px == 0 || window.height_px == 0 {
+        return None;
+    }
+    let (cell_w, cell_h) = window.cell_size();
+    let (cols, rows) = window.usable_cells();
+    let size = ((cols as f64 * cell_w) as u32, (rows as f64 * cell_h) as u32);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// The size in pixels of the usable area if each cell were `cell_width`
+/// pixels wide, and as tall as the window's shape says
+pub fn guessed_size(window: &Window, cell_width: f64) -> (u32, u32) {
+    let (cell_w, cell_h) = window.cell_size();
+    let (cols, rows) = window.usable_cells();
+    let size = (
+        cols as f64 * cell_width,
+        rows as f64 * cell_width * cell_h / cell_w,
+    );
+    ((size.0 as u32).max(1), (size.1 as u32).max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(cols: u16, rows: u16, width_px: u16, height_px: u16) -> Window {
+        Window {
+            cols,
+            rows,
+            width_px,
+            height_px,
+            cell_aspect: None,
+            margin: 1,
+            center: true,
+            strip: 0,
+        }
+    }
+
+    const A4_PORTRAIT: (u32, u32) = (1358, 1920);
+    const A4_LANDSCAPE: (u32, u32) = (1920, 1358);
+    const SLIDE_16_9: (u32, u32) = (1920, 1080);
+
+    #[test]
+    fn portrait_page_in_wide_terminal_fits_height() {
+        // 200x50 cells of 8x16 pixels: 1600x800
+        let w = window(200, 50, 1600, 800);
+        assert_eq!(fit(A4_PORTRAIT, &w), Fit::Height);
+    }
+
+    #[test]
+    fn portrait_page_in_tall_terminal_fits_width() {
+        let w = window(60, 80, 480, 1280);
+        assert_eq!(fit(A4_PORTRAIT, &w), Fit::Width);
+    }
+
+    #[test]
+    fn landscape_page_in_square_terminal_fits_width() {
+        let w = window(120, 60, 960, 960);
+        assert_eq!(fit(A4_LANDSCAPE, &w), Fit::Width);
+    }
+
+    #[test]
+    fn landscape_page_in_very_wide_terminal_fits_height() {
+        let w = window(300, 50, 2400, 800);
+        assert_eq!(fit(A4_LANDSCAPE, &w), Fit::Height);
+    }
+
+    #[test]
+    fn slide_in_square_ish_terminal_fits_width() {
+        // 100x50 cells looks wide in cells but is square in pixels
+        let w = window(100, 50, 800, 800);
+        assert_eq!(fit(SLIDE_16_9, &w), Fit::Width);
+    }
+
+    #[test]
+    fn cell_shape_matters() {
+        // The same cell grid with wide cells leaves room for a page's full
+        // height, with tall cells the width runs out first
+        let wide_cells = window(80, 40, 1280, 640);
+        let tall_cells = window(80, 40, 480, 960);
+        assert_eq!(fit(A4_LANDSCAPE, &wide_cells), Fit::Height);
+        assert_eq!(fit(A4_LANDSCAPE, &tall_cells), Fit::Width);
+    }
+
+    #[test]
+    fn integer_aspect_ratio_regression() {
+        // Integer division made both the page and any wider-than-tall
+        // terminal "1", so this page was always sized by width and ran off
+        // the bottom of the square-celled window
+        let w = window(130, 70, 1040, 1120);
+        assert_eq!(fit((2000, 1100), &w), Fit::Width);
+        let w = window(130, 70, 2080, 1120);
+        assert_eq!(fit((2000, 1100), &w), Fit::Height);
+    }
+
+    #[test]
+    fn unknown_pixel_size_assumes_tall_cells() {
+        let w = window(160, 40, 0, 0);
+        // 158x38 cells at 1:2 is 158x76, wider than an A4 page
+        assert_eq!(fit(A4_PORTRAIT, &w), Fit::Height);
+        let w = window(50, 40, 0, 0);
+        assert_eq!(fit(A4_PORTRAIT, &w), Fit::Width);
+    }
+
+    #[test]
+    fn cell_aspect_overrides_the_pixel_size() {
+        // Square cells, going by the pixels, but set as 1:2, so the slide
+        // is sized by width as in 8x16 pixel cells rather than by height
+        let w = Window {
+            cell_aspect: Some(2.0),
+            ..window(100, 50, 800, 400)
+        };
+        assert_eq!(w.cell_size(), (8.0, 16.0));
+        assert_eq!(placement(SLIDE_16_9, &w), (98, 28));
+        let w = Window {
+            cell_aspect: Some(1.0),
+            ..window(100, 50, 0, 0)
+        };
+        assert_eq!(fit(A4_PORTRAIT, &w), Fit::Height);
+    }
+
+    #[test]
+    fn degenerate_sizes_do_not_panic() {
+        assert_eq!(fit((0, 0), &window(80, 24, 640, 480)), Fit::Width);
+        assert_eq!(fit(A4_PORTRAIT, &window(1, 1, 0, 0)), Fit::Width);
+        assert_eq!(placement((0, 0), &window(80, 24, 640, 480)), (78, 22));
+        assert_eq!(placement(A4_PORTRAIT, &window(1, 1, 0, 0)), (1, 1));
+    }
+
+    #[test]
+    fn placement_keeps_aspect_ratio() {
+        // 98x48 usable cells of 8x16 pixels, height bound: 768px tall page
+        // is 543px wide, or 68 cells
+        let w = window(100, 50, 800, 800);
+        assert_eq!(placement(A4_PORTRAIT, &w), (68, 48));
+        // Width bound: 784px wide slide is 441px tall, or 28 rows
+        assert_eq!(placement(SLIDE_16_9, &w), (98, 28));
+    }
+
+    #[test]
+    fn placement_never_exceeds_usable_area() {
+        for &(cols, rows, wp, hp) in &[(80, 24, 640, 384), (200, 60, 0, 0), (33, 97, 330, 1940)] {
+            let w = window(cols, rows, wp, hp);
+            for &page in &[A4_PORTRAIT, A4_LANDSCAPE, SLIDE_16_9, (100, 5000)] {
+                let (c, r) = placement(page, &w);
+                let (max_c, max_r) = w.usable_cells();
+                assert!(c >= 1 && c <= max_c, "{:?} in {:?}", page, w);
+                assert!(r >= 1 && r <= max_r, "{:?} in {:?}", page, w);
+            }
+        }
+    }
+
+    #[test]
+    fn origin_centres_inside_the_margin() {
+        // 68x48 of 98x48 usable cells, after a one cell margin
+        let w = window(100, 50, 800, 800);
+        assert_eq!(origin(placement(A4_PORTRAIT, &w), &w), (17, 2));
+        let w = Window {
+            center: false,
+            margin: 0,
+            ..w
+        };
+        assert_eq!(origin(placement(A4_PORTRAIT, &w), &w), (1, 1));
+        let w = Window { margin: 3, ..w };
+        assert_eq!(w.usable_cells(), (94, 44));
+        assert_eq!(origin((10, 10), &w), (4, 4));
+    }
+
+    #[test]
+    fn strip_comes_out_of_the_page_width() {
+        assert_eq!(strip_cols(200, None), 25);
+        assert_eq!(strip_cols(120, None), 0);
+        assert_eq!(strip_cols(120, Some(true)), 15);
+        assert_eq!(strip_cols(30, Some(true)), 10);
+        assert_eq!(strip_cols(400, Some(false)), 0);
+        let w = Window {
+            strip: 25,
+            ..window(200, 50, 1600, 800)
+        };
+        assert_eq!(w.usable_cells(), (173, 48));
+    }
+
+    #[test]
+    fn render_size_is_the_usable_area_in_pixels() {
+        // 8x16 pixel cells, less the border
+        assert_eq!(render_size(&window(200, 50, 1600, 800)), Some((1584, 768)));
+        assert_eq!(render_size(&window(80, 24, 0, 0)), None);
+        assert_eq!(render_size(&window(2, 2, 16, 32)), None);
+    }
+
+    #[test]
+    fn views_zoom_until_the_page_fills_the_window_one_way() {
+        let area = (1600, 800);
+        // Sized by height to fit, so twice as big again to fill the width
+        assert_eq!(View::Page.zoom((800, 800), area), 1.0);
+        assert_eq!(View::Width.zoom((800, 800), area), 2.0);
+        assert_eq!(View::Height.zoom((800, 800), area), 1.0);
+        // A wide slide already fills the width
+        assert_eq!(View::Width.zoom((3200, 800), area), 1.0);
+        assert_eq!(View::Height.zoom((3200, 800), area), 2.0);
+        assert_eq!(View::Zoom(3.0).zoom(A4_PORTRAIT, area), 3.0);
+        assert_eq!(View::Zoom(0.5).zoom(A4_PORTRAIT, area), 1.0);
+        assert_eq!(View::Width.zoom((0, 0), area), 1.0);
+    }
+
+    #[test]
+    fn views_go_round() {
+        let mut view = View::Page;
+        let mut seen = vec![];
+        for _ in 0..4 {
+            view = view.next(1.5);
+            seen.push(view);
+        }
+        assert_eq!(
+            seen,
+            [View::Width, View::Height, View::Zoom(1.5), View::Page]
+        );
+        assert_eq!(View::parse("width"), Some(View::Width));
+        assert_eq!(View::parse("zoom"), None);
+    }
+
+    #[test]
+    fn guessed_size_follows_the_cells() {
+        // 78x22 usable cells, twice as tall as wide without pixels to go by
+        assert_eq!(guessed_size(&window(80, 24, 0, 0), 16.0), (1248, 704));
+        // and the shape the pixels give otherwise
+        assert_eq!(guessed_size(&window(80, 24, 640, 384), 2.0), (156, 88));
+    }
+}
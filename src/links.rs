@@ -0,0 +1,106 @@
+//! The links on a page and where they go, for following them from the
+//! keyboard.
+//!
+//! Like search matches, where they are is kept as rectangles in fractions
+//! of the page image.
+
+use anyhow::{bail, Result};
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::query::{self, Query};
+use crate::render;
+use crate::search::Rect;
+
+/// Where a link goes
+#[derive(Clone, Debug, PartialEq)]
+pub enum Target {
+    /// A page of the same document, counted from 0
+    Page(usize),
+    /// A web page or the like, for the browser
+    Uri(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Link {
+    pub rect: Rect,
+    pub target: Target,
+}
+
+impl Link {
+    pub fn to_json(&self) -> Value {
+        let (x, y, w, h) = self.rect;
+        match &self.target {
+            Target::Page(page) => json!({ "rect": [x, y, w, h], "page": page }),
+            Target::Uri(uri) => json!({ "rect": [x, y, w, h], "uri": uri }),
+        }
+    }
+
+    pub fn from_json(value: &Value) -> Result<Link> {
+        let target = match (value["page"].as_u64(), value["uri"].as_str()) {
+            (Some(page), _) => Target::Page(page as usize),
+            (None, Some(uri)) => Target::Uri(uri.to_string()),
+            _ => bail!("a link going nowhere"),
+        };
+        Ok(Link {
+            rect: serde_json::from_value(value["rect"].clone())?,
+            target,
+        })
+    }
+}
+
+/// Where a link annotation goes, if it's somewhere termpdf can follow
+fn target(link: &PdfLink) -> Option<Target> {
+    if let Some(destination) = link.destination() {
+        return Some(Target::Page(destination.page_index().ok()? as usize));
+    }
+    let action = link.action()?;
+    if let Some(uri) = action.as_uri_action() {
+        return Some(Target::Uri(uri.uri().ok()?));
+    }
+    let destination = action.as_local_destination_action()?.destination().ok()?;
+    Some(Target::Page(destination.page_index().ok()? as usize))
+}
+
+/// The links on page `p` of `file`, top to bottom. `size` is the page image
+/// as displayed, which may have been turned to fit the terminal.
+pub fn on_page(file: &str, p: usize, size: (u32, u32)) -> Result<Vec<Link>> {
+    let answer = render::ask(file, &Query::Links { page: p, size })?;
+    query::list(&answer)?.iter().map(Link::from_json).collect()
+}
+
+/// `on_page`, for the renderer
+pub fn links_in(document: &PdfDocument, p: usize, size: (u32, u32)) -> Result<Vec<Link>> {
+    let page = document.pages().get(p as u16)?;
+    let (width, height) = (page.width().value as f64, page.height().value as f64);
+    let rotated = width > height && size.1 > size.0;
+
+    let mut links = vec![];
+    for annotation in page.annotations().iter() {
+        let target = match annotation.as_link_annotation() {
+            Some(annotation) => annotation.link().ok().as_ref().and_then(target),
+            None => continue,
+        };
+        let (target, bounds) = match (target, annotation.bounds()) {
+            (Some(target), Ok(bounds)) => (target, bounds),
+            _ => continue,
+        };
+        // PDF coordinates go up from the bottom of the page
+        let left = bounds.left.value as f64 / width;
+        let right = bounds.right.value as f64 / width;
+        let top = 1.0 - bounds.top.value as f64 / height;
+        let bottom = 1.0 - bounds.bottom.value as f64 / height;
+        // Turned 90° clockwise, the bottom of the page is on the left
+        let rect = match rotated {
+            true => (1.0 - bottom, left, bottom - top, right - left),
+            false => (left, top, right - left, bottom - top),
+        };
+        links.push(Link { rect, target });
+    }
+    links.sort_by(|a, b| {
+        (a.rect.1, a.rect.0)
+            .partial_cmp(&(b.rect.1, b.rect.0))
+            .unwrap()
+    });
+    Ok(links)
+}
@@ -0,0 +1,108 @@
+//! A magnifying glass moved over the page with the keyboard, for fine print
+//! and dense figures. What's inside it comes from a render of the page at
+//! several times the resolution, so it's sharp rather than scaled up.
+//!
+//! Like a `Selection`, where it is is kept as fractions of the page image.
+
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use termion::event::Key;
+
+pub enum MagnifyResult {
+    Pending,
+    /// The zoom changed, so the detail has to be rendered again
+    Zoomed,
+    Close,
+}
+
+#[derive(Clone, Debug)]
+pub struct Magnifier {
+    /// The spot magnified, as fractions of the page
+    pub x: f64,
+    pub y: f64,
+    pub zoom: f64,
+}
+
+const STEP: f64 = 0.02;
+const BIG_STEP: f64 = 0.1;
+const MAX_ZOOM: f64 = 4.0;
+const MIN_ZOOM: f64 = 1.5;
+/// How much of the page's width the glass takes up
+const LENS_WIDTH: f64 = 0.5;
+
+impl Magnifier {
+    pub fn new() -> Magnifier {
+        Magnifier {
+            x: 0.5,
+            y: 0.5,
+            zoom: 2.5,
+        }
+    }
+
+    pub fn help(&self) -> String {
+        format!(
+            "magnify ×{:.1}: hjkl move  HJKL move more  + - zoom  Esc close",
+            self.zoom
+        )
+    }
+
+    pub fn key(&mut self, key: Key) -> MagnifyResult {
+        match key {
+            Key::Char('h') | Key::Left => self.x -= STEP,
+            Key::Char('l') | Key::Right => self.x += STEP,
+            Key::Char('k') | Key::Up => self.y -= STEP,
+            Key::Char('j') | Key::Down => self.y += STEP,
+            Key::Char('H') => self.x -= BIG_STEP,
+            Key::Char('L') => self.x += BIG_STEP,
+            Key::Char('K') => self.y -= BIG_STEP,
+            Key::Char('J') => self.y += BIG_STEP,
+            Key::Char('+') | Key::Char('=') => {
+                self.zoom = (self.zoom * 1.5).min(MAX_ZOOM);
+                return MagnifyResult::Zoomed;
+            }
+            Key::Char('-') => {
+                self.zoom = (self.zoom / 1.5).max(MIN_ZOOM);
+                return MagnifyResult::Zoomed;
+            }
+            Key::Esc | Key::Char('q') | Key::Char('z') => return MagnifyResult::Close,
+            _ => {}
+        }
+        self.x = self.x.clamp(0.0, 1.0);
+        self.y = self.y.clamp(0.0, 1.0);
+        MagnifyResult::Pending
+    }
+
+    /// The page with the glass over the spot, showing that part of `detail`,
+    /// the page rendered `zoom` times bigger
+    pub fn draw(&self, page: &DynamicImage, detail: &DynamicImage) -> DynamicImage {
+        let mut image: RgbaImage = page.to_rgba8();
+        let (width, height) = image.dimensions();
+        let lens_w = ((width as f64 * LENS_WIDTH) as u32).clamp(1, width);
+        let lens_h = ((lens_w as f64 * 0.6) as u32).clamp(1, height);
+
+        // Centred on the spot, as far as the edges of the page allow
+        let place = |spot: f64, size: u32, lens: u32| {
+            ((spot * size as f64) as i64 - lens as i64 / 2).clamp(0, (size - lens) as i64) as u32
+        };
+        let (x, y) = (place(self.x, width, lens_w), place(self.y, height, lens_h));
+        let (dw, dh) = detail.dimensions();
+        let (sw, sh) = (lens_w.min(dw), lens_h.min(dh));
+        let (sx, sy) = (place(self.x, dw, sw), place(self.y, dh, sh));
+        let inside = detail.crop_imm(sx, sy, sw, sh).to_rgba8();
+        imageops::replace(&mut image, &inside, x as i64, y as i64);
+
+        let border = (width / 300).max(2);
+        let colour = Rgba([40, 90, 220, 255]);
+        for py in y..(y + lens_h).min(height) {
+            for px in x..(x + lens_w).min(width) {
+                let edge = px < x + border
+                    || px + border >= x + lens_w
+                    || py < y + border
+                    || py + border >= y + lens_h;
+                if edge {
+                    image.put_pixel(px, py, colour);
+                }
+            }
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+}
@@ -1,23 +1,98 @@
-use base64::engine::general_purpose;
-use base64::Engine as _;
-use notify::RecursiveMode;
+mod atomic;
+mod cache;
+mod check;
+mod clipboard;
+mod column;
+mod command;
+mod compare;
+mod config;
+mod dedupe;
+mod dither;
+mod dwell;
+mod external;
+mod filter;
+mod flashcards;
+mod gallery;
+mod graphics;
+mod hints;
+mod input;
+mod jobs;
+mod jumps;
+mod latex;
+mod layout;
+mod links;
+mod magnify;
+mod marks;
+mod metadata;
+mod outline;
+mod pager;
+mod poster;
+mod probe;
+mod prompt;
+mod query;
+mod queue;
+mod render;
+mod review;
+mod sandbox;
+mod scroll;
+mod search;
+mod select;
+mod sixel;
+mod speech;
+mod stamp;
+mod state;
+mod stats;
+mod strip;
+mod tags;
+mod temp;
+mod term;
+mod text;
+mod toc;
+mod watch;
+
+use image::DynamicImage;
 use pdfium_render::prelude::*;
 
-use std::path::Path;
-use std::process::Command;
+use std::collections::{HashMap, VecDeque};
+use std::env;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::mpsc;
-use std::sync::mpsc::Receiver;
+use std::sync::mpsc::{Receiver, Sender};
+use std::sync::Arc;
 use std::thread;
-use std::time::Duration;
-use std::{env};
+use std::time::{Duration, Instant};
 
 use anyhow::{bail, Result};
-use notify_debouncer_mini::new_debouncer;
-use std::io::{stdin, Write};
-use std::io::{stdout, Cursor};
+use compare::{CompareResult, Comparison};
+use config::Config;
+use dwell::Dwell;
+use hints::{HintResult, Hints};
+use input::Input;
+use jobs::Jobs;
+use jumps::Jumps;
+use layout::{View, Window};
+use links::Target;
+use magnify::{Magnifier, MagnifyResult};
+use marks::Marks;
+use pager::Pager;
+use poster::{Poster, PosterResult};
+use prompt::{Prompt, PromptResult};
+use queue::Queue;
+use review::{Review, ReviewRun};
+use scroll::{Scroll, ScrollResult};
+use select::{SelectResult, Selection};
+use std::io::Write;
+use std::io::{stdout, Cursor, Stdout};
+use std::process::Stdio;
+use strip::Strip;
+use tags::Tags;
 use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::IntoRawMode;
+use termion::raw::{IntoRawMode, RawTerminal};
+use toc::Toc;
+use watch::{AutoRefresh, Watcher};
 
 #[derive(Debug)]
 struct Pdf {
@@ -25,16 +100,115 @@ struct Pdf {
     page: Page,
     current_page: usize,
     length: usize,
-    text: Vec<String>,
+    rotation: Rotation,
+    /// The pages that can be browsed, all of them unless `--pages` was given
+    range: Range<usize>,
+    /// Show and go to physical page numbers rather than ones counted from
+    /// the start of `range`
+    absolute: bool,
+    /// The text of the page being shown, to find it again if the document
+    /// changes around it
+    anchor: String,
+    /// Why the current page couldn't be rendered
+    error: Option<String>,
+    /// The page labels, found the first time they're needed
+    labels: Option<Vec<String>>,
+    /// The title from the document's metadata, empty if it hasn't one
+    title: String,
+    /// Go by the title rather than the file name, when there is one
+    by_title: bool,
+    /// Text to mark on every page shown, for `hlsearch`
+    highlight: Option<String>,
+    /// The outline as edited with `:outline`, None until it's changed and
+    /// again once it's written
+    outline: Option<Vec<outline::Entry>>,
+    /// Whether `page` is a quick low-resolution render, with the full one
+    /// on its way
+    preview: bool,
+    /// The page the full render in the background is wanted for, so ones
+    /// for pages already turned past can give up
+    wanted: Arc<AtomicUsize>,
+    /// Where on the page is in view, and how big, while it's zoomed in
+    zoomed: Option<Poster>,
+    /// The page the view and `zoomed` are for, which a page turned to by
+    /// search may not be until it's been fitted
+    fitted: usize,
+    /// How each page was being viewed when it was left, and where on it if
+    /// it was zoomed in, with zoom kept per page
+    views: HashMap<usize, (View, Option<(f64, f64)>)>,
+    /// Whether the file has changed on disk since it was last loaded, and
+    /// is waiting for `r`
+    changed: bool,
+    /// The pages jumped from, for `Ctrl-O` and `Ctrl-I`
+    jumps: Jumps,
+}
+
+/// How landscape pages are rotated before they're displayed. `Auto` only
+/// turns them on their side when the terminal itself is taller than it is
+/// wide, so slide decks stay upright in a wide window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rotation {
+    Auto,
+    Always,
+    Never,
+}
+
+impl Rotation {
+    fn parse(s: &str) -> Option<Rotation> {
+        match s {
+            "auto" => Some(Rotation::Auto),
+            "always" => Some(Rotation::Always),
+            "never" => Some(Rotation::Never),
+            _ => None,
+        }
+    }
+
+    fn next(self) -> Rotation {
+        match self {
+            Rotation::Auto => Rotation::Always,
+            Rotation::Always => Rotation::Never,
+            Rotation::Never => Rotation::Auto,
+        }
+    }
+
+    fn rotate_landscape(self) -> bool {
+        match self {
+            Rotation::Auto => terminal_is_portrait(),
+            Rotation::Always => true,
+            Rotation::Never => false,
+        }
+    }
+}
+
+fn terminal_is_portrait() -> bool {
+    match termion::terminal_size_pixels() {
+        Ok((w, h)) if w > 0 && h > 0 => h > w,
+        // No pixel size reported, so assume cells are twice as tall as wide
+        _ => match termion::terminal_size() {
+            Ok((cols, rows)) => (rows as u32 * 2) > cols as u32,
+            Err(_) => false,
+        },
+    }
 }
 
 #[derive(Debug)]
 struct FileList {
+    /// The files being browsed, which may be narrowed down by `:filter`
     files: Vec<String>,
     current_file: usize,
+    /// Every file that was opened
+    all: Vec<String>,
 }
 
 impl FileList {
+    fn new(files: Vec<String>) -> FileList {
+        FileList {
+            all: files.clone(),
+            files,
+            current_file: 0,
+        }
+    }
+
     fn current(&self) -> String {
         let current = self.current_file;
         self.files[current].clone()
@@ -42,33 +216,241 @@ impl FileList {
 
     fn next(&mut self) {
         if self.current_file != (self.files.len() - 1) {
-            self.current_file = self.current_file + 1;
+            self.current_file += 1;
         }
     }
 
     fn prev(&mut self) {
         if self.current_file != 0 {
-            self.current_file = self.current_file - 1;
+            self.current_file -= 1;
+        }
+    }
+
+    fn is_first(&self) -> bool {
+        self.current_file == 0
+    }
+
+    fn is_last(&self) -> bool {
+        self.current_file + 1 >= self.files.len()
+    }
+
+    fn rename_current(&mut self, file: &str) {
+        let old = std::mem::replace(&mut self.files[self.current_file], file.to_string());
+        for f in self.all.iter_mut().filter(|f| **f == old) {
+            *f = file.to_string();
+        }
+    }
+
+    /// Drop the current file from the list, moving on to the one after it.
+    /// Returns false once the list is empty.
+    fn remove_current(&mut self) -> bool {
+        let old = self.files.remove(self.current_file);
+        self.all.retain(|f| *f != old);
+        if self.current_file >= self.files.len() && self.current_file > 0 {
+            self.current_file -= 1;
+        }
+        !self.files.is_empty()
+    }
+
+    /// Switch to `file`, adding it after the current file if it isn't in
+    /// the list already
+    fn open(&mut self, file: &str) {
+        let key = state::key(file);
+        if let Some(i) = self.files.iter().position(|f| state::key(f) == key) {
+            self.current_file = i;
+            return;
         }
+        if !self.all.iter().any(|f| state::key(f) == key) {
+            let current = self.current();
+            let at = self
+                .all
+                .iter()
+                .position(|f| *f == current)
+                .map_or(0, |i| i + 1);
+            self.all.insert(at, file.to_string());
+        }
+        self.current_file += 1;
+        self.files.insert(self.current_file, file.to_string());
+    }
+
+    /// Narrow the list down to the files that `keep` accepts, staying on the
+    /// current file if it's one of them. Returns false, leaving the list as
+    /// it was, if nothing matches.
+    fn filter<F: Fn(&str) -> bool>(&mut self, keep: F) -> bool {
+        let current = self.current();
+        let files: Vec<String> = self.all.iter().filter(|f| keep(f)).cloned().collect();
+        if files.is_empty() {
+            return false;
+        }
+        self.current_file = files.iter().position(|f| *f == current).unwrap_or(0);
+        self.files = files;
+        true
     }
 }
 
+/// Whether `file` matches every term of a `:filter` query. `tag:NAME` terms
+/// match tagged files and anything else matches the file name.
+fn matches_filter(file: &str, query: &str, tags: &Tags) -> bool {
+    query
+        .split_whitespace()
+        .all(|term| match term.strip_prefix("tag:") {
+            Some(tag) => tags.has(file, tag),
+            None => file.to_lowercase().contains(&term.to_lowercase()),
+        })
+}
+
 #[derive(Clone, Debug)]
 struct Page {
     data: Vec<u8>,
     size: (u32, u32),
+    /// The rendered page, kept so things can be drawn over it
+    image: DynamicImage,
 }
 
 #[derive(Debug)]
 enum Msg {
-    NextPage,
-    PreviousPage,
+    Key(Key),
+    Command,
+    Trash,
+    TrashConfirmed,
+    Rename(String),
+    /// Rename the file to this, as it is, with nothing in it filled in
+    RenameTo(PathBuf),
+    /// Work out a name for the file, and offer to rename it to that
+    SuggestName,
+    Tag(Vec<String>),
+    Untag(Vec<String>),
+    Filter(String),
+    Queue(String),
+    /// Add to, take from, list or save the outline
+    Outline(String),
+    ExportDwell(String),
+    Review(String),
+    ReviewAnswer(bool),
+    ReviewStop,
+    Flashcards(String),
+    Select(select::Purpose),
+    /// A message for the status line from a background job
+    Notice(String),
+    /// Send the text of a page range to the summarize command
+    Summarize(String),
+    /// Look a word up in the dictionary, or pick one off the page if empty
+    Lookup(String),
+    /// Some output from a command being shown in the pager
+    Output(String),
+    /// The command being shown in the pager has finished
+    OutputEnd,
+    /// Move this many pages on (or back, if negative); held keys add up
+    /// to one move
+    Pages(isize),
+    /// Go to a page, numbered as the status line shows it
+    Goto(usize),
+    /// Go to the page with this label, like "iv" or "A-3"
+    GotoLabel(String),
+    /// Highlight where this text is on the current page, staying put
+    Find(String),
+    /// Go to the next page with this text on it, unless it's on this one
+    Search(String),
+    /// Go to the next (or previous) page with the last search on it
+    SearchNext(bool),
+    /// Take the frames off the matches on this page, if it's still shown
+    Unflash(usize),
+    /// Turn `hlsearch` on or off
+    HlSearch(bool),
+    /// The file changed on disk
+    Changed,
+    /// What to do when the current file changes
+    AutoRefresh(AutoRefresh),
+    /// Keep zoom per page (true) or let it follow from page to page
+    ZoomPerPage(bool),
+    /// Stop highlighting until the next search
+    NoHighlight,
+    /// Write out the text of the page again, as screen-reader mode does
+    Speak,
+    /// Say where in the document this is, in words
+    Announce,
+    /// Put the magnifying glass over the page
+    Magnify,
+    /// Zoom in this many times the size that fits the window, looking at
+    /// this spot, as fractions of the page
+    Zoom(f64, (f64, f64)),
+    /// Back to the whole page, which zoomed in `-` does a step at a time
+    ZoomOut,
+    /// Show the page as the view has it, looking at this spot if that's
+    /// zoomed in
+    Fit((f64, f64)),
+    /// Zoom in to the body text on the page, leaving out the margins
+    FitText,
+    /// Go on to the next view: fit page, width or height, or zoomed in
+    CycleView,
+    /// Show the pages one after another, to scroll through
+    Continuous,
+    /// Compare the page with another: a page number, or a file and
+    /// optionally a page of it
+    Compare(String),
+    /// Invert the pages' colours, or put them back
+    ToggleDark,
+    /// Put dark mode on or off
+    Dark(bool),
+    /// Show pages as they'd be printed, or as they are
+    TogglePrintPreview,
+    /// Draw pages in the recolouring colours, or their own
+    ToggleRecolor,
+    /// Tint pages warm, or stop
+    ToggleNightLight,
+    /// Show pages in colour, grey or black and white, whichever is next
+    CycleTone,
+    /// Set a mark, with the letter typed next
+    Mark,
+    /// Go to a mark, with the letter typed next
+    ToMark,
+    /// Mark this page with the letter
+    SetMark(char),
+    /// Go to the page marked with the letter
+    GotoMark(char),
+    /// Back to the page jumped from
+    JumpBack,
+    /// Forward again to the page gone back from
+    JumpForward,
+    /// Show the table of contents, to go to a section from
+    Contents,
+    /// Label the links on the page, to follow one
+    Hints,
+    /// List the keys
+    Help,
+    /// Go where a link goes
+    Follow(Target),
+    /// Open a link's address with the browser command, once it's been seen
+    Browse(String),
+    /// `]` (true) or `[`, waiting for what to go to the next or previous of
+    Bracket(bool),
+    /// Go to the next (true) or previous section in the outline
+    Section(bool),
+    /// Go to the next (true) or previous entry at the top of the outline
+    Chapter(bool),
+    /// Change one of the colour filter's settings, like `brightness`
+    SetColor(String, String),
+    /// The full render of a page shown as a preview, done
+    Sharpened(usize, Box<Result<(Page, render::Rendered)>>),
+    /// A page of a document rendered small for the thumbnail strip
+    Thumbnail(Box<strip::Thumb>),
+    /// Switch between physical page numbers and ones relative to `--pages`
+    ToggleNumbering,
     NextDocument,
     PreviousDocument,
     Refresh,
+    /// The window changed size, so the page needs rendering to fit it again
+    Resize,
+    /// The config file changed, so read it again
+    ReloadConfig,
+    /// Switch to this profile, "none" for none, or say which it is if empty
+    Profile(String),
     Quit,
+    /// Quit even though background jobs are still running
+    QuitConfirmed,
     Open,
-    // Rotate,
+    Reveal,
+    Rotate,
     None,
     LastPage,
     FirstPage,
@@ -77,64 +459,167 @@ enum Msg {
 impl From<Key> for Msg {
     fn from(item: Key) -> Self {
         match item {
-            Key::Char('j') => Msg::NextPage,
-            Key::Down => Msg::NextPage,
-            Key::Char('k') => Msg::PreviousPage,
-            Key::Up => Msg::PreviousPage,
+            Key::Char('j') => Msg::Pages(1),
+            Key::Down => Msg::Pages(1),
+            Key::Char('k') => Msg::Pages(-1),
+            Key::Up => Msg::Pages(-1),
             Key::Char('r') => Msg::Refresh,
             Key::Char('q') => Msg::Quit,
             Key::Char('o') => Msg::Open,
+            Key::Char('R') => Msg::Reveal,
             Key::Char('l') => Msg::NextDocument,
             Key::Char('h') => Msg::PreviousDocument,
             Key::Left => Msg::PreviousDocument,
             Key::Right => Msg::NextDocument,
             Key::Char('G') => Msg::LastPage,
             Key::Char('g') => Msg::FirstPage,
-            // Key::Char('w') => Msg::Rotate,
+            Key::Char('w') => Msg::Rotate,
+            Key::Char(':') => Msg::Command,
+            Key::Char('#') => Msg::ToggleNumbering,
+            Key::Char('v') => Msg::Select(select::Purpose::Screenshot),
+            Key::Char('z') => Msg::Magnify,
+            Key::Char('P') => Msg::Zoom(2.0, (0.5, 0.5)),
+            Key::Char('+') | Key::Char('=') => Msg::Zoom(1.5, (0.5, 0.0)),
+            Key::Char('-') => Msg::ZoomOut,
+            Key::Char('f') => Msg::CycleView,
+            Key::Char('T') => Msg::FitText,
+            Key::Char('c') => Msg::Continuous,
+            Key::Char('M') => Msg::CycleTone,
+            Key::Char('m') => Msg::Mark,
+            Key::Char('\'') => Msg::ToMark,
+            Key::Char('i') => Msg::ToggleDark,
+            Key::Char('p') => Msg::TogglePrintPreview,
+            Key::Ctrl('r') => Msg::ToggleRecolor,
+            Key::Ctrl('n') => Msg::ToggleNightLight,
+            Key::Char('/') => Msg::Search(String::new()),
+            Key::Ctrl('g') => Msg::Announce,
+            Key::Char('F') => Msg::Hints,
+            Key::Char('?') => Msg::Help,
+            Key::Char(']') => Msg::Bracket(true),
+            Key::Char('[') => Msg::Bracket(false),
+            Key::Char('}') => Msg::Chapter(true),
+            Key::Char('{') => Msg::Chapter(false),
+            Key::Ctrl('o') => Msg::JumpBack,
+            // Terminals send Ctrl-I as Tab, which is the table of contents
+            Key::Ctrl('p') => Msg::JumpForward,
+            Key::Char('\t') => Msg::Contents,
+            Key::Char('n') => Msg::SearchNext(true),
+            Key::Char('N') => Msg::SearchNext(false),
             _ => Msg::None,
         }
     }
 }
 
+/// A count typed before a key, like the 5 of `5j`: how many pages `j` `k`
+/// and the arrows turn, or the page `G` goes to
+#[derive(Debug, Default)]
+struct Count(Option<usize>);
+
+impl Count {
+    /// `key` as a message, done as many times as the count says, or None if
+    /// it's another digit of the count
+    fn key(&mut self, key: Key) -> Option<Msg> {
+        if let Key::Char(d) = key {
+            if d.is_ascii_digit() && (self.0.is_some() || d != '0') {
+                let n = self.0.unwrap_or(0).saturating_mul(10);
+                self.0 = Some(n.saturating_add(d as usize - '0' as usize));
+                return None;
+            }
+        }
+        Some(match (self.0.take(), key) {
+            (Some(n), Key::Char('G')) => Msg::Goto(n),
+            (Some(n), key) => match Msg::from(key) {
+                Msg::Pages(by) => {
+                    Msg::Pages(by.saturating_mul(n.min(isize::MAX as usize) as isize))
+                }
+                msg => msg,
+            },
+            (None, key) => key.into(),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_none()
+    }
+}
+
+impl std::fmt::Display for Count {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.0 {
+            Some(n) => write!(f, "{}", n),
+            None => Ok(()),
+        }
+    }
+}
+
 impl Page {
-    fn display(&self, r: Option<bool>) -> Result<()> {
-        let size = termion::terminal_size();
+    /// A plain grey stand-in for a page that couldn't be rendered
+    fn blank((width, height): (u32, u32)) -> Result<Page> {
+        let grey = image::Rgba([200, 200, 200, 255]);
+        Page::from_image(DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+            width.max(1),
+            height.max(1),
+            grey,
+        )))
+    }
+
+    fn from_image(image: DynamicImage) -> Result<Page> {
+        let image = graphics::toned(image);
+        Ok(Page {
+            data: graphics::encode(&image)?,
+            size: (image.width(), image.height()),
+            image,
+        })
+    }
 
-        let (cols, rows) = match size {
-            Ok((c, r)) => (c, r),
+    fn display(&self) -> Result<()> {
+        let window = match Window::query() {
+            Ok(w) => w,
             _ => anyhow::bail!("Whoops"),
         };
+        let (cols, rows) = layout::placement(self.size, &window);
+        let (x, y) = layout::origin((cols, rows), &window);
 
-        let mut stdout = stdout();
-
-        let mut pdf_aspect_ratio = (self.size.0 as i32 / self.size.1 as i32) >= 1;
-        let mut term_aspect_ratio = (cols as i32 / rows as i32) >= 1;
-
-        if r.is_some()  {
-            pdf_aspect_ratio = !pdf_aspect_ratio;
-            term_aspect_ratio = !term_aspect_ratio;
-        } 
-        if (pdf_aspect_ratio == false) & (term_aspect_ratio == true) {
-            write!(stdout, "{}", termion::cursor::Goto(1, 1))?;
-            writeln!(
-                stdout,
-                "\x1b]1337;File=inline=1;preserveAspectRatio=1;size={};height={}:{}\x07",
-                self.data.len(),
-                rows - 2,
-                general_purpose::STANDARD.encode(&self.data)
-            )?;
-        } else {
-            write!(stdout, "{}", termion::cursor::Goto(1, 1))?;
-            writeln!(
-                stdout,
-                "\x1b]1337;File=inline=1;preserveAspectRatio=1;size={};width={}:{}\x07",
-                self.data.len(),
-                cols - 2,
-                general_purpose::STANDARD.encode(&self.data)
-            )?;
-        }
-        Ok(())
+        // No trailing newline: the cursor already sits below the image, and
+        // a newline on the last row of the scroll region would scroll it
+        let sequence = format!(
+            "{}{}",
+            termion::cursor::Goto(x, y),
+            graphics::sequence(&self.data, &self.image, &window, cols, rows)?
+        );
+        // Which may have cleared the strip, or taken it down
+        strip::invalidate();
+        term::write_sequence(sequence.as_bytes())
+    }
+}
+
+/// Draw the thumbnail strip down the right of the window, if there's one,
+/// sending for the thumbnails it hasn't got
+fn show_strip(pdf: &Pdf, strip: &mut Strip, tx: &Sender<Msg>) -> Result<()> {
+    let window = Window::query()?;
+    if window.strip == 0 {
+        return Ok(());
     }
+    // Above the status line
+    let (cols, rows) = (window.strip, window.rows.saturating_sub(1).max(1));
+    let (cell_w, cell_h) = window.cell_size();
+    let scale = match window.width_px {
+        0 => graphics::cell_width() / cell_w,
+        _ => 1.0,
+    };
+    let size = (
+        (cols as f64 * cell_w * scale) as u32,
+        (rows as f64 * cell_h * scale) as u32,
+    );
+    strip.prefetch(&pdf.file, size, pdf.current_page, &pdf.range, tx);
+    let image = Page::from_image(strip.draw(size, pdf.current_page, &pdf.range))?;
+    let origin = (window.cols - cols + 1, 1);
+    let sequence = format!(
+        "{}{}",
+        termion::cursor::Goto(origin.0, origin.1),
+        graphics::beside(&image.data, &image.image, &window, (cols, rows), origin)?
+    );
+    term::write_sequence(sequence.as_bytes())
 }
 
 pub trait Apply<Res> {
@@ -156,99 +641,389 @@ pub trait Apply<Res> {
 
 impl<T: ?Sized, Res> Apply<Res> for T {}
 
+fn bind_pdfium() -> Result<Pdfium> {
+    Ok(Pdfium::new(Pdfium::bind_to_library(
+        Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"),
+    )?))
+}
+
+/// Height pages are rendered at for display when there's no terminal to ask
+/// how big it is
+const RENDER_HEIGHT: u32 = 1920;
+
+/// How much smaller pages are rendered in low-bandwidth mode, for about a
+/// quarter of the pixels to send
+const LOW_BANDWIDTH_SCALE: f64 = 0.5;
+
+/// The part of the window pages are shown in, in pixels. Without the
+/// terminal saying, it's a guess from how many cells there are, and for
+/// half blocks it's only as many as they can show.
+fn window_size() -> (u32, u32) {
+    let window = match Window::query() {
+        Ok(window) => window,
+        Err(_) => return (u32::MAX, RENDER_HEIGHT),
+    };
+    match (graphics::protocol(), layout::render_size(&window)) {
+        (graphics::Protocol::Text, _) | (_, None) => {
+            layout::guessed_size(&window, graphics::cell_width())
+        }
+        (_, Some(size)) => size,
+    }
+}
+
+/// The box pages are rendered to fit for display: where they're shown, so
+/// they're drawn a pixel per pixel, neither blurred by scaling up nor sent
+/// bigger than they're shown. In low-bandwidth mode the terminal scales
+/// them up instead.
+fn render_size() -> (u32, u32) {
+    let (width, height) = window_size();
+    match graphics::low_bandwidth() {
+        true => (
+            (width as f64 * LOW_BANDWIDTH_SCALE) as u32,
+            (height as f64 * LOW_BANDWIDTH_SCALE) as u32,
+        ),
+        false => (width, height),
+    }
+}
+
+/// How many times smaller, each way, the quick render of a page shown
+/// while the full one is on its way is
+const PREVIEW_SCALE: u32 = 4;
+
+/// Render page `p` of `file` for display (or find it in the cache), along
+/// with what else the renderer found out
+fn render_page(file: &str, p: usize, rotation: Rotation) -> Result<(Page, render::Rendered)> {
+    let rotate = rotation.rotate_landscape();
+    let size = render_size();
+    let key = cache::Key::new(file, p, size, rotate);
+    let mut cached = key.as_ref().and_then(cache::get);
+    // Only if it isn't there already; then again once the lock's held, in
+    // case another termpdf was rendering it and has put it there since
+    let mut lock = None;
+    if cached.is_none() {
+        lock = key.as_ref().and_then(|key| key.lock());
+        cached = key.as_ref().and_then(cache::get);
+    }
+    if let Some(entry) = cached {
+        let rendered = render::Rendered {
+            image: None,
+            page: entry.page,
+            length: entry.length,
+            text: entry.text,
+            labels: vec![],
+            title: entry.title,
+        };
+        let image = stamp::watermarked(filter::filtered(entry.image));
+        return Ok((Page::from_image(image)?, rendered));
+    }
+
+    let mut rendered = render::page(file, p, rotate, size)?;
+    let image = rendered
+        .image
+        .take()
+        .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+    if let Some(key) = key {
+        let entry = cache::Entry {
+            image: image.clone(),
+            page: rendered.page,
+            length: rendered.length,
+            text: rendered.text.clone(),
+            title: rendered.title.clone(),
+        };
+        cache::put(key, entry, lock);
+    }
+    let image = stamp::watermarked(filter::filtered(image));
+    Ok((Page::from_image(image)?, rendered))
+}
+
 impl Pdf {
+    fn is_restricted(&self) -> bool {
+        self.range != (0..self.length)
+    }
+
+    /// What the document is called on screen: its title if it has one
+    /// and that's wanted, or else its file name
+    fn name(&self) -> &str {
+        match self.by_title && !self.title.is_empty() {
+            true => &self.title,
+            false => &self.file,
+        }
+    }
+
+    fn status(&self, message: &str) -> Result<()> {
+        let position = if !self.is_restricted() || self.absolute {
+            format!("{}/{}", self.current_page + 1, self.length)
+        } else {
+            format!(
+                "{}/{} of pp. {}-{}",
+                self.current_page - self.range.start + 1,
+                self.range.len(),
+                self.range.start + 1,
+                self.range.end
+            )
+        };
+        let mut line = format!("{}  {}", self.name(), position);
+        if let Some(error) = &self.error {
+            line = format!("{}  {}", line, error);
+        }
+        if self.changed {
+            line = format!("{}  [changed]", line);
+        }
+        if !message.is_empty() {
+            line = format!("{}  {}", line, message);
+        }
+        term::status_line(&line)
+    }
+
+    /// Show page `p`, or a blank page saying why not if it can't be rendered
     fn get_page(&mut self, p: usize) {
-        let pdfium = Pdfium::new(
-            Pdfium::bind_to_library(Pdfium::pdfium_platform_library_name_at_path(
-                "/usr/local/lib/",
-            ))
-            .unwrap(),
+        self.show(p, render_page(&self.file, p, self.rotation));
+    }
+
+    /// Turn to page `p` without waiting for it to be rendered in full, if
+    /// it isn't already: a quick render at a fraction of the resolution is
+    /// shown now, and the full one sent as `Msg::Sharpened` when it's done
+    fn turn_to(&mut self, p: usize, progressive: bool, tx: &Sender<Msg>) {
+        let rotate = self.rotation.rotate_landscape();
+        let size = render_size();
+        let cached = cache::Key::new(&self.file, p, size, rotate)
+            .map(|key| cache::contains(&key))
+            .unwrap_or(false);
+        // Pages drawn as text are coarse enough to render in full anyway
+        if !progressive || cached || graphics::protocol() == graphics::Protocol::Text {
+            return self.get_page(p);
+        }
+        let quick = (size.0 / PREVIEW_SCALE, size.1 / PREVIEW_SCALE);
+        let preview = render::page(&self.file, p, rotate, quick).and_then(|mut rendered| {
+            let image = rendered
+                .image
+                .take()
+                .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+            let image = stamp::watermarked(filter::filtered(image));
+            Ok((Page::from_image(image)?, rendered))
+        });
+        if preview.is_err() {
+            return self.get_page(p);
+        }
+        self.show(p, preview);
+        self.preview = true;
+
+        self.wanted.store(p, Ordering::Relaxed);
+        let (file, rotation, wanted, tx) = (
+            self.file.clone(),
+            self.rotation,
+            self.wanted.clone(),
+            tx.clone(),
         );
+        thread::spawn(move || {
+            if wanted.load(Ordering::Relaxed) != p {
+                return;
+            }
+            let rendered = render_page(&file, p, rotation);
+            let _ = tx.send(Msg::Sharpened(p, Box::new(rendered)));
+        });
+    }
+
+    /// Replace the preview of page `p` with its full render, if it's still
+    /// what's shown. Returns whether it was.
+    fn sharpen(&mut self, p: usize, rendered: Result<(Page, render::Rendered)>) -> bool {
+        if !self.preview || p != self.current_page {
+            return false;
+        }
+        self.show(p, rendered);
+        true
+    }
 
-        let document = pdfium.load_pdf_from_file(&self.file, None).unwrap();
+    /// Show what rendering page `p` came to
+    fn show(&mut self, p: usize, rendered: Result<(Page, render::Rendered)>) {
+        self.preview = false;
+        match rendered {
+            Ok((page, rendered)) => {
+                self.page = self.highlighted(p, page);
+                self.anchor = rendered.text;
+                self.error = None;
+            }
+            Err(e) => {
+                if let Ok(blank) = Page::blank(self.page.size) {
+                    self.page = blank;
+                }
+                self.anchor.clear();
+                self.error = Some(format!("Couldn't render page {}: {}", p + 1, e));
+            }
+        }
+        self.current_page = p;
+    }
 
-        let render_config = PdfRenderConfig::new()
-            .set_target_height(1920)
-            .use_lcd_text_rendering(false)
-            .disable_native_text_rendering(false)
-            .rotate_if_landscape(PdfBitmapRotation::Degrees90, true);
+    /// Load the file again after it has changed on disk, only rendering the
+    /// page on screen, and staying with the same text if pages came or went
+    /// before it. Returns whether anything visible changed.
+    fn reload(&mut self) -> Result<bool> {
+        let current = render::text(&self.file, self.current_page)?;
+        let length = current.length;
+        let p = match current.text == self.anchor {
+            true => current.page,
+            false => text::find_anchor(length, self.length, &self.anchor, self.current_page, |q| {
+                render::text(&self.file, q)
+                    .map(|r| r.text)
+                    .unwrap_or_default()
+            })
+            .unwrap_or(current.page),
+        };
 
-        let page: Page = document
-            .pages()
-            .get(p as u16)
-            // .iter()
-            .apply(|page| {
-                let mut height: u32 = 0;
-                let mut width: u32 = 0;
-                let mut buffer: Cursor<Vec<u8>> = std::io::Cursor::new(vec![]);
-                page.unwrap()
-                    .render_with_config(&render_config)
-                    .expect("Error")
-                    .as_image()
-                    .apply(|x| {
-                        height = x.height();
-                        width = x.width();
-                        x
-                    })
-                    .write_to(&mut buffer, image::ImageFormat::Tiff)
-                    .expect("Error");
-                let p = Page {
-                    data: buffer.into_inner(),
-                    size: (width, height),
-                };
-                return p;
-            });
-        // .collect();
+        let (page, rendered) = render_page(&self.file, p, self.rotation)?;
+        let page = self.highlighted(p, page);
+        let changed =
+            page.image != self.page.image || p != self.current_page || length != self.length;
 
-        self.page = page;
+        self.range = match self.is_restricted() {
+            true => self.range.start.min(length.saturating_sub(1))..self.range.end.min(length),
+            false => 0..length,
+        };
+        self.length = length;
         self.current_page = p;
+        self.page = page;
+        self.anchor = rendered.text;
+        self.title = rendered.title;
+        // They may have changed with the rest of the document
+        self.labels = None;
+        self.error = None;
+        self.changed = false;
+        Ok(changed)
     }
 
-    fn new(file: &String, current_page: Option<usize>) -> Result<Pdf> {
-        let p = match current_page {
-            None => 0,
-            Some(v) => v,
+    /// Page `p` with the `highlight` text marked wherever it is on it
+    fn highlighted(&self, p: usize, page: Page) -> Page {
+        let query = match &self.highlight {
+            Some(query) => query,
+            None => return page,
         };
-        let pdfium = Pdfium::new(Pdfium::bind_to_library(
-            Pdfium::pdfium_platform_library_name_at_path("/usr/local/lib/"),
-        )?);
+        match search::on_page(&self.file, p, query, page.size) {
+            Ok(matches) if !matches.is_empty() => {
+                Page::from_image(search::highlight(&page.image, &matches)).unwrap_or(page)
+            }
+            _ => page,
+        }
+    }
+
+    /// Mark `query` on every page from now on, or stop if None
+    fn set_highlight(&mut self, query: Option<String>) {
+        if query != self.highlight {
+            self.highlight = query;
+            self.get_page(self.current_page);
+        }
+    }
 
-        let document = pdfium.load_pdf_from_file(&file, None)?;
+    /// Only browse `pages` (as for `:summarize`), moving into them if need be
+    fn restrict(&mut self, pages: &str) -> Result<()> {
+        self.range = text::parse_range(pages, self.current_page, self.length)?;
+        let p = self
+            .current_page
+            .clamp(self.range.start, self.range.end - 1);
+        if p != self.current_page {
+            self.get_page(p);
+        }
+        Ok(())
+    }
 
-        let render_config = PdfRenderConfig::new()
-            .set_target_height(1920)
-            .use_lcd_text_rendering(false)
-            .disable_native_text_rendering(false)
-            .rotate_if_landscape(PdfBitmapRotation::Degrees90, true);
+    /// The outline, as edited with `:outline` if it has been
+    fn entries(&self) -> Vec<outline::Entry> {
+        match &self.outline {
+            Some(entries) => entries.clone(),
+            None => outline::read(&self.file).unwrap_or_default(),
+        }
+    }
 
-        let length = document.pages().len() as usize;
+    /// Where this page is, in words that read out well: the file, the part
+    /// of the outline it's in, the page and how far through that is
+    fn position_words(&mut self) -> String {
+        let name = Path::new(&self.file)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut words = vec![name];
 
-        let page: Page = document
-            .pages()
-            .get(p as u16)
-            // .iter()
-            .apply(|page| {
-                let mut height: u32 = 0;
-                let mut width: u32 = 0;
-                let mut buffer: Cursor<Vec<u8>> = std::io::Cursor::new(vec![]);
-                page.unwrap()
-                    .render_with_config(&render_config)
-                    .expect("Error")
-                    .as_image()
-                    .apply(|x| {
-                        height = x.height();
-                        width = x.width();
-                        x
-                    })
-                    .write_to(&mut buffer, image::ImageFormat::Tiff)
-                    .expect("Error");
-                let p = Page {
-                    data: buffer.into_inner(),
-                    size: (width, height),
-                };
-                return p;
-            });
-        // .collect();
+        let entries = self.entries();
+        let p = self.current_page;
+        let mut within: Vec<&outline::Entry> = vec![];
+        for entry in entries.iter().filter(|e| e.page <= p) {
+            within.retain(|e| e.level < entry.level);
+            within.push(entry);
+        }
+        match (within.first(), within.last()) {
+            (Some(top), Some(inner)) if top != inner => {
+                words.push(format!("{}, {}", top.title, inner.title))
+            }
+            (Some(top), _) => words.push(top.title.clone()),
+            _ => {}
+        }
+
+        if self.labels.is_none() {
+            self.labels = render::labels(&self.file).ok();
+        }
+        let label = self
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(p))
+            .filter(|label| !label.is_empty() && **label != (p + 1).to_string());
+        words.push(match label {
+            Some(label) => format!("page {} ({} of {})", label, p + 1, self.length),
+            None => format!("page {} of {}", p + 1, self.length),
+        });
+        let percent = ((p + 1) as f64 * 100.0 / self.length.max(1) as f64).round();
+        words.push(format!("{} percent", percent));
+        words.join(", ")
+    }
+
+    fn page_index(&self, number: usize) -> Result<usize> {
+        let p = match self.absolute || !self.is_restricted() {
+            true => number.checked_sub(1),
+            false => number.checked_sub(1).map(|n| n + self.range.start),
+        };
+        match p {
+            Some(p) if self.range.contains(&p) => Ok(p),
+            _ if self.absolute || !self.is_restricted() => {
+                bail!(
+                    "No page {} (pp. {}-{})",
+                    number,
+                    self.range.start + 1,
+                    self.range.end
+                )
+            }
+            _ => bail!("No page {} (there are {})", number, self.range.len()),
+        }
+    }
+
+    /// The page labelled `label`, among the ones that can be browsed
+    fn label_index(&mut self, label: &str) -> Result<usize> {
+        if self.labels.is_none() {
+            self.labels = Some(render::labels(&self.file)?);
+        }
+        let labels = self.labels.as_deref().unwrap_or_default();
+        match text::find_label(labels, label) {
+            Some(p) if self.range.contains(&p) => Ok(p),
+            Some(p) => bail!(
+                "Page {} (p. {}) isn't in pp. {}-{}",
+                label,
+                p + 1,
+                self.range.start + 1,
+                self.range.end
+            ),
+            None if labels.iter().all(|l| l.is_empty()) => {
+                bail!("Not a command, and the pages have no labels: {}", label)
+            }
+            None => bail!("Not a command or page label: {}", label),
+        }
+    }
+
+    /// Open `file` at `current_page`, or the last page if it's past the end
+    fn new(file: &str, current_page: Option<usize>, rotation: Rotation) -> Result<Pdf> {
+        let (page, rendered) = render_page(file, current_page.unwrap_or_default(), rotation)?;
+        let (p, length, anchor, title) = (
+            rendered.page,
+            rendered.length,
+            rendered.text,
+            rendered.title,
+        );
 
         /*
         let text = document
@@ -257,263 +1032,2407 @@ impl Pdf {
             .map(|page| page.text().expect("Error reading text").to_string())
             .collect(); */
 
-        let text = vec![];
-
         Ok(Pdf {
-            file: file.clone(),
+            file: file.to_string(),
             page,
             current_page: p,
             length,
-            text,
+            rotation,
+            range: 0..length,
+            absolute: false,
+            anchor,
+            error: None,
+            changed: false,
+            jumps: Jumps::default(),
+            labels: None,
+            title,
+            by_title: false,
+            highlight: None,
+            outline: None,
+            preview: false,
+            wanted: Arc::new(AtomicUsize::new(p)),
+            zoomed: None,
+            fitted: p,
+            views: HashMap::new(),
         })
     }
 }
 
+/// Settings taken from the command line.
+#[derive(Debug)]
+struct Options {
+    /// Don't print anything on exit
+    quiet: bool,
+    /// Print the final viewer state as JSON on exit
+    print_state: bool,
+    /// Print the current file's directory on exit instead of its path
+    print_dir: bool,
+    /// Write the current file's directory here on exit, for shell `cd`
+    /// wrappers
+    last_dir_path: Option<String>,
+    /// Open the reading queue instead of files from the command line
+    queue: bool,
+    /// Only browse these pages of each file
+    pages: Option<String>,
+    /// Settings given as flags, which go over the config file's, and still
+    /// do when it's read again
+    settings: Vec<(&'static str, String)>,
+    /// The profile picked with `--profile`
+    profile: Option<String>,
+    /// Commands given with `-c`, to run once the first file is open
+    commands: Vec<String>,
+}
+
+/// What `?` shows. Where a key isn't the one vim (or Vimium, for links)
+/// has, it says why.
+const HELP: &[&str] = &[
+    "j k          next/previous page",
+    "gg G         first/last page, NG page N, Nj Nk N pages on/back",
+    "m' a-z       mark this page/go to the mark",
+    "Ctrl-O       back through the jump list",
+    "Ctrl-P       forward through it: vim's Ctrl-I arrives as Tab",
+    "Tab          table of contents",
+    "F            follow a link: f is already cycling the views",
+    "]s [s } {    next/previous section and chapter",
+    "l h          next/previous document",
+    "/ n N        search, next/previous page with it on",
+    "+ -          zoom in and out, P poster mode, z magnifying glass",
+    "f            cycle fit page, width, height and zoom",
+    "T            zoom in to the text",
+    "c            continuous mode",
+    "v            select part of the page to save or copy",
+    "i p M        dark mode, print preview, colour/grey/1-bit",
+    "w            cycle landscape rotation for this file",
+    "r o R        reload, open elsewhere, reveal in the file manager",
+    "Ctrl-G       say where you are",
+    ":            enter a command",
+    "q            quit",
+];
+
+fn usage() -> ! {
+    eprintln!(
+        "Usage: termpdf [--rotate auto|always|never] [--quiet] [--print-state] [--print-dir]\n               [--last-dir-path FILE] [--queue] [--dwell-log FILE]\n               [--pages RANGE] [--sandbox] [--graphics auto|iterm|kitty|sixel|text]\n               [--image-format auto|png|jpeg|tiff|raw] [--low-bandwidth]\n               [--no-antialias] [--lcd-text] [--cell-pixels WxH]\n               [--profile NAME] [--no-autorefresh] [--screen-reader]\n               [-c COMMAND]... [FILE...]\n       termpdf --daemon\n       termpdf --check\n       termpdf stats [--json] FILE...\n       termpdf dedupe DIR...\n       termpdf gallery [OPTIONS] [DIR...]"
+    );
+    std::process::exit(1);
+}
+
 fn main() {
-    let files: Vec<String> = env::args().skip(1).map(|x| x).collect();
+    if env::args().nth(1).as_deref() == Some("--daemon") {
+        if let Err(e) = render::daemon() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("stats") {
+        let (flags, files): (Vec<String>, Vec<String>) =
+            env::args().skip(2).partition(|a| a.starts_with("--"));
+        if files.is_empty() || flags.iter().any(|f| f != "--json") {
+            usage();
+        }
+        std::process::exit(match stats::run(&files, !flags.is_empty()) {
+            Ok(true) => 0,
+            Ok(false) => 1,
+            Err(e) => {
+                eprintln!("{}", e);
+                1
+            }
+        });
+    }
+    if env::args().nth(1).as_deref() == Some("dedupe") {
+        let paths: Vec<String> = env::args().skip(2).collect();
+        if paths.is_empty() {
+            usage();
+        }
+        if let Err(e) = dedupe::run(&paths) {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("--render-worker") {
+        let sandboxed = env::args().nth(2).as_deref() == Some("--sandbox");
+        std::process::exit(match render::worker(sandboxed) {
+            Ok(_) => 0,
+            Err(_) => 1,
+        });
+    }
 
-    let file = match files.len() {
-        0 => None,
-        _ => Some(files),
+    let mut options = Options {
+        quiet: false,
+        print_state: false,
+        print_dir: false,
+        last_dir_path: None,
+        queue: false,
+        pages: None,
+        settings: vec![],
+        profile: None,
+        commands: vec![],
     };
-    let files = match file {
-        Some(f) => f,
-        None => glob::glob("./*.pdf")
-            .unwrap()
-            .map(|x| {
-                let item = match x {
-                    Ok(v) => v.to_str().expect("Error with file").to_string(),
-                    Err(_) => {
-                        eprintln!("Couldn't find pdf files");
+    let mut files: Vec<String> = vec![];
+    let mut check = false;
+    // The options all work as they do for opening files, apart from the
+    // first, which are the directories to show instead
+    let gallery = env::args().nth(1).as_deref() == Some("gallery");
+    let mut args = env::args().skip(if gallery { 2 } else { 1 });
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--rotate" => match args.next() {
+                Some(r) => options.settings.push(("rotate", r)),
+                None => usage(),
+            },
+            "--quiet" | "-q" => options.quiet = true,
+            "--screen-reader" => options.settings.push(("screen_reader", "true".into())),
+            "--print-state" => options.print_state = true,
+            "--print-dir" => options.print_dir = true,
+            "--queue" => options.queue = true,
+            "--sandbox" => options
+                .settings
+                .push(("render_isolation", "sandbox".into())),
+            "--check" => check = true,
+            "--graphics" => match args.next() {
+                Some(p) => options.settings.push(("graphics", p)),
+                None => usage(),
+            },
+            "--image-format" => match args.next() {
+                Some(f) => options.settings.push(("image_format", f)),
+                None => usage(),
+            },
+            "--no-autorefresh" => options.settings.push(("autorefresh", "false".into())),
+            "--low-bandwidth" => options.settings.push(("low_bandwidth", "true".into())),
+            "--cell-pixels" => match args.next() {
+                Some(size) => options.settings.push(("cell_pixels", size)),
+                None => usage(),
+            },
+            "--no-antialias" => options.settings.push(("antialias", "false".into())),
+            "--lcd-text" => options.settings.push(("lcd_text", "true".into())),
+            "--dwell-log" => match args.next() {
+                Some(p) => options.settings.push(("dwell_log", p)),
+                None => usage(),
+            },
+            "--profile" => match args.next() {
+                Some(p) => options.profile = Some(p),
+                None => usage(),
+            },
+            "-c" => match args.next() {
+                Some(line) => match command::parse(&line) {
+                    Ok(_) => options.commands.push(line),
+                    Err(e) => {
+                        eprintln!("-c {}: {}", line, e);
                         std::process::exit(1);
                     }
-                };
-                item
+                },
+                None => usage(),
+            },
+            "--pages" => match args.next() {
+                Some(p) => options.pages = Some(p),
+                None => usage(),
+            },
+            "--last-dir-path" => match args.next() {
+                Some(p) => options.last_dir_path = Some(p),
+                None => usage(),
+            },
+            _ => files.push(arg),
+        }
+    }
+    let mut config = match Config::load(options.profile.as_deref()) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    for (key, value) in &options.settings {
+        if config.set(key, value).is_err() {
+            usage();
+        }
+    }
+
+    if config.render_isolation == render::Isolation::Sandbox {
+        if let Err(e) = sandbox::check() {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    layout::configure(
+        config.cell_pixels,
+        config.cell_aspect,
+        config.margin,
+        config.center,
+    );
+    layout::configure_strip(config.thumbnail_strip);
+    if check {
+        check::run(&config);
+        std::process::exit(0);
+    }
+    render::isolate(config.render_isolation);
+    render::configure(config.matte, config.render_quality);
+    render::set_dark(config.dark_mode);
+    graphics::configure(&config, probe::load().protocol);
+    stamp::configure(&config);
+    filter::configure(config.filter);
+    cache::configure(config.cache_size);
+
+    if options.queue {
+        match Queue::load() {
+            Ok(queue) if !queue.existing().is_empty() => files = queue.existing(),
+            Ok(_) => {
+                eprintln!("The reading queue is empty");
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if gallery {
+        let dirs = match files.is_empty() {
+            true => vec![".".to_string()],
+            false => files,
+        };
+        let (files, opened) = match gallery::run(&dirs, config.title_names) {
+            Ok(Some(picked)) => picked,
+            Ok(None) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        };
+        let mut files = FileList::new(files);
+        files.current_file = opened;
+        match runmulti(files, &options, config) {
+            Ok(_) => std::process::exit(0),
+            Err(e) => {
+                eprintln!("I encountered an erorr! {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let files = match files.len() {
+        0 => glob::glob("./*.pdf")
+            .unwrap()
+            .map(|x| match x {
+                Ok(v) => v.to_str().expect("Error with file").to_string(),
+                Err(_) => {
+                    eprintln!("Couldn't find pdf files");
+                    std::process::exit(1);
+                }
             })
             .collect(),
+        _ => files,
     };
 
-    if files.len() == 0 {
+    if files.is_empty() {
         eprintln!("Couldn't find pdf files");
         std::process::exit(1);
     };
 
-    let files = FileList {
-        files,
-        current_file: 0,
-    };
-    let res = runmulti(files);
+    let files = FileList::new(files);
+    let res = runmulti(files, &options, config);
     match res {
         Ok(_) => std::process::exit(0),
         Err(e) => {
-            eprintln!("I encountered an erorr! {}", e.to_string());
+            eprintln!("I encountered an erorr! {}", e);
             std::process::exit(1);
         }
     };
 }
 
-fn runmulti(mut files: FileList) -> anyhow::Result<()> {
+/// The absolute directory containing `file`
+fn directory_of(file: &str) -> String {
+    let path = Path::new(file);
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_string_lossy().to_string(),
+        _ => ".".to_string(),
+    }
+}
+
+fn runmulti(mut files: FileList, options: &Options, config: Config) -> anyhow::Result<()> {
+    let started = Instant::now();
+    let mut rotation = config.rotation;
     let file = files.current();
-    let file2 = file.clone();
 
-    let mut pdf = match Pdf::new(&file.clone(), None) {
+    // Rotation is a per-document preference, so remember it when moving
+    // between files
+    let mut rotations: HashMap<String, Rotation> = HashMap::new();
+    // Whether page numbers are physical, which sticks across files too
+    let mut absolute = false;
+
+    let mut pdf = match Pdf::new(&file, None, rotation) {
         Ok(v) => v,
         Err(_) => bail!("Couldn't load pdf or not a valid pdf file"),
     };
 
     let (tx, rx) = mpsc::channel();
-    let tx1 = tx.clone();
-    thread::spawn(move || {
-        let p = Path::new(&file2);
-        let (tx2, rx2) = std::sync::mpsc::channel();
-        let mut watcher = match new_debouncer(Duration::from_secs(2), None, tx2) {
-            Ok(v) => v,
-            Err(e) => bail!("{:?}", e.kind),
-        };
-        watcher
-            .watcher()
-            .watch(p.as_ref(), RecursiveMode::Recursive)
-            .expect("Couldn't create file watcher");
-
-        for res in rx2 {
-            match res {
-                Ok(_) => tx1
-                    .send(Msg::Refresh)
-                    .expect("Couldn't send REFRESH command"),
-                _ => {}
-            }
+    let mut watcher = Watcher::new(tx.clone(), Duration::from_secs_f64(config.poll_interval));
+    let mut message = String::new();
+    let mut session = Session {
+        tx: tx.clone(),
+        jobs: Jobs::default(),
+        input: Input::spawn(tx),
+        tags: Tags::load().unwrap_or_else(|e| {
+            message = e.to_string();
+            Tags::default()
+        }),
+        marks: Marks::load().unwrap_or_else(|e| {
+            message = e.to_string();
+            Marks::default()
+        }),
+        queue: Queue::load().unwrap_or_else(|e| {
+            message = e.to_string();
+            Queue::default()
+        }),
+        strip: Strip::default(),
+        dwell: Dwell::default(),
+        review: Review::load().unwrap_or_else(|e| {
+            message = e.to_string();
+            Review::default()
+        }),
+        review_run: None,
+        search: None,
+        hlsearch: config.hlsearch,
+        cleared: false,
+        spoken: None,
+        view: config.view,
+        custom_zoom: 2.0,
+        profile: options.profile.clone(),
+        commands: options.commands.clone(),
+        autorefresh: HashMap::new(),
+        zoom_per_page: config.zoom_per_page,
+        config,
+    };
+    // Whether a file is still open at the end, which it isn't once the last
+    // one has gone to the trash
+    let mut open = true;
+    loop {
+        // Follow the current file, which changes as we move between files or
+        // rename them
+        match watcher.watch(&pdf.file) {
+            Ok(Some(why)) => message = why,
+            Ok(None) => {}
+            Err(e) => message = e.to_string(),
         }
-        Ok(())
-    });
-    thread::spawn(move || {
-        let stdin = stdin();
-        for c in stdin.keys() {
-            match c {
-                key => match key {
-                    Ok(v) => tx.send(v.into()).expect("Couldn't send key press"),
-                    _ => {}
-                },
-            };
+        if let Some(pages) = &options.pages {
+            if let Err(e) = pdf.restrict(pages) {
+                message = e.to_string();
+            }
         }
-    });
-    loop {
-        let res = browser(&mut pdf, &rx); //, &refresh);
+        pdf.absolute = absolute;
+        pdf.by_title = session.config.title_names;
+        let res = browser(&mut pdf, &rx, &mut session, &message); //, &refresh);
+        message.clear();
+        absolute = pdf.absolute;
         match res.expect("Error in browser") {
-            Refersh::Done => {
-                println!("");
-                println!("{}", pdf.file);
-                return Ok(());
-            }
-            Refersh::Oker => {
-                let p = pdf.current_page;
-                pdf = Pdf::new(&file.clone().to_owned(), Some(p)).expect("Couldn't refresh file");
-            }
+            Refersh::Done => break,
             Refersh::Next => {
+                rotations.insert(pdf.file.clone(), pdf.rotation);
                 files.next();
-                let file = files.current();
-                pdf = Pdf::new(&file.clone().to_owned(), None).expect("Couldn't refresh file");
+                let opened = open_file(&mut files, None, true, &rotations, rotation, &mut message);
+                pdf = opened.unwrap_or_else(|| stay(&mut files, pdf));
             }
             Refersh::Previous => {
+                rotations.insert(pdf.file.clone(), pdf.rotation);
                 files.prev();
-                let file = files.current();
-                pdf = Pdf::new(&file.clone().to_owned(), None).expect("Couldn't refresh file");
+                let opened = open_file(&mut files, None, false, &rotations, rotation, &mut message);
+                pdf = opened.unwrap_or_else(|| stay(&mut files, pdf));
             }
-        }
-    }
-    // Ok(())
-}
-
-fn run(file: String) -> anyhow::Result<()> {
-    let file2 = file.clone();
-    let mut pdf = match Pdf::new(&file.clone(), None) {
-        Ok(v) => v,
-        Err(_) => bail!("Couldn't load pdf or not a valid pdf file"),
-    };
-
-    let (tx, rx) = mpsc::channel();
-    let tx1 = tx.clone();
-    thread::spawn(move || {
-        let p = Path::new(&file2);
-        let (tx2, rx2) = std::sync::mpsc::channel();
-        let mut watcher = match new_debouncer(Duration::from_secs(2), None, tx2) {
-            Ok(v) => v,
-            Err(e) => bail!("{:?}", e.kind),
-        };
-        watcher
-            .watcher()
-            .watch(p.as_ref(), RecursiveMode::Recursive)
-            .expect("Couldn't create file watcher");
-
-        for res in rx2 {
-            match res {
-                Ok(_) => tx1
-                    .send(Msg::Refresh)
-                    .expect("Couldn't send REFRESH command"),
-                _ => {}
+            Refersh::PastEnd if files.is_last() => message = "End of the last file".to_string(),
+            Refersh::PastEnd => {
+                rotations.insert(pdf.file.clone(), pdf.rotation);
+                files.next();
+                let opened = open_file(&mut files, None, true, &rotations, rotation, &mut message);
+                pdf = opened.unwrap_or_else(|| stay(&mut files, pdf));
             }
-        }
-        Ok(())
-    });
-    thread::spawn(move || {
-        let stdin = stdin();
-        for c in stdin.keys() {
-            match c {
-                key => match key {
-                    Ok(v) => tx.send(v.into()).expect("Couldn't send key press"),
-                    _ => {}
-                },
-            };
-        }
-    });
-    loop {
-        let res = browser(&mut pdf, &rx); //, &refresh);
-        match res.expect("Error in browser") {
-            Refersh::Done => {
-                println!("{}", pdf.file);
-                return Ok(());
+            Refersh::PastStart if files.is_first() => {
+                message = "Start of the first file".to_string()
             }
-            Refersh::Oker => {
-                let p = pdf.current_page;
-                pdf = Pdf::new(&file.clone().to_owned(), Some(p)).expect("Couldn't refresh file");
+            Refersh::PastStart => {
+                rotations.insert(pdf.file.clone(), pdf.rotation);
+                files.prev();
+                let last = Some(usize::MAX);
+                let opened = open_file(&mut files, last, false, &rotations, rotation, &mut message);
+                pdf = opened.unwrap_or_else(|| stay(&mut files, pdf));
+            }
+            Refersh::Rename(target) => {
+                // Worked out while the file is still there to be found
+                let key = state::key(&pdf.file);
+                match rename_file(&pdf.file, &target) {
+                    Ok(file) => {
+                        session.tags.rename(&key, &file);
+                        if let Err(e) = session.tags.save() {
+                            message = e.to_string();
+                        }
+                        session.marks.rename(&key, &file);
+                        if let Err(e) = session.marks.save() {
+                            message = e.to_string();
+                        }
+                        files.rename_current(&file);
+                        if let Some(rotation) = rotations.remove(&pdf.file) {
+                            rotations.insert(file.clone(), rotation);
+                        }
+                        if message.is_empty() {
+                            message = format!("Renamed to {}", file);
+                        }
+                        pdf.file = file;
+                    }
+                    Err(e) => message = format!("Couldn't rename: {}", e),
+                }
+            }
+            Refersh::Open(file, page) => {
+                rotations.insert(pdf.file.clone(), pdf.rotation);
+                files.open(&file);
+                let file = files.current();
+                let rotation = *rotations.get(&file).unwrap_or(&rotation);
+                match Pdf::new(&file, page, rotation) {
+                    Ok(p) => pdf = p,
+                    Err(e) => message = format!("Couldn't open {}: {}", file, e),
+                }
+                if let Some(run) = &session.review_run {
+                    message = run.status();
+                }
+            }
+            Refersh::Filter(query) => {
+                let current = pdf.file.clone();
+                if !files.filter(|f| matches_filter(f, &query, &session.tags)) {
+                    message = format!("No files match '{}'", query);
+                    continue;
+                }
+                message = match query.is_empty() {
+                    true => format!("Showing all {} files", files.files.len()),
+                    false => format!("{} files match '{}'", files.files.len(), query),
+                };
+                let file = files.current();
+                if file != current {
+                    rotations.insert(pdf.file.clone(), pdf.rotation);
+                    let opened =
+                        open_file(&mut files, None, true, &rotations, rotation, &mut message);
+                    pdf = opened.unwrap_or_else(|| stay(&mut files, pdf));
+                }
+            }
+            Refersh::Reconfigure(switch) => {
+                let profile = switch.clone().unwrap_or_else(|| session.profile.clone());
+                let reread = Config::load(profile.as_deref()).and_then(|mut config| {
+                    for (key, value) in &options.settings {
+                        config.set(key, value)?;
+                    }
+                    Ok(config)
+                });
+                match reread {
+                    Ok(config) => {
+                        rotation = config.rotation;
+                        reconfigure(&mut session, config);
+                        pdf.get_page(pdf.current_page);
+                        message = match switch {
+                            Some(_) => format!("profile: {}", profile.as_deref().unwrap_or("none")),
+                            None => "Reloaded the config".to_string(),
+                        };
+                        session.profile = profile;
+                    }
+                    Err(e) if switch.is_some() => message = e.to_string(),
+                    Err(e) => message = format!("Couldn't reload the config: {}", e),
+                }
+            }
+            Refersh::Trash => {
+                let key = state::key(&pdf.file);
+                if let Err(e) = trash::delete(&pdf.file) {
+                    message = format!("Couldn't move {} to the trash: {}", pdf.file, e);
+                    continue;
+                }
+                session.tags.forget(&key);
+                let _ = session.tags.save();
+                session.marks.forget(&key);
+                let _ = session.marks.save();
+                if !files.remove_current() {
+                    open = false;
+                    break;
+                }
+                match open_file(&mut files, None, true, &rotations, rotation, &mut message) {
+                    Some(opened) => pdf = opened,
+                    // Nothing left that opens
+                    None => {
+                        open = false;
+                        break;
+                    }
+                }
             }
-            _ => {}
         }
     }
-    // Ok(())
+    term::reset_scroll_region()?;
+    graphics::finish();
+    session.dwell.stop();
+    if let Some(path) = &session.config.dwell_log {
+        session.dwell.export(path, session.config.backups)?;
+    }
+    // There's no file or page to say anything about
+    if !open {
+        return Ok(());
+    }
+    let dir = directory_of(&pdf.file);
+    if let Some(path) = &options.last_dir_path {
+        std::fs::write(path, &dir)?;
+    }
+    if options.print_state {
+        let state = serde_json::json!({
+            "file": pdf.file,
+            "page": pdf.current_page + 1,
+            "pages": pdf.length,
+            "duration": started.elapsed().as_secs_f64(),
+        });
+        println!("{}", state);
+    } else if options.print_dir {
+        println!("{}", dir);
+    } else if !options.quiet {
+        // Off the status line, so the name has a line to itself
+        println!();
+        println!("{}", pdf.file);
+    }
+    Ok(())
+}
+
+/// Open the current file of `files` at `page`, or if it won't open, the
+/// next one that does going `forward` (or back), saying which couldn't be
+/// opened in `message`. None if none of them will, leaving `files` on the
+/// last one tried.
+fn open_file(
+    files: &mut FileList,
+    page: Option<usize>,
+    forward: bool,
+    rotations: &HashMap<String, Rotation>,
+    rotation: Rotation,
+    message: &mut String,
+) -> Option<Pdf> {
+    loop {
+        let file = files.current();
+        let rotation = *rotations.get(&file).unwrap_or(&rotation);
+        match Pdf::new(&file, page, rotation) {
+            Ok(pdf) => return Some(pdf),
+            Err(e) => *message = format!("Couldn't open {}: {}", file, e),
+        }
+        match forward {
+            true if !files.is_last() => files.next(),
+            false if !files.is_first() => files.prev(),
+            _ => return None,
+        }
+    }
+}
+
+/// Keep reading `pdf` when none of the files it was leaving for would open
+fn stay(files: &mut FileList, pdf: Pdf) -> Pdf {
+    files.open(&pdf.file);
+    pdf
 }
 
 enum Refersh {
-    Oker,
     Done,
     Next,
     Previous,
+    /// `j` on the last page: on to the start of the next file
+    PastEnd,
+    /// `k` on the first page: back to the end of the previous file
+    PastStart,
+    Trash,
+    /// Rename the file to this path
+    Rename(PathBuf),
+    Filter(String),
+    Open(String, Option<usize>),
+    /// The config file changed, so read it again, with the profile
+    /// `:profile` switched to if it's given (None for none)
+    Reconfigure(Option<Option<String>>),
 }
 
-fn browser(pdf: &mut Pdf, rx: &Receiver<Msg>) -> anyhow::Result<Refersh> {
-    let mut stdout = stdout().into_raw_mode()?;
+/// What the browser works with besides the open document
+struct Session {
+    config: Config,
+    /// For background jobs to report back on
+    tx: Sender<Msg>,
+    jobs: Jobs,
+    input: Input,
+    tags: Tags,
+    marks: Marks,
+    queue: Queue,
+    strip: Strip,
+    dwell: Dwell,
+    review: Review,
+    /// The review under way, if there is one
+    review_run: Option<ReviewRun>,
+    /// What `/` or `:find` looked for last
+    search: Option<String>,
+    /// Whether to keep it highlighted on every page
+    hlsearch: bool,
+    /// Whether `:noh` has cleared the highlighting until the next search
+    cleared: bool,
+    /// The page whose text was last written out in screen-reader mode
+    spoken: Option<(String, usize)>,
+    /// How big pages are shown, which sticks across files
+    view: View,
+    /// The zoom the view cycles round to, the last one zoomed in to
+    custom_zoom: f64,
+    /// The profile the config is read with, if there is one
+    profile: Option<String>,
+    /// The `-c` commands, until they've been run
+    commands: Vec<String>,
+    /// Documents `:set autorefresh` has been used on, and what it was set to
+    autorefresh: HashMap<String, AutoRefresh>,
+    /// Whether each page is shown as it was zoomed in on, rather than the
+    /// zoom following from page to page
+    zoom_per_page: bool,
+}
+
+impl Session {
+    /// The text to mark on every page, if any
+    fn highlight(&self) -> Option<String> {
+        self.search
+            .clone()
+            .filter(|_| self.hlsearch && !self.cleared)
+    }
+
+    /// What's done when `file` changes on disk
+    fn autorefresh(&self, file: &str) -> AutoRefresh {
+        match self.autorefresh.get(file) {
+            Some(&a) => a,
+            None => self.config.autorefresh,
+        }
+    }
+}
+
+/// Put `config`, read again after the file changed, into effect as far as
+/// it can be while reading: how pages are laid out, rendered and filtered,
+/// and whatever's only looked at when it's wanted. Settings that haven't
+/// changed stay as they've been switched to since, with `i` and the like.
+/// How pages are sent to the terminal, and where they're rendered, wait for
+/// the next start.
+fn reconfigure(session: &mut Session, config: Config) {
+    let old = &session.config;
+    layout::configure(
+        config.cell_pixels,
+        config.cell_aspect,
+        config.margin,
+        config.center,
+    );
+    layout::configure_strip(config.thumbnail_strip);
+    render::configure(config.matte, config.render_quality);
+    if config.filter != old.filter {
+        filter::configure(config.filter);
+    }
+    if config.dark_mode != old.dark_mode {
+        render::set_dark(config.dark_mode);
+    }
+    if config.grayscale != old.grayscale {
+        graphics::set_tone(config.grayscale);
+    }
+    if config.view != old.view {
+        session.view = config.view;
+    }
+    if config.hlsearch != old.hlsearch {
+        session.hlsearch = config.hlsearch;
+    }
+    if config.zoom_per_page != old.zoom_per_page {
+        session.zoom_per_page = config.zoom_per_page;
+    }
+    session.config = config;
+}
+
+/// A name for `file` made up from its metadata and first page
+fn suggest_name(file: &str) -> Result<String> {
+    let (metadata, first_page) = metadata::naming(file)?;
+    match metadata::suggest(&metadata, &first_page) {
+        Some(name) => Ok(name),
+        None => bail!("Couldn't work out a title for this document"),
+    }
+}
+
+/// Where `file` goes when it's renamed according to `template`. Names
+/// without a directory stay next to the original file.
+fn rename_target(file: &str, template: &str) -> Result<PathBuf> {
+    let (metadata, _) = metadata::naming(file)?;
+    let mut name = metadata::expand(template, &metadata, file)?;
+    if Path::new(&name).extension().is_none() {
+        name.push_str(".pdf");
+    }
+    Ok(match name.contains('/') {
+        true => PathBuf::from(name),
+        false => Path::new(file).with_file_name(name),
+    })
+}
+
+/// Rename `file` to `target`, returning the new path
+fn rename_file(file: &str, target: &Path) -> Result<String> {
+    if target.exists() {
+        bail!("{} already exists", target.display());
+    }
+    std::fs::rename(file, target)?;
+    Ok(target.to_string_lossy().to_string())
+}
+
+/// Show the current file in the configured file manager
+fn reveal(
+    pdf: &Pdf,
+    config: &Config,
+    input: &Input,
+    stdout: &mut RawTerminal<Stdout>,
+) -> Result<()> {
+    let path = Path::new(&pdf.file);
+    let file = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    let file = file.to_string_lossy();
+    let dir = directory_of(&pdf.file);
+    let mut command = external::command(&config.file_manager, &[("file", &file), ("dir", &dir)])?;
+
+    if config.file_manager_in_terminal {
+        input.pause();
+        term::reset_scroll_region()?;
+        stdout.suspend_raw_mode()?;
+        let status = command.status();
+        stdout.activate_raw_mode()?;
+        input.resume();
+        let status = status?;
+        if !status.success() {
+            bail!("file manager exited with {}", status);
+        }
+    } else {
+        let mut child = command
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+        thread::spawn(move || child.wait());
+    }
+    Ok(())
+}
+
+/// Open `uri` with `browser_command`
+fn browse(uri: &str, config: &Config) -> Result<()> {
+    let mut child = external::command(&config.browser_command, &[("url", uri)])?
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Open `file` in whatever the system opens PDFs with, leaving it running
+fn open_elsewhere(file: &str) -> Result<()> {
+    let opener = match cfg!(target_os = "macos") {
+        true => "open",
+        false => "xdg-open",
+    };
+    let mut child = Command::new(opener)
+        .arg(file)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Hand `words` to `announce_command`, to be spoken, say
+fn announce(words: &str, config: &Config) -> Result<()> {
+    let mut child = external::command(&config.announce_command, &[("text", words)])?
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+/// Save any change to the tags, describing the current file's tags (or
+/// what went wrong) for the status line
+fn save_tags(tags: &Tags, file: &str) -> String {
+    match tags.save() {
+        Ok(_) => match tags.get(file) {
+            t if t.is_empty() => "no tags".to_string(),
+            t => format!("tags: {}", t.join(", ")),
+        },
+        Err(e) => e.to_string(),
+    }
+}
+
+/// The selected part of the current page as a PNG, rendered sharper than
+/// it is on screen. If it's being exported, rather than read by a program,
+/// it has the watermark and (with `stamp_exports`) where it's from.
+fn crop_selection(
+    pdf: &Pdf,
+    selection: &Selection,
+    config: &Config,
+    export: bool,
+) -> Result<Vec<u8>> {
+    // Not made smaller for a slow connection, as it isn't being sent
+    let (width, height) = window_size();
+    let scale = |n: u32| (n as f32 * config.screenshot_scale) as u32;
+    let rotate = pdf.rotation.rotate_landscape();
+    let image = render::page(
+        &pdf.file,
+        pdf.current_page,
+        rotate,
+        (scale(width), scale(height)),
+    )?
+    .image
+    .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+
+    let mut image = selection.crop(&image);
+    if export {
+        image = stamp::watermarked(image);
+    }
+    if export && config.stamp_exports {
+        let name = Path::new(&pdf.file)
+            .file_name()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_default();
+        image = stamp::stamp(&image, &format!("{}, p. {}", name, pdf.current_page + 1))?;
+    }
+    let mut png: Cursor<Vec<u8>> = std::io::Cursor::new(vec![]);
+    image.write_to(&mut png, image::ImageFormat::Png)?;
+    Ok(png.into_inner())
+}
+
+/// Most pixels high the page is rendered for the magnifier, which at its
+/// highest zoom would otherwise be hundreds of megabytes
+const MAGNIFIED_HEIGHT: u32 = 6000;
+
+/// The current page rendered `zoom` times the size it's shown at, for the
+/// magnifier, or as near as the renderer's limit on pixels allows for a
+/// very wide page
+fn magnified(pdf: &Pdf, zoom: f64) -> Result<DynamicImage> {
+    let (width, height) = pdf.page.size;
+    let pixels = (width as f64 * height as f64).max(1.0);
+    let zoom = zoom
+        .min(MAGNIFIED_HEIGHT as f64 / height.max(1) as f64)
+        .min((render::MAX_PIXELS / pixels).sqrt());
+    let size = ((width as f64 * zoom) as u32, (height as f64 * zoom) as u32);
+    let rotate = pdf.rotation.rotate_landscape();
+    let image = render::page(&pdf.file, pdf.current_page, rotate, size)?
+        .image
+        .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+    Ok(stamp::watermarked(filter::filtered(image)))
+}
+
+/// The current page zoomed in as `view` has it, looking at `spot`, or None
+/// if it's all in view anyway
+fn poster(pdf: &Pdf, view: View, spot: (f64, f64)) -> Result<Option<Poster>> {
+    if view == View::Page {
+        return Ok(None);
+    }
+    let window = render_size();
+    let small = [window.0, window.1].map(|side| (side as f64 * poster::OVERVIEW) as u32);
+    let overview = render::page(&pdf.file, pdf.current_page, false, (small[0], small[1]))?
+        .image
+        .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+    // Going by the page the right way up, as it's shown zoomed in
+    let zoom = view.zoom((overview.width(), overview.height()), window);
+    Ok((zoom > 1.01).then(|| Poster::new(filter::filtered(overview), window, zoom, spot)))
+}
+
+/// What's shown zoomed in: the part of the page in view, rendered just for
+/// it, with the overview over it
+fn poster_view(pdf: &Pdf, poster: &Poster) -> Result<DynamicImage> {
+    let tile = render::tile(
+        &pdf.file,
+        pdf.current_page,
+        poster.view(),
+        poster.tile_size(),
+    )?
+    .image
+    .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+    Ok(poster.draw(&stamp::watermarked(filter::filtered(tile))))
+}
+
+/// Page `p` rendered as wide as continuous mode shows it, `width` pixels
+fn scroll_page(pdf: &Pdf, p: usize, width: u32) -> Result<DynamicImage> {
+    let image = render::page(&pdf.file, p, false, (width, u32::MAX))?
+        .image
+        .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+    Ok(stamp::watermarked(filter::filtered(image)))
+}
+
+/// Show the current page zoomed in as `view` has it, looking at `spot`.
+/// Returns false, leaving the page for the caller to show, when the view
+/// has it all in view.
+fn show_view(pdf: &mut Pdf, view: View, spot: (f64, f64)) -> Result<bool> {
+    let shown = poster(pdf, view, spot).and_then(|zoomed| match zoomed {
+        Some(zoomed) => Ok(Some((poster_view(pdf, &zoomed)?, zoomed))),
+        None => Ok(None),
+    });
+    pdf.zoomed = None;
+    pdf.fitted = pdf.current_page;
+    match shown {
+        Ok(Some((image, zoomed))) => {
+            Page::from_image(image)?.display()?;
+            term::status_line(&zoomed.help(&view.name()))?;
+            pdf.zoomed = Some(zoomed);
+        }
+        Ok(None) => return Ok(false),
+        Err(e) => {
+            pdf.page.display()?;
+            pdf.status(&format!("Couldn't zoom in: {}", e))?;
+        }
+    }
+    Ok(true)
+}
+
+/// Zoom in on the page just turned to again, unless the view has all of it
+/// in view: from its start if it's further on, or its end if it's back, at
+/// the same place across. With zoom kept `per_page`, a page seen before is
+/// shown as it was left instead.
+fn refit(
+    pdf: &mut Pdf,
+    view: &mut View,
+    per_page: bool,
+    forward: bool,
+    deferred: &mut VecDeque<Msg>,
+) {
+    let zoomed = pdf.zoomed.take();
+    let left = std::mem::replace(&mut pdf.fitted, pdf.current_page);
+    if per_page {
+        let spot = zoomed.as_ref().map(|zoomed| (zoomed.x, zoomed.y));
+        pdf.views.insert(left, (*view, spot));
+        if let Some(&(was, spot)) = pdf.views.get(&pdf.current_page) {
+            *view = was;
+            if let Some(spot) = spot {
+                deferred.push_back(Msg::Fit(spot));
+            }
+            return;
+        }
+    }
+    let x = zoomed.map_or(0.5, |zoomed| zoomed.x);
+    let spot = match (*view, forward) {
+        (View::Page, _) => return,
+        (View::Height, true) => (0.0, 0.5),
+        (View::Height, false) => (1.0, 0.5),
+        (_, true) => (x, 0.0),
+        (_, false) => (x, 1.0),
+    };
+    deferred.push_back(Msg::Fit(spot));
+}
+
+/// The page `:compare` was given to compare the current one with: a page
+/// of this document, counted as the status line does, or of another one,
+/// the same page unless one is given
+fn comparison(pdf: &Pdf, args: &str) -> Result<Comparison> {
+    let (file, p) = match args.parse::<usize>() {
+        Ok(number) => (pdf.file.clone(), pdf.page_index(number)?),
+        Err(_) => match args.rsplit_once(char::is_whitespace) {
+            Some((file, number)) if number.parse::<usize>().is_ok() => {
+                let number: usize = number.parse()?;
+                match number.checked_sub(1) {
+                    Some(p) => (file.trim().to_string(), p),
+                    None => bail!("No page 0"),
+                }
+            }
+            _ => (args.to_string(), pdf.current_page),
+        },
+    };
+    let rotate = pdf.rotation.rotate_landscape();
+    let rendered = render::page(&file, p, rotate, render_size())?;
+    if rendered.page != p {
+        bail!("{} only has {} pages", file, rendered.length);
+    }
+    let image = rendered
+        .image
+        .ok_or_else(|| anyhow::anyhow!("nothing was rendered"))?;
+    let label = match file == pdf.file {
+        true => format!("p. {}", p + 1),
+        false => format!("{} p. {}", file, p + 1),
+    };
+    let image = stamp::watermarked(filter::filtered(image));
+    Ok(Comparison::new(&image, pdf.page.size, &label))
+}
+
+/// Save (or copy) the selected part of the current page. Returns a message
+/// saying where it went.
+fn save_selection(
+    pdf: &Pdf,
+    selection: &Selection,
+    config: &Config,
+    action: select::Action,
+) -> Result<String> {
+    let png = crop_selection(pdf, selection, config, true)?;
+
+    match action {
+        select::Action::Save => {
+            let stem = Path::new(&pdf.file)
+                .file_stem()
+                .map(|s| s.to_string_lossy().to_string())
+                .unwrap_or_else(|| "page".to_string());
+            let name = format!("{}-p{}-{}.png", stem, pdf.current_page + 1, review::now());
+            let path = Path::new(&config.screenshot_dir).join(name);
+            std::fs::write(&path, png)?;
+            Ok(format!("Saved {}", path.display()))
+        }
+        select::Action::Copy => {
+            clipboard::copy_image(&png, config)?;
+            Ok("Copied the selection to the clipboard".to_string())
+        }
+    }
+}
+
+/// Show the output of a command started with `external::stream` until the
+/// user closes the pager, then stop it. Returns the messages that arrived
+/// meanwhile.
+fn show_output(mut child: Child, title: &str, rx: &Receiver<Msg>) -> Result<Vec<Msg>> {
+    let mut pager = Pager::streaming(title);
+    let mut later = vec![];
+    let result = pager.run(rx, &mut later);
+    let _ = child.kill();
+    let _ = child.wait();
+    result.map(|_| later)
+}
 
+/// How long matches are framed for after a search lands on a page
+const FLASH: Duration = Duration::from_millis(500);
+
+/// Show the page with `matches` framed, so the eye can find them on a busy
+/// page, and take the frames off again after `FLASH`
+fn flash(pdf: &Pdf, matches: &[search::Match], tx: &Sender<Msg>) -> Result<()> {
+    let highlighted = search::highlight(&pdf.page.image, matches);
+    Page::from_image(search::outline(&highlighted, matches))?.display()?;
+    let (tx, p) = (tx.clone(), pdf.current_page);
+    thread::spawn(move || {
+        thread::sleep(FLASH);
+        let _ = tx.send(Msg::Unflash(p));
+    });
+    Ok(())
+}
+
+/// What the status line says about the matches on a page
+fn found(n: usize, note: &str) -> String {
+    let plural = if n == 1 { "match" } else { "matches" };
+    format!("{} {} on this page{}", n, plural, note)
+}
+
+fn redraw(pdf: &Pdf, stdout: &mut RawTerminal<Stdout>, message: &str) -> Result<()> {
     write!(
         stdout,
         "{}{}",
-        termion::cursor::Restore,
-        termion::clear::CurrentLine
+        termion::cursor::Goto(1, 1),
+        termion::clear::All,
     )?;
+    term::set_scroll_region(termion::terminal_size()?.1)?;
+
+    pdf.page.display()?;
+    pdf.status(message)
+}
+
+/// Fold page turns (and reloads, and resizes) that arrive within `wait` of
+/// `msg` into it, so a burst of them costs one redraw. Anything else that
+/// turns up ends the burst and is handled next.
+fn coalesce(msg: Msg, rx: &Receiver<Msg>, deferred: &mut VecDeque<Msg>, wait: Duration) -> Msg {
+    let mut msg = match msg {
+        Msg::Pages(_) | Msg::Refresh | Msg::Resize if deferred.is_empty() => msg,
+        msg => return msg,
+    };
+    let until = Instant::now() + wait;
+    loop {
+        let next = match rx.try_recv() {
+            Ok(next) => next,
+            Err(mpsc::TryRecvError::Empty) => {
+                match rx.recv_timeout(until.saturating_duration_since(Instant::now())) {
+                    Ok(next) => next,
+                    Err(_) => return msg,
+                }
+            }
+            Err(mpsc::TryRecvError::Disconnected) => return msg,
+        };
+        let folded = match &next {
+            Msg::Key(key) => Msg::from(*key),
+            _ => Msg::None,
+        };
+        msg = match (msg, next, folded) {
+            (Msg::Pages(a), Msg::Pages(b), _) | (Msg::Pages(a), Msg::Key(_), Msg::Pages(b)) => {
+                Msg::Pages(a + b)
+            }
+            (Msg::Refresh, Msg::Refresh, _) => Msg::Refresh,
+            (Msg::Resize, Msg::Resize, _) => Msg::Resize,
+            (msg, next, _) => {
+                deferred.push_back(next);
+                return msg;
+            }
+        };
+    }
+}
+
+fn browser(
+    pdf: &mut Pdf,
+    rx: &Receiver<Msg>,
+    session: &mut Session,
+    message: &str,
+) -> anyhow::Result<Refersh> {
+    let config = &session.config;
+    let input = &session.input;
+    let mut stdout = stdout().into_raw_mode()?;
+
     write!(
         stdout,
         "{}{}",
-        termion::cursor::Goto(1, 1),
-        termion::clear::All,
+        termion::cursor::Restore,
+        termion::clear::CurrentLine
     )?;
-
-    pdf.page.display(None)?;
+    // Carry the highlighting over from the last file
+    pdf.set_highlight(session.highlight());
+    redraw(pdf, &mut stdout, message)?;
 
     let mut double_gg = false;
-    for c in rx {
+    // The first key of two, like the `m` of `ma` or the `]` of `]s`
+    let mut pending: Option<Msg> = None;
+    // The count typed so far, like the 5 of `5j`
+    let mut count = Count::default();
+    let mut prompt: Option<Prompt> = None;
+    let mut selection: Option<Selection> = None;
+    // The magnifying glass, and the page rendered at its zoom
+    let mut magnifier: Option<(Magnifier, DynamicImage)> = None;
+    // The page from `:compare`, while it's laid over this one
+    let mut compared: Option<Comparison> = None;
+    // Continuous mode, while the pages are being scrolled through
+    let mut scrolled: Option<Scroll> = None;
+    // The links on the page, labelled to be followed
+    let mut hinting: Option<Hints> = None;
+    // Messages that arrived while something else (like the pager) had the
+    // keyboard
+    let mut deferred: VecDeque<Msg> = VecDeque::new();
+    // Page turns and reloads are held back to at most `max_fps` a second, so
+    // holding a key down skips pages instead of queueing up every one
+    let frame = match config.max_fps {
+        0 => Duration::ZERO,
+        fps => Duration::from_secs(1) / fps,
+    };
+    let mut drawn = Instant::now() - frame;
+    if session.view != View::Page {
+        deferred.push_back(Msg::Fit(session.view.start()));
+    }
+    // Run the `-c` commands as if they'd been typed at the `:` prompt, each
+    // after the one before it has finished
+    for line in std::mem::take(&mut session.commands) {
+        match command::parse(&line) {
+            Ok(msg) => deferred.push_back(msg),
+            Err(e) => pdf.status(&e.to_string())?,
+        }
+    }
+    loop {
+        session.dwell.observe(&pdf.file, pdf.current_page);
+        let here = Some((pdf.file.clone(), pdf.current_page));
+        if config.screen_reader && prompt.is_none() && session.spoken != here {
+            speech::say(pdf.current_page, &pdf.anchor)?;
+            pdf.status("")?;
+            session.spoken = here;
+        }
+        if strip::take_stale() {
+            show_strip(pdf, &mut session.strip, &session.tx)?;
+        }
+        let c = match deferred.pop_front() {
+            Some(c) => c,
+            None => match rx.recv() {
+                Ok(c) => c,
+                Err(_) => break,
+            },
+        };
+        let c = match (c, prompt.as_mut()) {
+            (Msg::Key(key), Some(p)) => match p.key(key) {
+                PromptResult::Pending => {
+                    term::status_line(&p.render())?;
+                    continue;
+                }
+                PromptResult::Cancel => {
+                    prompt = None;
+                    pdf.status("")?;
+                    continue;
+                }
+                PromptResult::Confirmed(msg) => {
+                    prompt = None;
+                    pdf.status("")?;
+                    msg
+                }
+                PromptResult::Submit(line) => {
+                    prompt = None;
+                    pdf.status("")?;
+                    match command::parse(&line) {
+                        Ok(msg) => msg,
+                        Err(e) => {
+                            pdf.status(&e.to_string())?;
+                            continue;
+                        }
+                    }
+                }
+            },
+            (Msg::Key(key), None) if selection.is_some() => {
+                let sel = selection.as_mut().unwrap();
+                match sel.key(key) {
+                    SelectResult::Pending => {
+                        Page::from_image(sel.draw(&pdf.page.image))?.display()?;
+                        term::status_line(sel.help())?;
+                    }
+                    SelectResult::Cancel => {
+                        selection = None;
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                    }
+                    SelectResult::Done(action) => {
+                        let sel = selection.take().unwrap();
+                        pdf.page.display()?;
+                        match sel.purpose {
+                            select::Purpose::Screenshot => {
+                                let message = match save_selection(pdf, &sel, config, action) {
+                                    Ok(m) => m,
+                                    Err(e) => e.to_string(),
+                                };
+                                pdf.status(&message)?;
+                            }
+                            select::Purpose::Latex => {
+                                match crop_selection(pdf, &sel, config, false) {
+                                    Ok(png) => {
+                                        let job = session
+                                            .jobs
+                                            .start("Converting to LaTeX", session.tx.clone());
+                                        let config = config.clone();
+                                        thread::spawn(move || {
+                                            job.finish(match latex::convert(&png, &config) {
+                                                Ok(latex) => format!("Copied {}", latex),
+                                                Err(e) => format!("Couldn't convert: {}", e),
+                                            });
+                                        });
+                                    }
+                                    Err(e) => pdf.status(&e.to_string())?,
+                                }
+                            }
+                            select::Purpose::Lookup => {
+                                let region = (sel.x, sel.y, sel.w, sel.h);
+                                let size = pdf.page.size;
+                                match text::in_region(&pdf.file, pdf.current_page, region, size) {
+                                    Ok(words) if !words.is_empty() => {
+                                        deferred.push_back(Msg::Lookup(words))
+                                    }
+                                    Ok(_) => pdf.status("No text there")?,
+                                    Err(e) => pdf.status(&e.to_string())?,
+                                }
+                            }
+                        }
+                    }
+                }
+                continue;
+            }
+            (Msg::Key(key), None) if magnifier.is_some() => {
+                let (glass, detail) = magnifier.as_mut().unwrap();
+                let shown = match glass.key(key) {
+                    MagnifyResult::Pending => Ok(()),
+                    MagnifyResult::Zoomed => magnified(pdf, glass.zoom).map(|d| *detail = d),
+                    MagnifyResult::Close => {
+                        magnifier = None;
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        continue;
+                    }
+                };
+                match shown {
+                    Ok(()) => {
+                        Page::from_image(glass.draw(&pdf.page.image, detail))?.display()?;
+                        term::status_line(&glass.help())?;
+                    }
+                    Err(e) => term::status_line(&format!("Couldn't magnify: {}", e))?,
+                }
+                continue;
+            }
+            // `f` goes on to the next view from here too
+            (Msg::Key(key), None) if pdf.zoomed.is_some() && key != Key::Char('f') => {
+                // Out of the way while it's rendered, as that needs the page
+                let mut view = pdf.zoomed.take().unwrap();
+                let zoom = view.zoom;
+                match view.key(key) {
+                    PosterResult::Pending => {}
+                    PosterResult::Moved => {
+                        if view.zoom != zoom {
+                            session.view = View::Zoom(view.zoom);
+                            session.custom_zoom = view.zoom;
+                        }
+                        match poster_view(pdf, &view) {
+                            Ok(image) => {
+                                Page::from_image(image)?.display()?;
+                                term::status_line(&view.help(&session.view.name()))?;
+                            }
+                            Err(e) => term::status_line(&format!("Couldn't render: {}", e))?,
+                        }
+                    }
+                    PosterResult::Turn(n) => {
+                        // Kept for where across the page it's looking, to
+                        // zoom in on the next one there
+                        pdf.zoomed = Some(view);
+                        deferred.push_back(Msg::Pages(n));
+                        continue;
+                    }
+                    PosterResult::Close => {
+                        session.view = View::Page;
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        continue;
+                    }
+                }
+                pdf.zoomed = Some(view);
+                continue;
+            }
+            (Msg::Key(key), None) if scrolled.is_some() => {
+                let view = scrolled.as_mut().unwrap();
+                let by = match view.key(key) {
+                    ScrollResult::Pending => continue,
+                    ScrollResult::Scroll(by) => by,
+                    ScrollResult::Close => {
+                        // Back to turning pages, from the one at the top
+                        let p = view.page;
+                        scrolled = None;
+                        pdf.turn_to(p, config.progressive_render, &session.tx);
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        continue;
+                    }
+                };
+                let width = view.width();
+                let mut render = |p| scroll_page(pdf, p, width);
+                match view
+                    .scroll(by, &mut render)
+                    .and_then(|_| view.draw(&mut render))
+                {
+                    Ok(image) => {
+                        Page::from_image(image)?.display()?;
+                        // For the status line, and the time spent on it
+                        pdf.current_page = view.page;
+                        pdf.status(view.help())?;
+                    }
+                    Err(e) => pdf.status(&format!("Couldn't render: {}", e))?,
+                }
+                continue;
+            }
+            (Msg::Key(key), None) if compared.is_some() => {
+                let comparison = compared.as_mut().unwrap();
+                match comparison.key(key) {
+                    CompareResult::Pending => {}
+                    CompareResult::Redraw => {
+                        Page::from_image(comparison.draw(&pdf.page.image))?.display()?;
+                        term::status_line(&comparison.help(&pdf.page.image))?;
+                    }
+                    CompareResult::Close => {
+                        compared = None;
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                    }
+                }
+                continue;
+            }
+            (Msg::Key(key), None) if hinting.is_some() => {
+                let hints = hinting.as_mut().unwrap();
+                match hints.key(key) {
+                    HintResult::Pending => continue,
+                    HintResult::Redraw => {
+                        match hints.draw(&pdf.page.image) {
+                            Ok(drawn) => Page::from_image(drawn)?.display()?,
+                            Err(e) => pdf.status(&format!("Couldn't label the links: {}", e))?,
+                        }
+                        term::status_line(&hints.help())?;
+                        continue;
+                    }
+                    HintResult::Close => {
+                        hinting = None;
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        continue;
+                    }
+                    HintResult::Follow(target) => {
+                        hinting = None;
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        Msg::Follow(target)
+                    }
+                }
+            }
+            (Msg::Key(key), None) if pending.is_some() => {
+                pdf.status("")?;
+                match (pending.take(), key) {
+                    (Some(Msg::Mark), Key::Char(c)) if c.is_ascii_lowercase() => Msg::SetMark(c),
+                    (Some(Msg::ToMark), Key::Char(c)) if c.is_ascii_lowercase() => Msg::GotoMark(c),
+                    (Some(Msg::Bracket(forward)), Key::Char('s')) => Msg::Section(forward),
+                    _ => continue,
+                }
+            }
+            (Msg::Key(key), None) if session.review_run.is_some() => match key {
+                Key::Char('y') => Msg::ReviewAnswer(true),
+                Key::Char('n') => Msg::ReviewAnswer(false),
+                Key::Esc => Msg::ReviewStop,
+                key => key.into(),
+            },
+            (Msg::Key(key), None) => {
+                let counting = !count.is_empty();
+                match count.key(key) {
+                    Some(msg) => {
+                        if counting {
+                            pdf.status("")?;
+                        }
+                        msg
+                    }
+                    None => {
+                        pdf.status(&count.to_string())?;
+                        continue;
+                    }
+                }
+            }
+            (c, _) => c,
+        };
+        let c = match c {
+            Msg::Changed => match session.autorefresh(&pdf.file) {
+                AutoRefresh::On => Msg::Refresh,
+                AutoRefresh::Notify => Msg::Changed,
+                AutoRefresh::Off => Msg::None,
+            },
+            c => c,
+        };
+        let c = coalesce(c, rx, &mut deferred, frame.saturating_sub(drawn.elapsed()));
         match c {
+            Msg::Key(_) => {}
+            Msg::Command => {
+                let p = Prompt::Command(String::new());
+                term::status_line(&p.render())?;
+                prompt = Some(p);
+            }
+            Msg::Trash => {
+                let p = Prompt::confirm(
+                    &format!("Move {} to the trash?", pdf.file),
+                    Msg::TrashConfirmed,
+                );
+                term::status_line(&p.render())?;
+                prompt = Some(p);
+            }
+            Msg::TrashConfirmed => return Ok(Refersh::Trash),
+            Msg::Rename(template) => match rename_target(&pdf.file, &template) {
+                Ok(target) => return Ok(Refersh::Rename(target)),
+                Err(e) => pdf.status(&format!("Couldn't rename: {}", e))?,
+            },
+            Msg::RenameTo(target) => return Ok(Refersh::Rename(target)),
+            Msg::SuggestName => match suggest_name(&pdf.file) {
+                Ok(name) => {
+                    // Taken as it is, since a title can have braces in it
+                    let target = Path::new(&pdf.file).with_file_name(&name);
+                    let p = Prompt::confirm(&format!("Rename to {}?", name), Msg::RenameTo(target));
+                    term::status_line(&p.render())?;
+                    prompt = Some(p);
+                }
+                Err(e) => pdf.status(&e.to_string())?,
+            },
+            Msg::Filter(query) => return Ok(Refersh::Filter(query)),
+            Msg::ExportDwell(path) => {
+                let message = match session.dwell.export(&path, config.backups) {
+                    Ok(_) => format!("Reading times written to {}", path),
+                    Err(e) => e.to_string(),
+                };
+                pdf.status(&message)?;
+            }
+            Msg::Select(purpose) => {
+                let sel = Selection::new(purpose);
+                Page::from_image(sel.draw(&pdf.page.image))?.display()?;
+                term::status_line(sel.help())?;
+                selection = Some(sel);
+            }
+            Msg::Magnify => {
+                let glass = Magnifier::new();
+                match magnified(pdf, glass.zoom) {
+                    Ok(detail) => {
+                        Page::from_image(glass.draw(&pdf.page.image, &detail))?.display()?;
+                        term::status_line(&glass.help())?;
+                        magnifier = Some((glass, detail));
+                    }
+                    Err(e) => pdf.status(&format!("Couldn't magnify: {}", e))?,
+                }
+            }
+            Msg::Zoom(zoom, spot) => {
+                session.view = View::Zoom(zoom);
+                session.custom_zoom = zoom;
+                show_view(pdf, session.view, spot)?;
+            }
+            Msg::ZoomOut => {
+                session.view = View::Page;
+                pdf.zoomed = None;
+                pdf.page.display()?;
+                pdf.status("")?;
+            }
+            Msg::Fit(spot) => {
+                let zoomed = pdf.zoomed.is_some();
+                if !show_view(pdf, session.view, spot)? && zoomed {
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                }
+            }
+            Msg::FitText => match column::on_page(&pdf.file, pdf.current_page) {
+                Ok(Some(column)) => match column.fit(render_size()) {
+                    (zoom, spot) if zoom > 1.01 => deferred.push_back(Msg::Zoom(zoom, spot)),
+                    // Already as big as it goes
+                    _ => {
+                        session.view = View::Page;
+                        pdf.zoomed = None;
+                        pdf.page.display()?;
+                        pdf.status("The text fills the window already")?;
+                    }
+                },
+                Ok(None) => pdf.status("No text on this page to fit to")?,
+                Err(e) => pdf.status(&format!("Couldn't find the text: {}", e))?,
+            },
+            Msg::CycleView => {
+                session.view = session.view.next(session.custom_zoom);
+                if !show_view(pdf, session.view, session.view.start())? {
+                    pdf.page.display()?;
+                    pdf.status(&session.view.name())?;
+                }
+            }
+            Msg::Continuous => {
+                let mut view = Scroll::new(pdf.current_page, pdf.range.clone(), render_size());
+                let width = view.width();
+                match view.draw(&mut |p| scroll_page(pdf, p, width)) {
+                    Ok(image) => {
+                        pdf.zoomed = None;
+                        Page::from_image(image)?.display()?;
+                        pdf.status(view.help())?;
+                        scrolled = Some(view);
+                    }
+                    Err(e) => pdf.status(&format!("Couldn't scroll: {}", e))?,
+                }
+            }
+            Msg::Compare(args) => match comparison(pdf, &args) {
+                Ok(comparison) => {
+                    Page::from_image(comparison.draw(&pdf.page.image))?.display()?;
+                    term::status_line(&comparison.help(&pdf.page.image))?;
+                    compared = Some(comparison);
+                }
+                Err(e) => pdf.status(&format!("Couldn't compare: {}", e))?,
+            },
+            Msg::Notice(message) => {
+                if prompt.is_none()
+                    && selection.is_none()
+                    && magnifier.is_none()
+                    && pdf.zoomed.is_none()
+                    && compared.is_none()
+                    && scrolled.is_none()
+                    && hinting.is_none()
+                {
+                    pdf.status(&message)?;
+                }
+            }
+            Msg::Summarize(range) => {
+                let pages = match text::parse_range(&range, pdf.current_page, pdf.length) {
+                    Ok(pages) => pages,
+                    Err(e) => {
+                        pdf.status(&e.to_string())?;
+                        continue;
+                    }
+                };
+                let title = if pages.len() == 1 {
+                    format!("Summary of p. {}", pages.start + 1)
+                } else {
+                    format!("Summary of pp. {}-{}", pages.start + 1, pages.end)
+                };
+                let started = text::extract(&pdf.file, pages).and_then(|input| {
+                    let command = external::command(&config.summarize_command, &[])?;
+                    external::stream(command, input, session.tx.clone())
+                });
+                match started {
+                    Ok(child) => {
+                        deferred.extend(show_output(child, &title, rx)?);
+                        redraw(pdf, &mut stdout, "")?;
+                    }
+                    Err(e) => pdf.status(&format!("Couldn't summarize: {}", e))?,
+                }
+            }
+            Msg::Lookup(word) if word.is_empty() => {
+                deferred.push_back(Msg::Select(select::Purpose::Lookup))
+            }
+            Msg::Lookup(word) => {
+                let started = external::command(&config.dictionary_command, &[("word", &word)])
+                    .and_then(|command| {
+                        external::stream(command, String::new(), session.tx.clone())
+                    });
+                match started {
+                    Ok(child) => {
+                        deferred.extend(show_output(child, &word, rx)?);
+                        redraw(pdf, &mut stdout, "")?;
+                    }
+                    Err(e) => pdf.status(&format!("Couldn't look up {}: {}", word, e))?,
+                }
+            }
+            // Left over from a command whose pager was closed
+            Msg::Output(_) | Msg::OutputEnd => {}
+            Msg::Flashcards(out) => {
+                let out = match out.is_empty() {
+                    true => Path::new(&pdf.file)
+                        .with_extension("tsv")
+                        .to_string_lossy()
+                        .to_string(),
+                    false => out,
+                };
+                let job = session
+                    .jobs
+                    .start("Exporting flashcards", session.tx.clone());
+                let file = pdf.file.clone();
+                let backups = config.backups;
+                thread::spawn(move || {
+                    let result = flashcards::export(&file, &out, backups, |page, pages| {
+                        job.progress(&format!("page {}/{}", page, pages))
+                    });
+                    job.finish(match result {
+                        Ok(0) => "No highlights to make flashcards from".to_string(),
+                        Ok(n) => format!("{} flashcards written to {}", n, out),
+                        Err(e) => format!("Couldn't export flashcards: {}", e),
+                    });
+                });
+            }
+            Msg::Review(action) => match action.as_str() {
+                "add" => {
+                    let message = match session.review.add(&pdf.file, pdf.current_page) {
+                        true => match session.review.save() {
+                            Ok(_) => "page marked for review".to_string(),
+                            Err(e) => e.to_string(),
+                        },
+                        false => "page is already marked for review".to_string(),
+                    };
+                    pdf.status(&message)?;
+                }
+                "rm" | "remove" => {
+                    let message = match session.review.remove(&pdf.file, pdf.current_page) {
+                        true => match session.review.save() {
+                            Ok(_) => "page no longer marked for review".to_string(),
+                            Err(e) => e.to_string(),
+                        },
+                        false => "page isn't marked for review".to_string(),
+                    };
+                    pdf.status(&message)?;
+                }
+                "list" => {
+                    let lines = session
+                        .review
+                        .items
+                        .iter()
+                        .map(|i| {
+                            let days = i.due.saturating_sub(review::now()) as f64 / 86400.0;
+                            format!("{} p. {}  due in {:.1} days", i.file, i.page + 1, days)
+                        })
+                        .collect();
+                    let mut pager = Pager::new("Pages marked for review", lines);
+                    let mut later = vec![];
+                    pager.run(rx, &mut later)?;
+                    deferred.extend(later);
+                    redraw(pdf, &mut stdout, "")?;
+                }
+                "" => {
+                    let run = session.review.due();
+                    match run.current().cloned() {
+                        Some((file, page)) => {
+                            session.review_run = Some(run);
+                            return Ok(Refersh::Open(file, Some(page)));
+                        }
+                        None => pdf.status("Nothing is due for review")?,
+                    }
+                }
+                _ => pdf.status("Usage: :review [add|rm|list]")?,
+            },
+            Msg::ReviewAnswer(remembered) => {
+                let run = match session.review_run.as_mut() {
+                    Some(run) => run,
+                    None => continue,
+                };
+                if let Some((file, page)) = run.current().cloned() {
+                    session.review.answer(&file, page, remembered);
+                }
+                run.position += 1;
+                let next = run.current().cloned();
+                if let Err(e) = session.review.save() {
+                    pdf.status(&e.to_string())?;
+                }
+                match next {
+                    Some((file, page)) => return Ok(Refersh::Open(file, Some(page))),
+                    None => {
+                        session.review_run = None;
+                        pdf.status("Review finished")?;
+                    }
+                }
+            }
+            Msg::ReviewStop => {
+                session.review_run = None;
+                pdf.status("Review stopped")?;
+            }
+            Msg::Outline(action) => {
+                let (action, title) = action.split_once(' ').unwrap_or((&action, ""));
+                let entries = match (&mut pdf.outline, action) {
+                    (Some(entries), _) => entries,
+                    (None, "add" | "rm" | "remove" | "list") => match outline::read(&pdf.file) {
+                        Ok(entries) => pdf.outline.insert(entries),
+                        Err(e) => {
+                            pdf.status(&format!("Couldn't read the outline: {}", e))?;
+                            continue;
+                        }
+                    },
+                    (None, "write") => {
+                        pdf.status("The outline hasn't been changed")?;
+                        continue;
+                    }
+                    (None, _) => {
+                        pdf.status("Usage: :outline add TITLE|rm|list|write")?;
+                        continue;
+                    }
+                };
+                let current_page = pdf.current_page;
+                let message = match action {
+                    "add" if title.trim_start_matches('>').trim().is_empty() => {
+                        "Usage: :outline add TITLE (>TITLE for a level below)".to_string()
+                    }
+                    "add" => {
+                        let (title, level) = outline::parse_title(title);
+                        outline::insert(
+                            entries,
+                            outline::Entry {
+                                title,
+                                level,
+                                page: current_page,
+                            },
+                        );
+                        "added to the outline; :outline write to save it".to_string()
+                    }
+                    "rm" | "remove" => {
+                        let before = entries.len();
+                        entries.retain(|e| e.page != current_page);
+                        match before - entries.len() {
+                            0 => "nothing in the outline goes to this page".to_string(),
+                            n => {
+                                format!("{} taken out of the outline; :outline write to save it", n)
+                            }
+                        }
+                    }
+                    "list" => {
+                        let lines = entries
+                            .iter()
+                            .map(|e| {
+                                let indent = "  ".repeat(e.level - 1);
+                                format!("p. {:<5} {}{}", e.page + 1, indent, e.title)
+                            })
+                            .collect();
+                        let mut pager = Pager::new("Outline", lines);
+                        let mut later = vec![];
+                        pager.run(rx, &mut later)?;
+                        deferred.extend(later);
+                        redraw(pdf, &mut stdout, "")?;
+                        continue;
+                    }
+                    "write" => match outline::write(&pdf.file, entries, config) {
+                        Ok(_) => {
+                            pdf.outline = None;
+                            "outline saved".to_string()
+                        }
+                        Err(e) => format!("Couldn't save the outline: {}", e),
+                    },
+                    _ => "Usage: :outline add TITLE|rm|list|write".to_string(),
+                };
+                pdf.status(&message)?;
+            }
+            Msg::Queue(action) => match action.as_str() {
+                "add" => {
+                    let message = match session.queue.add(&pdf.file) {
+                        true => match session.queue.save() {
+                            Ok(_) => format!("queued ({} in queue)", session.queue.files.len()),
+                            Err(e) => e.to_string(),
+                        },
+                        false => "already queued".to_string(),
+                    };
+                    pdf.status(&message)?;
+                }
+                "rm" | "remove" => {
+                    let message = match session.queue.remove(&pdf.file) {
+                        true => match session.queue.save() {
+                            Ok(_) => "removed from queue".to_string(),
+                            Err(e) => e.to_string(),
+                        },
+                        false => "not queued".to_string(),
+                    };
+                    pdf.status(&message)?;
+                }
+                "next" => {
+                    // Finishing with this file takes it off the queue
+                    session.queue.remove(&pdf.file);
+                    if let Err(e) = session.queue.save() {
+                        pdf.status(&e.to_string())?;
+                        continue;
+                    }
+                    match session.queue.existing().first() {
+                        Some(file) => return Ok(Refersh::Open(file.clone(), None)),
+                        None => pdf.status("The reading queue is empty")?,
+                    }
+                }
+                "list" | "" => {
+                    let lines = session.queue.files.to_vec();
+                    let mut pager = Pager::new("Reading queue", lines);
+                    let mut later = vec![];
+                    pager.run(rx, &mut later)?;
+                    deferred.extend(later);
+                    redraw(pdf, &mut stdout, "")?;
+                }
+                _ => pdf.status("Usage: :queue add|rm|next|list")?,
+            },
+            Msg::Tag(names) => {
+                for name in &names {
+                    session.tags.add(&pdf.file, name);
+                }
+                pdf.status(&save_tags(&session.tags, &pdf.file))?;
+            }
+            Msg::Untag(names) => {
+                for name in &names {
+                    session.tags.remove(&pdf.file, name);
+                }
+                pdf.status(&save_tags(&session.tags, &pdf.file))?;
+            }
             Msg::FirstPage => match double_gg {
                 true => {
-                    pdf.current_page = 0;
-                    pdf.get_page(pdf.current_page);
-                    pdf.page.display(None)?;
+                    pdf.jumps.push(pdf.current_page);
+                    pdf.turn_to(pdf.range.start, config.progressive_render, &session.tx);
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    refit(
+                        pdf,
+                        &mut session.view,
+                        session.zoom_per_page,
+                        true,
+                        &mut deferred,
+                    );
                 }
                 false => {
                     double_gg = true;
                 }
             },
             Msg::LastPage => {
-                pdf.current_page = pdf.length - 1;
-                pdf.get_page(pdf.current_page);
-                pdf.page.display(None)?;
+                pdf.jumps.push(pdf.current_page);
+                pdf.turn_to(pdf.range.end - 1, config.progressive_render, &session.tx);
+                pdf.page.display()?;
+                pdf.status("")?;
+                refit(
+                    pdf,
+                    &mut session.view,
+                    session.zoom_per_page,
+                    true,
+                    &mut deferred,
+                );
             }
             Msg::None => {}
-            Msg::Quit => return Ok(Refersh::Done),
+            Msg::Changed => {
+                pdf.changed = true;
+                if prompt.is_none()
+                    && selection.is_none()
+                    && magnifier.is_none()
+                    && pdf.zoomed.is_none()
+                    && compared.is_none()
+                    && scrolled.is_none()
+                    && hinting.is_none()
+                {
+                    pdf.status("r to reload")?;
+                }
+            }
+            Msg::Quit => match session.jobs.describe() {
+                Some(running) => {
+                    let p = Prompt::confirm(
+                        &format!("Still {}. Quit anyway?", running),
+                        Msg::QuitConfirmed,
+                    );
+                    term::status_line(&p.render())?;
+                    prompt = Some(p);
+                }
+                None => return Ok(Refersh::Done),
+            },
+            Msg::QuitConfirmed => return Ok(Refersh::Done),
             Msg::Open => {
-                Command::new("open")
-                    .arg(&pdf.file)
-                    .spawn()
-                    .expect("Couldn't open file in external application");
+                if let Err(e) = open_elsewhere(&pdf.file) {
+                    pdf.status(&format!("Couldn't open it in another application: {}", e))?;
+                }
+            }
+            Msg::Reveal => match reveal(pdf, config, input, &mut stdout) {
+                Ok(_) if config.file_manager_in_terminal => redraw(pdf, &mut stdout, "")?,
+                Ok(_) => {}
+                Err(e) => {
+                    if config.file_manager_in_terminal {
+                        redraw(pdf, &mut stdout, "")?;
+                    }
+                    pdf.status(&format!("Couldn't reveal file: {}", e))?;
+                }
+            },
+            Msg::Refresh => match pdf.reload() {
+                Ok(true) => {
+                    session.strip.forget();
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    drawn = Instant::now();
+                    // Zoomed in on the same place again
+                    if let Some(zoomed) = pdf.zoomed.take() {
+                        deferred.push_back(Msg::Fit((zoomed.x, zoomed.y)));
+                    }
+                }
+                // Rebuilt without visible changes: don't send the same image
+                // again
+                Ok(false) => pdf.status("no changes")?,
+                // Keep showing what we had rather than giving up
+                Err(e) => pdf.status(&format!("Couldn't reload: {}", e))?,
+            },
+            Msg::Resize => {
+                // What's on screen was laid out for the old size
+                if let Some(view) = scrolled.take() {
+                    pdf.current_page = view.page;
+                }
+                (selection, magnifier, compared, hinting) = (None, None, None, None);
+                session.strip.forget();
+                pdf.get_page(pdf.current_page);
+                redraw(pdf, &mut stdout, "")?;
+                if let Some(p) = &prompt {
+                    term::status_line(&p.render())?;
+                }
+                drawn = Instant::now();
+                if let Some(zoomed) = pdf.zoomed.take() {
+                    deferred.push_back(Msg::Fit((zoomed.x, zoomed.y)));
+                }
             }
-            Msg::Refresh => return Ok(Refersh::Oker),
-            Msg::NextPage => {
+            Msg::Pages(n) => {
                 double_gg = false;
-                if pdf.current_page != (pdf.length - 1) {
-                    pdf.current_page = pdf.current_page + 1;
-                    pdf.get_page(pdf.current_page);
-                    pdf.page.display(None)?;
+                let last = pdf.range.end as isize - 1;
+                let target = (pdf.current_page as isize + n).clamp(pdf.range.start as isize, last);
+                if target as usize != pdf.current_page {
+                    pdf.turn_to(target as usize, config.progressive_render, &session.tx);
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    drawn = Instant::now();
+                    refit(
+                        pdf,
+                        &mut session.view,
+                        session.zoom_per_page,
+                        n > 0,
+                        &mut deferred,
+                    );
+                } else if config.cross_documents && n > 0 {
+                    return Ok(Refersh::PastEnd);
+                } else if config.cross_documents && n < 0 {
+                    return Ok(Refersh::PastStart);
+                }
+            }
+            Msg::Goto(number) => match pdf.page_index(number) {
+                Ok(p) => {
+                    double_gg = false;
+                    pdf.jumps.push(pdf.current_page);
+                    pdf.turn_to(p, config.progressive_render, &session.tx);
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    refit(
+                        pdf,
+                        &mut session.view,
+                        session.zoom_per_page,
+                        true,
+                        &mut deferred,
+                    );
+                }
+                Err(e) => pdf.status(&e.to_string())?,
+            },
+            Msg::GotoLabel(label) => match pdf.label_index(&label) {
+                Ok(p) => {
+                    double_gg = false;
+                    pdf.jumps.push(pdf.current_page);
+                    pdf.turn_to(p, config.progressive_render, &session.tx);
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    refit(
+                        pdf,
+                        &mut session.view,
+                        session.zoom_per_page,
+                        true,
+                        &mut deferred,
+                    );
+                }
+                Err(e) => pdf.status(&e.to_string())?,
+            },
+            Msg::Find(query) => {
+                session.search = Some(query.clone());
+                session.cleared = false;
+                if session.hlsearch {
+                    pdf.set_highlight(session.highlight());
+                }
+                match search::on_page(&pdf.file, pdf.current_page, &query, pdf.page.size) {
+                    Ok(matches) if matches.is_empty() => {
+                        pdf.page.display()?;
+                        pdf.status(&format!("Not on this page: {}", query))?
+                    }
+                    Ok(matches) => {
+                        match session.hlsearch {
+                            true => pdf.page.display()?,
+                            false => {
+                                Page::from_image(search::highlight(&pdf.page.image, &matches))?
+                                    .display()?
+                            }
+                        }
+                        let plural = if matches.len() == 1 {
+                            "match"
+                        } else {
+                            "matches"
+                        };
+                        pdf.status(&format!("{} {} on this page", matches.len(), plural))?;
+                    }
+                    Err(e) => pdf.status(&format!("Couldn't search: {}", e))?,
+                }
+            }
+            Msg::Search(query) if query.is_empty() => {
+                let p = Prompt::Search(String::new());
+                term::status_line(&p.render())?;
+                prompt = Some(p);
+            }
+            Msg::Search(query) => {
+                session.search = Some(query.clone());
+                session.cleared = false;
+                if session.hlsearch {
+                    pdf.set_highlight(session.highlight());
+                }
+                let here = search::on_page(&pdf.file, pdf.current_page, &query, pdf.page.size);
+                match here {
+                    Ok(matches) if !matches.is_empty() => {
+                        flash(pdf, &matches, &session.tx)?;
+                        pdf.status(&found(matches.len(), ""))?;
+                    }
+                    _ => deferred.push_back(Msg::SearchNext(true)),
+                }
+            }
+            Msg::SearchNext(forward) => {
+                let query = match &session.search {
+                    Some(query) => query.clone(),
+                    None => {
+                        pdf.status("Nothing searched for yet: / to search")?;
+                        continue;
+                    }
                 };
+                let range = pdf.range.clone();
+                match search::next_page(&pdf.file, range, pdf.current_page, &query, forward) {
+                    Ok(Some(p)) => {
+                        let wrapped = match forward {
+                            true => p <= pdf.current_page,
+                            false => p >= pdf.current_page,
+                        };
+                        double_gg = false;
+                        if p != pdf.current_page {
+                            pdf.jumps.push(pdf.current_page);
+                        }
+                        pdf.get_page(p);
+                        let matches = search::on_page(&pdf.file, p, &query, pdf.page.size)
+                            .unwrap_or_default();
+                        flash(pdf, &matches, &session.tx)?;
+                        let note = match (wrapped, forward) {
+                            (false, _) => "",
+                            (true, true) => " (from the top)",
+                            (true, false) => " (from the bottom)",
+                        };
+                        pdf.status(&found(matches.len(), note))?;
+                        drawn = Instant::now();
+                    }
+                    Ok(None) => pdf.status(&format!("Not found: {}", query))?,
+                    Err(e) => pdf.status(&format!("Couldn't search: {}", e))?,
+                }
             }
-            Msg::PreviousPage => {
-                double_gg = false;
-                if pdf.current_page != 0 {
-                    pdf.current_page = pdf.current_page - 1;
+            Msg::Unflash(p) => {
+                let drawn_over = selection.is_some()
+                    || magnifier.is_some()
+                    || pdf.zoomed.is_some()
+                    || compared.is_some()
+                    || scrolled.is_some()
+                    || hinting.is_some();
+                if p == pdf.current_page && prompt.is_none() && !drawn_over {
+                    pdf.page.display()?;
+                }
+            }
+            Msg::ZoomPerPage(on) => {
+                session.zoom_per_page = on;
+                pdf.status(match on {
+                    true => "zoom kept per page",
+                    false => "zoom follows from page to page",
+                })?;
+            }
+            Msg::AutoRefresh(autorefresh) => {
+                session.autorefresh.insert(pdf.file.clone(), autorefresh);
+                pdf.status(&format!("autorefresh {} for this file", autorefresh.name()))?;
+            }
+            Msg::HlSearch(on) => {
+                session.hlsearch = on;
+                pdf.set_highlight(session.highlight());
+                pdf.page.display()?;
+                pdf.status(if on { "hlsearch" } else { "nohlsearch" })?;
+            }
+            Msg::Sharpened(p, rendered) => {
+                let drawn_over = selection.is_some()
+                    || magnifier.is_some()
+                    || pdf.zoomed.is_some()
+                    || compared.is_some()
+                    || scrolled.is_some()
+                    || hinting.is_some();
+                if pdf.sharpen(p, *rendered) && !drawn_over {
+                    pdf.page.display()?;
+                }
+            }
+            Msg::Thumbnail(thumb) => session.strip.insert(*thumb),
+            Msg::ToggleDark => deferred.push_back(Msg::Dark(!render::dark())),
+            Msg::Dark(on) => {
+                render::set_dark(on);
+                session.strip.forget();
+                pdf.get_page(pdf.current_page);
+                pdf.page.display()?;
+                pdf.status(match render::dark() {
+                    true => "dark mode",
+                    false => "light mode",
+                })?;
+            }
+            Msg::TogglePrintPreview => {
+                render::set_print_preview(!render::print_preview());
+                session.strip.forget();
+                pdf.get_page(pdf.current_page);
+                pdf.page.display()?;
+                pdf.status(match render::print_preview() {
+                    true => "print preview: trim box red, bleed box blue, art box green",
+                    false => "print preview off",
+                })?;
+            }
+            Msg::ToggleRecolor => {
+                let on = !filter::recoloring();
+                deferred.push_back(Msg::SetColor("recolor".to_string(), on.to_string()));
+            }
+            Msg::ToggleNightLight => {
+                let lit = filter::toggle_night_light();
+                pdf.get_page(pdf.current_page);
+                pdf.page.display()?;
+                pdf.status(if lit {
+                    "night light"
+                } else {
+                    "night light off"
+                })?;
+            }
+            Msg::Mark | Msg::ToMark | Msg::Bracket(_) => {
+                pdf.status(match c {
+                    Msg::Mark => "mark: a-z",
+                    Msg::ToMark => "go to mark: a-z",
+                    _ => "s for the section",
+                })?;
+                pending = Some(c);
+            }
+            Msg::Hints => match links::on_page(&pdf.file, pdf.current_page, pdf.page.size) {
+                Ok(links) if links.is_empty() => pdf.status("No links on this page")?,
+                Ok(links) => {
+                    let hints = Hints::new(links);
+                    match hints.draw(&pdf.page.image) {
+                        Ok(drawn) => {
+                            Page::from_image(drawn)?.display()?;
+                            term::status_line(&hints.help())?;
+                            hinting = Some(hints);
+                        }
+                        Err(e) => pdf.status(&format!("Couldn't label the links: {}", e))?,
+                    }
+                }
+                Err(e) => pdf.status(&format!("Couldn't find the links: {}", e))?,
+            },
+            Msg::Follow(Target::Page(p)) => match pdf.range.contains(&p) {
+                true => {
+                    double_gg = false;
+                    pdf.jumps.push(pdf.current_page);
+                    pdf.turn_to(p, config.progressive_render, &session.tx);
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    refit(
+                        pdf,
+                        &mut session.view,
+                        session.zoom_per_page,
+                        true,
+                        &mut deferred,
+                    );
+                }
+                false => pdf.status(&format!("The link goes to page {}, out of range", p + 1))?,
+            },
+            // Seen before it's opened, since a link can say one thing and go
+            // somewhere else
+            Msg::Follow(Target::Uri(uri)) => {
+                let p = Prompt::confirm(&format!("Open {}?", uri), Msg::Browse(uri));
+                term::status_line(&p.render())?;
+                prompt = Some(p);
+            }
+            Msg::Browse(uri) => match browse(&uri, config) {
+                Ok(()) => pdf.status(&format!("Opened {}", uri))?,
+                Err(e) => pdf.status(&format!("Couldn't open {}: {}", uri, e))?,
+            },
+            Msg::Help => {
+                let lines = HELP.iter().map(|line| line.to_string()).collect();
+                let mut pager = Pager::new("Keys", lines);
+                let mut later = vec![];
+                pager.run(rx, &mut later)?;
+                deferred.extend(later);
+                redraw(pdf, &mut stdout, "")?;
+            }
+            Msg::Contents => {
+                let entries = pdf.entries();
+                if entries.is_empty() {
+                    pdf.status("This document has no outline")?;
+                    continue;
+                }
+                let mut toc = Toc::new(entries, pdf.current_page);
+                let mut later = vec![];
+                let picked = toc.run(rx, &mut later)?;
+                deferred.extend(later);
+                match picked {
+                    Some(p) if pdf.range.contains(&p) => {
+                        double_gg = false;
+                        pdf.jumps.push(pdf.current_page);
+                        pdf.turn_to(p, config.progressive_render, &session.tx);
+                        redraw(pdf, &mut stdout, "")?;
+                        refit(
+                            pdf,
+                            &mut session.view,
+                            session.zoom_per_page,
+                            true,
+                            &mut deferred,
+                        );
+                    }
+                    Some(p) => {
+                        redraw(pdf, &mut stdout, &format!("Page {} is out of range", p + 1))?
+                    }
+                    None => redraw(pdf, &mut stdout, "")?,
+                }
+            }
+            Msg::Section(forward) | Msg::Chapter(forward) => {
+                let chapter = matches!(c, Msg::Chapter(_));
+                let entries = pdf.entries();
+                match outline::boundary(&entries, pdf.current_page, forward, chapter) {
+                    Some(p) if pdf.range.contains(&p) => {
+                        double_gg = false;
+                        pdf.jumps.push(pdf.current_page);
+                        pdf.turn_to(p, config.progressive_render, &session.tx);
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        refit(
+                            pdf,
+                            &mut session.view,
+                            session.zoom_per_page,
+                            true,
+                            &mut deferred,
+                        );
+                    }
+                    _ if entries.is_empty() => pdf.status("This document has no outline")?,
+                    _ => pdf.status(match (chapter, forward) {
+                        (false, true) => "No next section",
+                        (false, false) => "No previous section",
+                        (true, true) => "No next chapter",
+                        (true, false) => "No previous chapter",
+                    })?,
+                }
+            }
+            Msg::SetMark(letter) => {
+                session.marks.set(&pdf.file, letter, pdf.current_page);
+                match session.marks.save() {
+                    Ok(()) => pdf.status(&format!("marked {}", letter))?,
+                    Err(e) => pdf.status(&e.to_string())?,
+                }
+            }
+            Msg::GotoMark(letter) => match session.marks.get(&pdf.file, letter) {
+                Some(p) if pdf.range.contains(&p) => {
+                    double_gg = false;
+                    pdf.jumps.push(pdf.current_page);
+                    pdf.turn_to(p, config.progressive_render, &session.tx);
+                    pdf.page.display()?;
+                    pdf.status("")?;
+                    refit(
+                        pdf,
+                        &mut session.view,
+                        session.zoom_per_page,
+                        true,
+                        &mut deferred,
+                    );
+                }
+                Some(p) => pdf.status(&format!(
+                    "Mark {} is on page {}, out of range",
+                    letter,
+                    p + 1
+                ))?,
+                None => pdf.status(&format!("No mark {}", letter))?,
+            },
+            Msg::JumpBack | Msg::JumpForward => {
+                let to = match c {
+                    Msg::JumpBack => pdf.jumps.back(pdf.current_page),
+                    _ => pdf.jumps.forward(),
+                };
+                match to {
+                    Some(p) if pdf.range.contains(&p) => {
+                        double_gg = false;
+                        pdf.turn_to(p, config.progressive_render, &session.tx);
+                        pdf.page.display()?;
+                        pdf.status("")?;
+                        refit(
+                            pdf,
+                            &mut session.view,
+                            session.zoom_per_page,
+                            true,
+                            &mut deferred,
+                        );
+                    }
+                    Some(p) => pdf.status(&format!("Page {} is out of range", p + 1))?,
+                    None => pdf.status("No more jumps")?,
+                }
+            }
+            Msg::CycleTone => {
+                let tone = graphics::tone().next();
+                graphics::set_tone(tone);
+                session.strip.forget();
+                pdf.get_page(pdf.current_page);
+                pdf.page.display()?;
+                pdf.status(tone.name())?;
+            }
+            Msg::SetColor(key, value) => match filter::set(&key, &value) {
+                Ok(()) => {
                     pdf.get_page(pdf.current_page);
-                    pdf.page.display(None)?;
+                    pdf.page.display()?;
+                    pdf.status(&format!("{}={}", key, value))?;
                 }
+                Err(e) => pdf.status(&e.to_string())?,
             },
-            /* Msg::Rotate => {
-                pdf.page.display(Some(true))?;
-            }, */
+            Msg::Announce => {
+                let words = pdf.position_words();
+                let spoken = match config.announce_command.is_empty() {
+                    true => Ok(()),
+                    false => announce(&words, config),
+                };
+                match spoken {
+                    Ok(()) => term::status_line(&words)?,
+                    Err(e) => pdf.status(&format!("Couldn't announce: {}", e))?,
+                }
+            }
+            Msg::Speak => {
+                speech::say(pdf.current_page, &pdf.anchor)?;
+                pdf.status("")?;
+            }
+            Msg::NoHighlight => {
+                session.cleared = true;
+                pdf.set_highlight(None);
+                pdf.page.display()?;
+                pdf.status("")?;
+            }
+            Msg::ToggleNumbering => {
+                pdf.absolute = !pdf.absolute;
+                let numbering = match pdf.absolute {
+                    true => "page numbers: physical",
+                    false => "page numbers: from the first page shown",
+                };
+                pdf.status(numbering)?;
+            }
+            Msg::Rotate => {
+                pdf.rotation = pdf.rotation.next();
+                pdf.get_page(pdf.current_page);
+                write!(stdout, "{}", termion::clear::All)?;
+                pdf.page.display()?;
+                pdf.status(&format!("rotation: {:?}", pdf.rotation).to_lowercase())?;
+            }
 
+            Msg::ReloadConfig => return Ok(Refersh::Reconfigure(None)),
+            Msg::Profile(name) if name.is_empty() => {
+                let profiles = session.config.profiles.join(", ");
+                let current = session.profile.as_deref().unwrap_or("none");
+                pdf.status(&format!("profile: {} (there's {})", current, profiles))?;
+            }
+            Msg::Profile(name) if name == "none" => return Ok(Refersh::Reconfigure(Some(None))),
+            Msg::Profile(name) => return Ok(Refersh::Reconfigure(Some(Some(name)))),
             Msg::NextDocument => return Ok(Refersh::Next),
             Msg::PreviousDocument => return Ok(Refersh::Previous),
         }
@@ -521,3 +3440,49 @@ fn browser(pdf: &mut Pdf, rx: &Receiver<Msg>) -> anyhow::Result<Refersh> {
 
     Ok(Refersh::Done)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_turns_are_added_up() {
+        let (tx, rx) = mpsc::channel();
+        for msg in [
+            Msg::Pages(1),
+            Msg::Key(Key::Char('j')),
+            Msg::Pages(-3),
+            Msg::Quit,
+        ] {
+            tx.send(msg).unwrap();
+        }
+        let mut deferred = VecDeque::new();
+        let msg = coalesce(Msg::Pages(1), &rx, &mut deferred, Duration::ZERO);
+        assert!(matches!(msg, Msg::Pages(0)));
+        // What ended the burst is handled next
+        assert!(matches!(deferred.pop_front(), Some(Msg::Quit)));
+    }
+
+    #[test]
+    fn resizes_are_folded() {
+        let (tx, rx) = mpsc::channel();
+        for msg in [Msg::Resize, Msg::Resize, Msg::Pages(1)] {
+            tx.send(msg).unwrap();
+        }
+        let mut deferred = VecDeque::new();
+        let msg = coalesce(Msg::Resize, &rx, &mut deferred, Duration::ZERO);
+        assert!(matches!(msg, Msg::Resize));
+        assert!(matches!(deferred.pop_front(), Some(Msg::Pages(1))));
+        assert!(deferred.is_empty());
+    }
+
+    #[test]
+    fn nothing_jumps_the_queue() {
+        let (tx, rx) = mpsc::channel();
+        tx.send(Msg::Pages(1)).unwrap();
+        let mut deferred = VecDeque::from([Msg::Quit]);
+        let msg = coalesce(Msg::Pages(1), &rx, &mut deferred, Duration::ZERO);
+        assert!(matches!(msg, Msg::Pages(1)));
+        assert!(matches!(rx.try_recv(), Ok(Msg::Pages(1))));
+    }
+}
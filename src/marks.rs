@@ -0,0 +1,78 @@
+//! Marks set with `m` and a letter on a page of a document, to go back to
+//! with `'` and the letter, like a figure and the text that refers to it.
+
+use std::collections::BTreeMap;
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::state;
+
+const FILE: &str = "marks.json";
+
+#[derive(Debug, Default)]
+pub struct Marks {
+    /// The page (counted from 0) each letter marks, for each document
+    map: BTreeMap<String, BTreeMap<char, usize>>,
+}
+
+impl Marks {
+    pub fn load() -> Result<Marks> {
+        let mut marks = Marks::default();
+        if let Value::Object(files) = state::load(FILE)? {
+            for (file, letters) in files {
+                let set = letters
+                    .as_object()
+                    .map(|l| {
+                        l.iter()
+                            .filter_map(|(letter, page)| {
+                                Some((letter.chars().next()?, page.as_u64()? as usize))
+                            })
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                marks.map.insert(file, set);
+            }
+        }
+        Ok(marks)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let value: serde_json::Map<String, Value> = self
+            .map
+            .iter()
+            .filter(|(_, letters)| !letters.is_empty())
+            .map(|(file, letters)| {
+                let letters: serde_json::Map<String, Value> = letters
+                    .iter()
+                    .map(|(letter, &page)| (letter.to_string(), page.into()))
+                    .collect();
+                (file.clone(), Value::Object(letters))
+            })
+            .collect();
+        state::save(FILE, &Value::Object(value))
+    }
+
+    pub fn get(&self, file: &str, letter: char) -> Option<usize> {
+        self.map.get(&state::key(file))?.get(&letter).copied()
+    }
+
+    pub fn set(&mut self, file: &str, letter: char, page: usize) {
+        self.map
+            .entry(state::key(file))
+            .or_default()
+            .insert(letter, page);
+    }
+
+    /// Carry marks over to a file's new name. `old` no longer exists, so it
+    /// has to be given as the absolute path it had.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(letters) = self.map.remove(old) {
+            self.map.insert(state::key(new), letters);
+        }
+    }
+
+    pub fn forget(&mut self, key: &str) {
+        self.map.remove(key);
+    }
+}
@@ -0,0 +1,313 @@
+//! Document information dictionary fields, and filling them into templates
+//! like `{author} - {title}.pdf`, or making up a name from them (and the
+//! first page, where they're missing).
+
+use std::path::Path;
+
+use anyhow::{bail, Result};
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::query::Query;
+use crate::render;
+
+#[derive(Clone, Debug, Default)]
+pub struct Metadata {
+    pub title: String,
+    pub author: String,
+    pub subject: String,
+    /// The raw creation date, in the PDF `D:YYYYMMDDHHmmSS` format
+    pub created: String,
+}
+
+impl Metadata {
+    pub fn read(document: &PdfDocument) -> Metadata {
+        let metadata = document.metadata();
+        let get = |tag| {
+            metadata
+                .get(tag)
+                .map(|t| t.value().trim().to_string())
+                .unwrap_or_default()
+        };
+        Metadata {
+            title: get(PdfDocumentMetadataTagType::Title),
+            author: get(PdfDocumentMetadataTagType::Author),
+            subject: get(PdfDocumentMetadataTagType::Subject),
+            created: get(PdfDocumentMetadataTagType::CreationDate),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "title": self.title,
+            "author": self.author,
+            "subject": self.subject,
+            "created": self.created,
+        })
+    }
+
+    fn from_json(value: &Value) -> Metadata {
+        let get = |field: &str| value[field].as_str().unwrap_or_default().to_string();
+        Metadata {
+            title: get("title"),
+            author: get("author"),
+            subject: get("subject"),
+            created: get("created"),
+        }
+    }
+
+    /// The four digit year from the creation date, if it has one
+    pub fn year(&self) -> String {
+        let date = self.created.trim_start_matches("D:");
+        match date.get(..4) {
+            Some(y) if y.chars().all(|c| c.is_ascii_digit()) => y.to_string(),
+            _ => String::new(),
+        }
+    }
+}
+
+/// The metadata of `file` and the title on its first page (see
+/// `first_page_title`), as the renderer reads them
+pub fn naming(file: &str) -> Result<(Metadata, String)> {
+    let answer = render::ask(file, &Query::Naming)?;
+    let first_page = answer["first_page"].as_str().unwrap_or_default();
+    Ok((
+        Metadata::from_json(&answer["metadata"]),
+        first_page.to_string(),
+    ))
+}
+
+/// Characters that can't (or shouldn't) appear in a file name
+fn clean(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' => '-',
+            c if c.is_control() => ' ',
+            c => c,
+        })
+        .collect::<String>()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Fill `{title}`, `{author}`, `{subject}`, `{year}` and `{name}` (the
+/// current file name without its extension) into `template`. Fields the
+/// document doesn't have are an error, rather than quietly producing names
+/// like " - .pdf".
+pub fn expand(template: &str, metadata: &Metadata, file: &str) -> Result<String> {
+    let name = Path::new(file)
+        .file_stem()
+        .map(|s| s.to_string_lossy().to_string())
+        .unwrap_or_default();
+    let fields = [
+        ("title", metadata.title.clone()),
+        ("author", metadata.author.clone()),
+        ("subject", metadata.subject.clone()),
+        ("year", metadata.year()),
+        ("name", name),
+    ];
+
+    let mut result = template.to_string();
+    for (field, value) in fields.iter() {
+        let placeholder = format!("{{{}}}", field);
+        if result.contains(&placeholder) {
+            let value = clean(value);
+            if value.is_empty() {
+                bail!("This document has no {}", field);
+            }
+            result = result.replace(&placeholder, &value);
+        }
+    }
+    Ok(result)
+}
+
+/// Titles much longer than this are cut short at a word, so the name stays
+/// manageable
+const LONGEST_TITLE: usize = 100;
+
+/// Whether `title` is a real title rather than what some programs leave
+/// there, like the name of the file the PDF was made from
+fn plausible(title: &str) -> bool {
+    let lower = title.to_lowercase();
+    let leftover = [".dvi", ".pdf", ".tex", ".doc", ".docx", ".ps"]
+        .iter()
+        .any(|ext| lower.ends_with(ext))
+        || lower.starts_with("microsoft word - ")
+        || lower == "untitled";
+    !leftover && title.chars().filter(|c| c.is_alphabetic()).count() >= 3
+}
+
+/// The text on the first page set in the biggest type, which is the title
+/// on most papers. Rotated text is left out, as arXiv's stamp down the side
+/// is bigger still.
+pub fn first_page_title(document: &PdfDocument) -> String {
+    let page = match document.pages().get(0) {
+        Ok(page) => page,
+        Err(_) => return String::new(),
+    };
+    let text = match page.text() {
+        Ok(text) => text,
+        Err(_) => return String::new(),
+    };
+    let chars: Vec<(char, f32)> = text
+        .chars()
+        .iter()
+        .filter(|c| c.angle_degrees().map_or(true, |a| a.abs() < 1.0))
+        .filter_map(|c| Some((c.unicode_char()?, c.scaled_font_size().value)))
+        .collect();
+    let biggest = chars
+        .iter()
+        .filter(|(c, _)| !c.is_whitespace())
+        .map(|&(_, size)| size)
+        .fold(0.0, f32::max);
+    let title: String = chars
+        .iter()
+        .map(
+            |&(c, size)| match size >= biggest - 0.5 && !c.is_whitespace() {
+                true => c,
+                false => ' ',
+            },
+        )
+        .collect();
+    clean(&title)
+}
+
+/// The surname of whoever is named first in `authors`, which may be like
+/// "Ada Lovelace and Charles Babbage" or "Lovelace, A.; Babbage, C."
+fn first_surname(authors: &str) -> String {
+    let first = authors
+        .split([';', '&'])
+        .flat_map(|a| a.split(" and "))
+        .map(str::trim)
+        .find(|a| !a.is_empty())
+        .unwrap_or_default();
+    match first.split_once(',') {
+        Some((surname, _)) => surname.trim().to_string(),
+        None => first
+            .split_whitespace()
+            .last()
+            .unwrap_or_default()
+            .to_string(),
+    }
+}
+
+/// A file name for the document like `Lovelace 1843 - Sketch of the
+/// Analytical Engine.pdf`, from its metadata, or the biggest text on its
+/// first page (`first_page`) for the title if the metadata hasn't a real
+/// one. None if there's no title to be had.
+pub fn suggest(metadata: &Metadata, first_page: &str) -> Option<String> {
+    let title = match plausible(&metadata.title) {
+        true => clean(&metadata.title),
+        false => clean(first_page),
+    };
+    if !plausible(&title) {
+        return None;
+    }
+    let mut title = title;
+    if title.chars().count() > LONGEST_TITLE {
+        let cut: String = title.chars().take(LONGEST_TITLE).collect();
+        title = match cut.rsplit_once(' ') {
+            Some((words, _)) => words.to_string(),
+            None => cut,
+        };
+    }
+    let by = [clean(&first_surname(&metadata.author)), metadata.year()]
+        .into_iter()
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    Some(match by.is_empty() {
+        true => format!("{}.pdf", title),
+        false => format!("{} - {}.pdf", by, title),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lovelace() -> Metadata {
+        Metadata {
+            title: "Sketch of the Analytical Engine".to_string(),
+            author: "Ada Lovelace".to_string(),
+            subject: String::new(),
+            created: "D:18431001120000".to_string(),
+        }
+    }
+
+    #[test]
+    fn fields_are_filled_in() {
+        let name = expand("{author} - {title}.pdf", &lovelace(), "papers/old.pdf").unwrap();
+        assert_eq!(name, "Ada Lovelace - Sketch of the Analytical Engine.pdf");
+        let name = expand("{name} ({year}).pdf", &lovelace(), "papers/old.pdf").unwrap();
+        assert_eq!(name, "old (1843).pdf");
+    }
+
+    #[test]
+    fn fields_are_cleaned() {
+        let metadata = Metadata {
+            title: "Either/Or:\n a  fragment".to_string(),
+            ..Metadata::default()
+        };
+        let name = expand("{title}.pdf", &metadata, "a.pdf").unwrap();
+        assert_eq!(name, "Either-Or- a fragment.pdf");
+    }
+
+    #[test]
+    fn missing_fields_are_an_error() {
+        assert!(expand("{subject}.pdf", &lovelace(), "a.pdf").is_err());
+        assert_eq!(
+            expand("same.pdf", &lovelace(), "a.pdf").unwrap(),
+            "same.pdf"
+        );
+    }
+
+    #[test]
+    fn years() {
+        assert_eq!(lovelace().year(), "1843");
+        let metadata = Metadata {
+            created: "yesterday".to_string(),
+            ..Metadata::default()
+        };
+        assert_eq!(metadata.year(), "");
+    }
+
+    #[test]
+    fn surnames() {
+        assert_eq!(
+            first_surname("Ada Lovelace and Charles Babbage"),
+            "Lovelace"
+        );
+        assert_eq!(first_surname("Lovelace, A.; Babbage, C."), "Lovelace");
+        assert_eq!(first_surname(""), "");
+    }
+
+    #[test]
+    fn suggested_names() {
+        assert_eq!(
+            suggest(&lovelace(), "").unwrap(),
+            "Lovelace 1843 - Sketch of the Analytical Engine.pdf"
+        );
+        let metadata = Metadata {
+            title: "paper.dvi".to_string(),
+            ..Metadata::default()
+        };
+        assert_eq!(
+            suggest(&metadata, "A Real Title").unwrap(),
+            "A Real Title.pdf"
+        );
+        assert_eq!(suggest(&metadata, "12"), None);
+    }
+
+    #[test]
+    fn long_titles_are_cut_at_a_word() {
+        let metadata = Metadata {
+            title: "word ".repeat(30),
+            ..Metadata::default()
+        };
+        let expected = format!("{}.pdf", vec!["word"; 20].join(" "));
+        assert_eq!(suggest(&metadata, "").unwrap(), expected);
+    }
+}
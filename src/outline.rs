@@ -0,0 +1,213 @@
+//! Editing a document's outline (its bookmarks, or table of contents), for
+//! scans and generated PDFs that come without one. pdfium can read outlines
+//! but not write them, so saving goes through `outline_command`, pdftk by
+//! default, which is given the whole outline in its `update_info` format.
+
+use std::fs;
+use std::process::Stdio;
+
+use anyhow::{bail, Result};
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::query::{self, Query};
+use crate::temp::TempDir;
+use crate::{atomic, external, render};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub title: String,
+    /// 1 for the top level, 2 for the entries under one of those and so on
+    pub level: usize,
+    pub page: usize,
+}
+
+impl Entry {
+    pub fn to_json(&self) -> Value {
+        json!({ "title": self.title, "level": self.level, "page": self.page })
+    }
+
+    fn from_json(value: &Value) -> Entry {
+        Entry {
+            title: value["title"].as_str().unwrap_or_default().to_string(),
+            level: value["level"].as_u64().unwrap_or(1) as usize,
+            page: value["page"].as_u64().unwrap_or(0) as usize,
+        }
+    }
+}
+
+fn walk(bookmark: Option<PdfBookmark>, level: usize, entries: &mut Vec<Entry>) {
+    let mut next = bookmark;
+    while let Some(bookmark) = next {
+        // Only entries that go somewhere in this document can be kept
+        let page = bookmark.action().and_then(|action| {
+            let destination = action.as_local_destination_action()?.destination().ok()?;
+            destination.page_index().ok()
+        });
+        if let Some(page) = page {
+            entries.push(Entry {
+                title: bookmark.title().unwrap_or_default(),
+                level,
+                page: page as usize,
+            });
+        }
+        walk(bookmark.first_child(), level + 1, entries);
+        next = bookmark.next_sibling();
+    }
+}
+
+/// The outline of `file` in order, children after their parents
+pub fn read(file: &str) -> Result<Vec<Entry>> {
+    let answer = render::ask(file, &Query::Outline)?;
+    Ok(query::list(&answer)?.iter().map(Entry::from_json).collect())
+}
+
+/// `read`, for the renderer
+pub fn entries_in(document: &PdfDocument) -> Vec<Entry> {
+    let mut entries = vec![];
+    walk(document.bookmarks().root(), 1, &mut entries);
+    entries
+}
+
+/// The first page of the next section after `page` (or, going back, of the
+/// one it's in, or the one before if it's that section's first page), or
+/// of the next chapter, an entry at the top level of the outline
+pub fn boundary(entries: &[Entry], page: usize, forward: bool, chapter: bool) -> Option<usize> {
+    let top = entries.iter().map(|e| e.level).min()?;
+    let pages = entries
+        .iter()
+        .filter(|e| !chapter || e.level == top)
+        .map(|e| e.page);
+    match forward {
+        true => pages.filter(|&p| p > page).min(),
+        false => pages.filter(|&p| p < page).max(),
+    }
+}
+
+/// Add an entry for `page`, after the ones for pages before it and this one
+pub fn insert(entries: &mut Vec<Entry>, entry: Entry) {
+    let at = entries
+        .iter()
+        .position(|e| e.page > entry.page)
+        .unwrap_or(entries.len());
+    entries.insert(at, entry);
+}
+
+/// A title with a `>` in front for each level below the top, as `:outline
+/// add` takes it, as a title and level
+pub fn parse_title(title: &str) -> (String, usize) {
+    let trimmed = title.trim_start_matches('>');
+    let level = title.len() - trimmed.len() + 1;
+    (trimmed.trim().to_string(), level)
+}
+
+/// The outline as pdftk's `update_info` data
+fn info(entries: &[Entry]) -> String {
+    let mut levels = vec![];
+    let mut out = String::new();
+    for entry in entries {
+        // pdftk refuses to skip a level, so an entry can only go one deeper
+        // than the one before it
+        levels.truncate(entry.level.saturating_sub(1));
+        let level = levels.len() + 1;
+        levels.push(level);
+        out.push_str(&format!(
+            "BookmarkBegin\nBookmarkTitle: {}\nBookmarkLevel: {}\nBookmarkPageNumber: {}\n",
+            entry.title.replace('\n', " "),
+            level,
+            entry.page + 1
+        ));
+    }
+    out
+}
+
+/// Replace the outline of `file` with `entries`, keeping a backup of how it
+/// was
+pub fn write(file: &str, entries: &[Entry], config: &Config) -> Result<()> {
+    let dir = TempDir::new("termpdf-outline")?;
+    let data = dir.write("outline.txt", info(entries).as_bytes())?;
+    let output = dir.path().join("outline.pdf");
+    let (data_str, output_str) = (
+        data.to_string_lossy().to_string(),
+        output.to_string_lossy().to_string(),
+    );
+
+    let status = external::command(
+        &config.outline_command,
+        &[("file", file), ("data", &data_str), ("output", &output_str)],
+    )?
+    .stdin(Stdio::null())
+    .stdout(Stdio::null())
+    .stderr(Stdio::null())
+    .status()?;
+    let written = fs::read(&output);
+    if !status.success() {
+        bail!("'{}' exited with {}", config.outline_command, status);
+    }
+    atomic::write(file, written?, config.backups)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title: &str, level: usize, page: usize) -> Entry {
+        Entry {
+            title: title.to_string(),
+            level,
+            page,
+        }
+    }
+
+    fn entries() -> Vec<Entry> {
+        vec![
+            entry("1", 1, 0),
+            entry("1.1", 2, 3),
+            entry("2", 1, 10),
+            entry("2.1", 2, 12),
+        ]
+    }
+
+    #[test]
+    fn sections_forward() {
+        assert_eq!(boundary(&entries(), 0, true, false), Some(3));
+        assert_eq!(boundary(&entries(), 0, true, true), Some(10));
+        assert_eq!(boundary(&entries(), 12, true, false), None);
+    }
+
+    #[test]
+    fn sections_back() {
+        assert_eq!(boundary(&entries(), 11, false, false), Some(10));
+        assert_eq!(boundary(&entries(), 10, false, false), Some(3));
+        assert_eq!(boundary(&entries(), 12, false, true), Some(10));
+        assert_eq!(boundary(&entries(), 10, false, true), Some(0));
+        assert_eq!(boundary(&[], 10, false, true), None);
+    }
+
+    #[test]
+    fn inserted_after_the_same_page() {
+        let mut list = vec![entry("a", 1, 0), entry("b", 1, 5)];
+        insert(&mut list, entry("c", 1, 5));
+        insert(&mut list, entry("d", 1, 2));
+        let titles: Vec<&str> = list.iter().map(|e| e.title.as_str()).collect();
+        assert_eq!(titles, ["a", "d", "b", "c"]);
+    }
+
+    #[test]
+    fn titles() {
+        assert_eq!(parse_title(">> Methods "), ("Methods".to_string(), 3));
+        assert_eq!(parse_title("Intro"), ("Intro".to_string(), 1));
+    }
+
+    #[test]
+    fn levels_arent_skipped_for_pdftk() {
+        let info = info(&[entry("a", 1, 0), entry("b\nc", 3, 1), entry("d", 2, 2)]);
+        let levels: Vec<&str> = info
+            .lines()
+            .filter_map(|l| l.strip_prefix("BookmarkLevel: "))
+            .collect();
+        assert_eq!(levels, ["1", "2", "2"]);
+        assert!(info.contains("BookmarkTitle: b c\nBookmarkLevel: 2\nBookmarkPageNumber: 2\n"));
+    }
+}
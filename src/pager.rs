@@ -0,0 +1,132 @@
+//! A full screen, scrollable text view drawn over the page, for lists and
+//! the output of external commands.
+
+use std::io::{stdout, Write};
+use std::sync::mpsc::Receiver;
+
+use anyhow::Result;
+use termion::event::Key;
+
+use crate::{term, Msg};
+
+pub struct Pager {
+    title: String,
+    lines: Vec<String>,
+    top: usize,
+    /// Output is still arriving
+    running: bool,
+    /// The end of the last line hasn't arrived yet
+    partial: bool,
+}
+
+impl Pager {
+    pub fn new(title: &str, lines: Vec<String>) -> Pager {
+        Pager {
+            title: title.to_string(),
+            lines,
+            top: 0,
+            running: false,
+            partial: false,
+        }
+    }
+
+    /// A pager for output that arrives while it's open, as `Msg::Output`
+    /// and then `Msg::OutputEnd`
+    pub fn streaming(title: &str) -> Pager {
+        Pager {
+            running: true,
+            ..Pager::new(title, vec![])
+        }
+    }
+
+    /// Add output, wrapped to the width of the terminal, keeping the end in
+    /// view if it already was
+    fn push(&mut self, text: &str) {
+        let width = termion::terminal_size()
+            .map(|(cols, _)| cols.saturating_sub(1).max(1) as usize)
+            .unwrap_or(80);
+        let following = self.top >= self.max_top();
+        for (i, piece) in text.split('\n').enumerate() {
+            if i > 0 || !self.partial || self.lines.is_empty() {
+                self.lines.push(String::new());
+            }
+            for c in piece.chars() {
+                let line = self.lines.last_mut().unwrap();
+                if line.chars().count() >= width {
+                    self.lines.push(String::new());
+                }
+                self.lines.last_mut().unwrap().push(c);
+            }
+        }
+        self.partial = !text.ends_with('\n');
+        if !self.partial {
+            self.lines.pop();
+        }
+        if following {
+            self.top = self.max_top();
+        }
+    }
+
+    fn height() -> usize {
+        termion::terminal_size()
+            .map(|(_, rows)| rows.saturating_sub(1) as usize)
+            .unwrap_or(24)
+            .max(1)
+    }
+
+    fn max_top(&self) -> usize {
+        self.lines.len().saturating_sub(Pager::height())
+    }
+
+    pub fn draw(&self) -> Result<()> {
+        let (cols, _) = termion::terminal_size()?;
+        let height = Pager::height();
+        let mut stdout = stdout();
+        write!(stdout, "{}", termion::clear::All)?;
+        for (i, line) in self.lines.iter().skip(self.top).take(height).enumerate() {
+            let line: String = line.chars().take(cols.saturating_sub(1) as usize).collect();
+            write!(stdout, "{}{}", termion::cursor::Goto(1, i as u16 + 1), line)?;
+        }
+        stdout.flush()?;
+        let end = (self.top + height).min(self.lines.len());
+        term::status_line(&format!(
+            "{}{}  {}-{}/{}  j/k to scroll, q to close",
+            self.title,
+            if self.running { " (running)" } else { "" },
+            (self.top + 1).min(end),
+            end,
+            self.lines.len()
+        ))
+    }
+
+    /// Handle keys until the pager is closed. Anything else that arrives
+    /// meanwhile is handed back to be dealt with afterwards.
+    pub fn run(&mut self, rx: &Receiver<Msg>, deferred: &mut Vec<Msg>) -> Result<()> {
+        self.draw()?;
+        for msg in rx {
+            match msg {
+                Msg::Key(key) => {
+                    let height = Pager::height();
+                    match key {
+                        Key::Char('q') | Key::Esc | Key::Char('\n') => return Ok(()),
+                        Key::Char('j') | Key::Down => self.top += 1,
+                        Key::Char('k') | Key::Up => self.top = self.top.saturating_sub(1),
+                        Key::Char(' ') | Key::PageDown | Key::Ctrl('f') => self.top += height,
+                        Key::Char('b') | Key::PageUp | Key::Ctrl('b') => {
+                            self.top = self.top.saturating_sub(height)
+                        }
+                        Key::Char('g') | Key::Home => self.top = 0,
+                        Key::Char('G') | Key::End => self.top = self.max_top(),
+                        _ => continue,
+                    }
+                    self.top = self.top.min(self.max_top());
+                }
+                Msg::Output(text) if self.running => self.push(&text),
+                Msg::OutputEnd if self.running => self.running = false,
+                other => deferred.push(other),
+            }
+            self.draw()?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,181 @@
+//! Zooming in, and poster mode, for pages too big to read fitted to the
+//! window: maps, posters and the like. The window shows one part of the page
+//! at a time, rendered on its own at the zoom it's looked at with, so
+//! however big the page is only a window's worth of it is ever in memory.
+//! The whole page is shown small in a corner, with a rectangle where the
+//! view is.
+//!
+//! Like the magnifier, where it is is kept as fractions of the page.
+
+use image::{imageops, DynamicImage, Rgba};
+use termion::event::Key;
+
+use crate::render::Fractions;
+
+pub enum PosterResult {
+    Pending,
+    /// The view moved or zoomed, so it has to be rendered again
+    Moved,
+    /// Turn this many pages, staying zoomed in
+    Turn(isize),
+    Close,
+}
+
+#[derive(Debug)]
+pub struct Poster {
+    /// The middle of the view, as fractions of the page
+    pub x: f64,
+    pub y: f64,
+    /// How many times the size the page is when it fits the window
+    pub zoom: f64,
+    /// The whole page, small, the right way up
+    overview: DynamicImage,
+    /// The pixels the view is shown in
+    window: (u32, u32),
+}
+
+/// How much of the view a small step moves it by
+const STEP: f64 = 0.25;
+const MAX_ZOOM: f64 = 64.0;
+/// How much of the view's width and height the overview takes up at most
+pub const OVERVIEW: f64 = 0.3;
+/// Between the overview and the edge of the view
+const INSET: u32 = 8;
+
+const FRAME: Rgba<u8> = Rgba([80, 80, 80, 255]);
+const VIEW: Rgba<u8> = Rgba([40, 90, 220, 255]);
+
+impl Poster {
+    /// Looking at `(x, y)` on the page `overview` is of, at `zoom` times
+    /// the size that fits in `window`
+    pub fn new(
+        overview: DynamicImage,
+        window: (u32, u32),
+        zoom: f64,
+        (x, y): (f64, f64),
+    ) -> Poster {
+        let mut poster = Poster {
+            x,
+            y,
+            zoom: zoom.clamp(1.0, MAX_ZOOM),
+            overview,
+            window,
+        };
+        let (l, t, r, b) = poster.view();
+        (poster.x, poster.y) = ((l + r) / 2.0, (t + b) / 2.0);
+        poster
+    }
+
+    /// What the keys do, after `view`, what it's zoomed in as
+    pub fn help(&self, view: &str) -> String {
+        format!(
+            "{}: hjkl pan  HJKL pan more  + - zoom  Space PgUp turn  f view  Esc fit page",
+            view
+        )
+    }
+
+    /// The page's size in pixels at this zoom
+    fn size(&self) -> (f64, f64) {
+        let (w, h) = (
+            self.overview.width().max(1) as f64,
+            self.overview.height().max(1) as f64,
+        );
+        let fit = (self.window.0 as f64 / w).min(self.window.1 as f64 / h);
+        (w * fit * self.zoom, h * fit * self.zoom)
+    }
+
+    /// The part of the page in view
+    pub fn view(&self) -> Fractions {
+        let (width, height) = self.size();
+        let w = (self.window.0 as f64 / width).min(1.0);
+        let h = (self.window.1 as f64 / height).min(1.0);
+        let left = (self.x - w / 2.0).clamp(0.0, 1.0 - w);
+        let top = (self.y - h / 2.0).clamp(0.0, 1.0 - h);
+        (left, top, left + w, top + h)
+    }
+
+    /// The pixels the view is rendered with
+    pub fn tile_size(&self) -> (u32, u32) {
+        let (width, height) = self.size();
+        let (l, t, r, b) = self.view();
+        (
+            (width * (r - l)).round().max(1.0) as u32,
+            (height * (b - t)).round().max(1.0) as u32,
+        )
+    }
+
+    pub fn key(&mut self, key: Key) -> PosterResult {
+        let (l, t, r, b) = self.view();
+        let (w, h) = (r - l, b - t);
+        match key {
+            Key::Char('h') | Key::Left => self.x -= w * STEP,
+            Key::Char('l') | Key::Right => self.x += w * STEP,
+            Key::Char('k') | Key::Up => self.y -= h * STEP,
+            Key::Char('j') | Key::Down => self.y += h * STEP,
+            Key::Char('H') => self.x -= w,
+            Key::Char('L') => self.x += w,
+            Key::Char('K') => self.y -= h,
+            Key::Char('J') => self.y += h,
+            Key::Char('+') | Key::Char('=') => self.zoom = (self.zoom * 1.5).min(MAX_ZOOM),
+            // Zoomed right out is just the page again
+            Key::Char('-') if self.zoom / 1.5 < 1.01 => return PosterResult::Close,
+            Key::Char('-') => self.zoom /= 1.5,
+            Key::Char(' ') | Key::PageDown => return PosterResult::Turn(1),
+            Key::PageUp => return PosterResult::Turn(-1),
+            Key::Esc | Key::Char('q') | Key::Char('0') | Key::Char('P') => {
+                return PosterResult::Close
+            }
+            _ => return PosterResult::Pending,
+        }
+        // Kept to where the view can go, so moving back from past an edge
+        // moves straight away
+        let (l, t, r, b) = self.view();
+        (self.x, self.y) = ((l + r) / 2.0, (t + b) / 2.0);
+        PosterResult::Moved
+    }
+
+    /// `tile`, the view rendered, with the overview over its bottom right
+    /// corner
+    pub fn draw(&self, tile: &DynamicImage) -> DynamicImage {
+        let mut image = tile.to_rgba8();
+        let (width, height) = image.dimensions();
+        let box_w = (width as f64 * OVERVIEW) as u32;
+        let box_h = (height as f64 * OVERVIEW) as u32;
+        // Nowhere to put it when the whole page is in view anyway
+        if self.zoom <= 1.0 || box_w < INSET * 2 || box_h < INSET * 2 {
+            return DynamicImage::ImageRgba8(image);
+        }
+        let overview = self
+            .overview
+            .resize(box_w, box_h, imageops::FilterType::Triangle)
+            .to_rgba8();
+        let (ow, oh) = overview.dimensions();
+        let (x, y) = (width - ow - INSET, height - oh - INSET);
+        imageops::replace(&mut image, &overview, x as i64, y as i64);
+
+        let mut outline = |(l, t, r, b): (u32, u32, u32, u32), thickness: u32, colour| {
+            for py in t..b.min(height) {
+                for px in l..r.min(width) {
+                    let edge = px < l + thickness
+                        || px + thickness >= r
+                        || py < t + thickness
+                        || py + thickness >= b;
+                    if edge {
+                        image.put_pixel(px, py, colour);
+                    }
+                }
+            }
+        };
+        outline((x - 1, y - 1, x + ow + 1, y + oh + 1), 1, FRAME);
+        let (l, t, r, b) = self.view();
+        let at = |f: f64, size: u32, start: u32| start + (f * size as f64).round() as u32;
+        let view = (
+            at(l, ow, x),
+            at(t, oh, y),
+            at(r, ow, x).max(at(l, ow, x) + 4),
+            at(b, oh, y).max(at(t, oh, y) + 4),
+        );
+        outline(view, 2, VIEW);
+        DynamicImage::ImageRgba8(image)
+    }
+}
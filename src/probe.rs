@@ -0,0 +1,215 @@
+//! Asking the terminal what it can do. Terminals answer escape sequences
+//! with replies on standard input, which can take a while (or never come,
+//! over a slow link or from one that ignores them), so the answers are kept
+//! in `terminals.json` in the state directory, for each `$TERM` and
+//! `$TERM_PROGRAM`, and only asked again for a terminal we haven't seen.
+
+use std::env;
+use std::io::{stdin, stdout};
+use std::os::unix::io::AsRawFd;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use termion::raw::IntoRawMode;
+
+use crate::graphics::Protocol;
+use crate::state;
+
+const FILE: &str = "terminals.json";
+
+/// How long to wait for the terminal to answer
+const PATIENCE: Duration = Duration::from_millis(500);
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capabilities {
+    /// The protocol the terminal answered to, None if it didn't say
+    pub protocol: Option<Protocol>,
+    /// The size of a cell in pixels, for terminals (or multiplexers) that
+    /// don't give the window's size in pixels
+    pub cell: Option<(f64, f64)>,
+    /// Whether it has SGR mouse reporting, None if it didn't say
+    pub mouse: Option<bool>,
+    /// What it says it is (XTVERSION), like "WezTerm 20240203"
+    pub name: Option<String>,
+}
+
+static CAPABILITIES: OnceLock<Capabilities> = OnceLock::new();
+
+/// What terminal this is, as far as remembering it goes
+fn terminal() -> String {
+    let var = |name| env::var(name).unwrap_or_default();
+    format!(
+        "{} {} {}",
+        var("TERM"),
+        var("TERM_PROGRAM"),
+        var("TERM_PROGRAM_VERSION")
+    )
+}
+
+/// Find out what the terminal can do, from last time if it's the same
+/// terminal
+pub fn load() -> Capabilities {
+    CAPABILITIES
+        .get_or_init(|| {
+            let mut known = match state::load(FILE) {
+                Ok(Value::Object(known)) => known,
+                _ => Default::default(),
+            };
+            let terminal = terminal();
+            if let Some(capabilities) = known.get(&terminal).map(from_json) {
+                return capabilities;
+            }
+            // Not a terminal, so nothing will answer: don't remember that
+            if !termion::is_tty(&stdin()) || !termion::is_tty(&stdout()) {
+                return Capabilities::default();
+            }
+            let capabilities = probe();
+            known.insert(terminal, to_json(&capabilities));
+            let _ = state::save(FILE, &Value::Object(known));
+            capabilities
+        })
+        .clone()
+}
+
+/// The cell size found by `load`, if it's been called
+pub fn cell_size() -> Option<(f64, f64)> {
+    CAPABILITIES.get().and_then(|c| c.cell)
+}
+
+fn from_json(value: &Value) -> Capabilities {
+    let protocol = value["protocol"]
+        .as_str()
+        .and_then(Protocol::parse)
+        .flatten();
+    let cell = match (value["cell_width"].as_f64(), value["cell_height"].as_f64()) {
+        (Some(w), Some(h)) if w > 0.0 && h > 0.0 => Some((w, h)),
+        _ => None,
+    };
+    Capabilities {
+        protocol,
+        cell,
+        mouse: value["mouse"].as_bool(),
+        name: value["name"].as_str().map(str::to_string),
+    }
+}
+
+fn to_json(capabilities: &Capabilities) -> Value {
+    json!({
+        "protocol": capabilities.protocol.map(|p| p.name()),
+        "cell_width": capabilities.cell.map(|c| c.0),
+        "cell_height": capabilities.cell.map(|c| c.1),
+        "mouse": capabilities.mouse,
+        "name": capabilities.name,
+    })
+}
+
+/// Ask whether kitty images work, what the terminal is, how big a cell is
+/// (or the window, for terminals that only say that) and whether it has SGR
+/// mouse reports, then ask for the device attributes, which say whether
+/// sixels work and which every terminal answers, so we know when it has said
+/// all it's going to. `load` keeps the answers; this asks again.
+pub fn probe() -> Capabilities {
+    let raw = match stdout().into_raw_mode() {
+        Ok(raw) => raw,
+        Err(_) => return Capabilities::default(),
+    };
+    let query =
+        "\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[>0q\x1b[16t\x1b[14t\x1b[?1006$p\x1b[c";
+    if crate::term::write_sequence(query.as_bytes()).is_err() {
+        return Capabilities::default();
+    }
+
+    let fd = stdin().as_raw_fd();
+    let mut reply = vec![];
+    let until = Instant::now() + PATIENCE;
+    while !answered(&reply) {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            break;
+        }
+        let mut pfd = libc::pollfd {
+            fd,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one pollfd, which lives across the call
+        if unsafe { libc::poll(&mut pfd, 1, left.as_millis() as libc::c_int) } <= 0 {
+            continue;
+        }
+        let mut buf = [0u8; 256];
+        // SAFETY: `buf` is valid for its length
+        let n = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+        if n <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..n as usize]);
+    }
+    drop(raw);
+    parse(&String::from_utf8_lossy(&reply))
+}
+
+/// Whether the device attributes (`ESC [ ? ... c`) have come back
+fn answered(reply: &[u8]) -> bool {
+    let text = String::from_utf8_lossy(reply);
+    match text.rfind("\x1b[?") {
+        Some(start) => text[start..].contains('c'),
+        None => false,
+    }
+}
+
+/// The device attributes: `ESC [ ? 62 ; 4 ; 22 c`, say
+fn attributes(reply: &str) -> Vec<&str> {
+    match reply.rfind("\x1b[?") {
+        Some(start) => reply[start + 3..]
+            .split('c')
+            .next()
+            .unwrap_or_default()
+            .split(';')
+            .collect(),
+        None => vec![],
+    }
+}
+
+fn parse(reply: &str) -> Capabilities {
+    let protocol = if reply.contains("\x1b_Gi=31;OK") {
+        Some(Protocol::Kitty)
+    } else if attributes(reply).contains(&"4") {
+        Some(Protocol::Sixel)
+    } else if answered(reply.as_bytes()) {
+        // It answered everything it was going to, and nothing about images
+        Some(Protocol::Text)
+    } else {
+        None
+    };
+    // ESC [ 6 ; height ; width t for a cell, and ESC [ 4 ; ... t for the
+    // window, which comes to the same thing divided by the cells in it
+    let size = |reply: &str, prefix: &str| {
+        let rest = &reply[reply.find(prefix)? + prefix.len()..];
+        let (numbers, _) = rest.split_once('t')?;
+        let (h, w) = numbers.split_once(';')?;
+        let (w, h): (f64, f64) = (w.parse().ok()?, h.parse().ok()?);
+        (w > 0.0 && h > 0.0).then_some((w, h))
+    };
+    let cell = size(reply, "\x1b[6;").or_else(|| {
+        let (w, h) = size(reply, "\x1b[4;")?;
+        let (cols, rows) = termion::terminal_size().ok()?;
+        (cols > 0 && rows > 0).then(|| (w / cols as f64, h / rows as f64))
+    });
+    // ESC [ ? 1006 ; status $ y, where 0 means it doesn't know the mode
+    let mouse = reply.find("\x1b[?1006;").and_then(|start| {
+        let status = reply[start + 8..].split_once("$y")?.0;
+        Some(status != "0")
+    });
+    // ESC P > | name ESC \
+    let name = reply.find("\x1bP>|").and_then(|start| {
+        let name = reply[start + 4..].split_once('\x1b')?.0;
+        Some(name.to_string()).filter(|n| !n.is_empty())
+    });
+    Capabilities {
+        protocol,
+        cell,
+        mouse,
+        name,
+    }
+}
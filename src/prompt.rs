@@ -0,0 +1,71 @@
+//! The line along the bottom of the screen used to type commands and answer
+//! questions.
+
+use termion::event::Key;
+
+use crate::Msg;
+
+pub enum Prompt {
+    /// An ex-style command being typed after `:`
+    Command(String),
+    /// Text to search for being typed after `/`
+    Search(String),
+    /// A yes/no question guarding a destructive action, which is carried out
+    /// if the answer is yes
+    Confirm(String, Msg),
+}
+
+pub enum PromptResult {
+    Pending,
+    Cancel,
+    Submit(String),
+    Confirmed(Msg),
+}
+
+impl Prompt {
+    pub fn confirm(question: &str, msg: Msg) -> Prompt {
+        Prompt::Confirm(format!("{} (y/n)", question), msg)
+    }
+
+    pub fn key(&mut self, key: Key) -> PromptResult {
+        let search = matches!(self, Prompt::Search(_));
+        match self {
+            Prompt::Command(line) | Prompt::Search(line) => match key {
+                Key::Char('\n') if search => {
+                    PromptResult::Confirmed(Msg::Search(std::mem::take(line)))
+                }
+                Key::Char('\n') => PromptResult::Submit(std::mem::take(line)),
+                Key::Esc | Key::Ctrl('c') => PromptResult::Cancel,
+                Key::Backspace if line.is_empty() => PromptResult::Cancel,
+                Key::Backspace => {
+                    line.pop();
+                    PromptResult::Pending
+                }
+                Key::Ctrl('u') => {
+                    line.clear();
+                    PromptResult::Pending
+                }
+                Key::Char(c) => {
+                    line.push(c);
+                    PromptResult::Pending
+                }
+                _ => PromptResult::Pending,
+            },
+            Prompt::Confirm(_, msg) => match key {
+                Key::Char('y') | Key::Char('Y') => {
+                    PromptResult::Confirmed(std::mem::replace(msg, Msg::None))
+                }
+                _ => PromptResult::Cancel,
+            },
+        }
+    }
+
+    /// The text shown in the status line while the prompt is open
+    pub fn render(&self) -> String {
+        match self {
+            Prompt::Command(line) => format!(":{}", line),
+            Prompt::Search(line) => format!("/{}", line),
+            Prompt::Confirm(question, _) => question.clone(),
+        }
+    }
+}
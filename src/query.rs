@@ -0,0 +1,175 @@
+//! Questions for pdfium about a document other than what a page looks like:
+//! where some text is, where the links go, what's in the outline and so on.
+//! They're sent to the render worker like pages are (see `render::ask`), so
+//! a malformed file crashing pdfium, or taking it over, doesn't take the
+//! viewer with it. The answers come back as JSON, which the module asking
+//! turns back into what it wanted.
+
+use std::ops::Range;
+
+use anyhow::{anyhow, bail, Result};
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::metadata::{self, Metadata};
+use crate::search::Rect;
+use crate::{column, flashcards, links, outline, search, text};
+
+pub enum Query {
+    /// Where `text` is on `page`, as `search::on_page` finds it
+    Matches {
+        page: usize,
+        text: String,
+        size: (u32, u32),
+    },
+    /// The first of `pages` with `text` on it
+    Find {
+        pages: Vec<usize>,
+        text: String,
+    },
+    /// The text of `pages`, as `text::extract` has it
+    Text {
+        pages: Range<usize>,
+    },
+    /// The text in `rect` on `page`, as `text::in_region` finds it
+    Region {
+        page: usize,
+        rect: Rect,
+        size: (u32, u32),
+    },
+    Links {
+        page: usize,
+        size: (u32, u32),
+    },
+    /// Where the body text is on `page`
+    Column {
+        page: usize,
+    },
+    Outline,
+    /// The metadata, and the title going by the first page, for naming the
+    /// file
+    Naming,
+    /// The highlights on `page`, with the text they're over and their notes
+    Highlights {
+        page: usize,
+    },
+}
+
+fn size_json((width, height): (u32, u32)) -> Value {
+    json!([width, height])
+}
+
+fn size_from(value: &Value) -> (u32, u32) {
+    match value.as_array().map(|a| a.as_slice()) {
+        Some([w, h]) => (
+            w.as_u64().unwrap_or(1) as u32,
+            h.as_u64().unwrap_or(1) as u32,
+        ),
+        _ => (1, 1),
+    }
+}
+
+impl Query {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Query::Matches { page, text, size } => {
+                json!({ "kind": "matches", "page": page, "text": text, "size": size_json(*size) })
+            }
+            Query::Find { pages, text } => json!({ "kind": "find", "pages": pages, "text": text }),
+            Query::Text { pages } => json!({ "kind": "text", "pages": [pages.start, pages.end] }),
+            Query::Region { page, rect, size } => json!({
+                "kind": "region",
+                "page": page,
+                "rect": [rect.0, rect.1, rect.2, rect.3],
+                "size": size_json(*size),
+            }),
+            Query::Links { page, size } => {
+                json!({ "kind": "links", "page": page, "size": size_json(*size) })
+            }
+            Query::Column { page } => json!({ "kind": "column", "page": page }),
+            Query::Outline => json!({ "kind": "outline" }),
+            Query::Naming => json!({ "kind": "naming" }),
+            Query::Highlights { page } => json!({ "kind": "highlights", "page": page }),
+        }
+    }
+
+    pub fn from_json(value: &Value) -> Result<Query> {
+        let page = value["page"].as_u64().unwrap_or(0) as usize;
+        let text = value["text"].as_str().unwrap_or_default().to_string();
+        let size = size_from(&value["size"]);
+        Ok(match value["kind"].as_str().unwrap_or_default() {
+            "matches" => Query::Matches { page, text, size },
+            "find" => Query::Find {
+                pages: serde_json::from_value(value["pages"].clone())?,
+                text,
+            },
+            "text" => {
+                let (start, end): (usize, usize) = serde_json::from_value(value["pages"].clone())?;
+                Query::Text { pages: start..end }
+            }
+            "region" => Query::Region {
+                page,
+                rect: serde_json::from_value(value["rect"].clone())?,
+                size,
+            },
+            "links" => Query::Links { page, size },
+            "column" => Query::Column { page },
+            "outline" => Query::Outline,
+            "naming" => Query::Naming,
+            "highlights" => Query::Highlights { page },
+            kind => bail!("no such query as {:?}", kind),
+        })
+    }
+
+    /// What it was doing, for saying so if the renderer crashed
+    pub fn doing(&self) -> String {
+        match self {
+            Query::Matches { page, .. }
+            | Query::Region { page, .. }
+            | Query::Links { page, .. }
+            | Query::Column { page }
+            | Query::Highlights { page } => format!("on page {}", page + 1),
+            _ => "reading the document".to_string(),
+        }
+    }
+
+    /// The answer, from `document`
+    pub fn answer(&self, document: &PdfDocument) -> Result<Value> {
+        Ok(match self {
+            Query::Matches { page, text, size } => {
+                serde_json::to_value(search::matches_in(document, *page, text, *size)?)?
+            }
+            Query::Find { pages, text } => json!(search::first_in(document, pages, text)?),
+            Query::Text { pages } => json!(text::extract_in(document, pages.clone())?),
+            Query::Region { page, rect, size } => {
+                json!(text::region_in(document, *page, *rect, *size)?)
+            }
+            Query::Links { page, size } => {
+                let links = links::links_in(document, *page, *size)?;
+                Value::Array(links.iter().map(links::Link::to_json).collect())
+            }
+            Query::Column { page } => match column::column_in(document, *page)? {
+                Some(column) => column.to_json(),
+                None => Value::Null,
+            },
+            Query::Outline => {
+                let entries = outline::entries_in(document);
+                Value::Array(entries.iter().map(outline::Entry::to_json).collect())
+            }
+            Query::Naming => json!({
+                "metadata": Metadata::read(document).to_json(),
+                "first_page": metadata::first_page_title(document),
+            }),
+            Query::Highlights { page } => {
+                serde_json::to_value(flashcards::highlights_in(document, *page)?)?
+            }
+        })
+    }
+}
+
+/// `value` as a list, for answers that are one
+pub fn list(value: &Value) -> Result<&Vec<Value>> {
+    value
+        .as_array()
+        .ok_or_else(|| anyhow!("the renderer's answer wasn't a list"))
+}
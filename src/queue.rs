@@ -0,0 +1,57 @@
+//! The reading queue: documents saved to read later, in order.
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::state;
+
+const FILE: &str = "queue.json";
+
+#[derive(Debug, Default)]
+pub struct Queue {
+    pub files: Vec<String>,
+}
+
+impl Queue {
+    pub fn load() -> Result<Queue> {
+        let files = match state::load(FILE)? {
+            Value::Array(list) => list
+                .iter()
+                .filter_map(|f| f.as_str().map(String::from))
+                .collect(),
+            _ => vec![],
+        };
+        Ok(Queue { files })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        state::save(FILE, &Value::from(self.files.clone()))
+    }
+
+    /// Add a file to the end of the queue. Returns false if it was already
+    /// queued.
+    pub fn add(&mut self, file: &str) -> bool {
+        let key = state::key(file);
+        if self.files.contains(&key) {
+            return false;
+        }
+        self.files.push(key);
+        true
+    }
+
+    pub fn remove(&mut self, file: &str) -> bool {
+        let key = state::key(file);
+        let len = self.files.len();
+        self.files.retain(|f| *f != key);
+        self.files.len() != len
+    }
+
+    /// Queued files that still exist
+    pub fn existing(&self) -> Vec<String> {
+        self.files
+            .iter()
+            .filter(|f| std::path::Path::new(f).exists())
+            .cloned()
+            .collect()
+    }
+}
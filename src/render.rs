@@ -0,0 +1,1002 @@
+//! Rendering pages with pdfium, either in this process or in a worker
+//! process (termpdf run again with `--render-worker`), so that pdfium
+//! crashing on a malformed file costs a page rather than the whole viewer.
+//! Everything else pdfium is asked about a document (see `query`) goes the
+//! same way.
+//!
+//! The worker reads one JSON request per line on standard input and answers
+//! each with a JSON line, followed by the page's RGBA pixels if it was asked
+//! for an image. A query's answer is the reply's `data`. Drawings of text
+//! pdfium sets on pages of its own (see `stamp`) need no document, and come
+//! back as images. A sandboxed worker can't open files, so requests to it are
+//! followed by the document itself whenever it's one the worker hasn't seen.
+//!
+//! `termpdf --daemon` answers the same requests on a Unix socket, keeping
+//! pdfium loaded for the termpdf processes that find it there.
+
+use std::env;
+use std::fs;
+use std::io::{self, stdin, stdout, BufRead, BufReader, Read, Write};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::thread;
+use std::time::SystemTime;
+
+use anyhow::{anyhow, bail, Result};
+use image::{DynamicImage, RgbaImage};
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::metadata::Metadata;
+use crate::query::Query;
+use crate::stamp::{self, Drawing};
+use crate::{bind_pdfium, cache, graphics, sandbox, text};
+
+/// Where pdfium runs
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Isolation {
+    /// In the viewer itself, which is quickest
+    None,
+    /// In a worker process that is started again if it crashes
+    Process,
+    /// In a worker process that is also locked down (seccomp on Linux,
+    /// sandbox-exec on macOS), for PDFs from untrusted sources
+    Sandbox,
+}
+
+impl Isolation {
+    pub fn parse(s: &str) -> Option<Isolation> {
+        match s {
+            "none" => Some(Isolation::None),
+            "process" => Some(Isolation::Process),
+            "sandbox" => Some(Isolation::Sandbox),
+            _ => None,
+        }
+    }
+}
+
+/// Most pixels a page is rendered with, whatever size it's asked for: about
+/// 160 MB of RGBA. A poster or a map can be metres across, and rendering it
+/// at the resolution a magnifier or a wide window would otherwise ask for
+/// is enough to run out of memory, so past this it's drawn less sharply.
+pub const MAX_PIXELS: f64 = 40_000_000.0;
+
+/// Biggest document sent to a sandboxed worker, which refuses anything
+/// bigger rather than trying to find the memory for it
+const MAX_DOCUMENT: u64 = 1 << 30;
+
+/// How pdfium draws text and lines, and which parts of the page
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quality {
+    /// Smooth the edges of text, lines and pictures
+    pub antialias: bool,
+    /// Smooth text with coloured fringes for the subpixels of an LCD, for
+    /// terminals that show images a pixel per pixel
+    pub lcd_text: bool,
+    /// Let the system draw text with its own font renderer where pdfium
+    /// can, rather than pdfium doing it
+    pub native_text: bool,
+    /// Draw what's filled in in forms
+    pub forms: bool,
+    /// Draw annotations, like highlights and sticky notes
+    pub annotations: bool,
+}
+
+impl Default for Quality {
+    fn default() -> Quality {
+        Quality::DEFAULT
+    }
+}
+
+impl Quality {
+    const DEFAULT: Quality = Quality {
+        antialias: true,
+        lcd_text: false,
+        native_text: true,
+        forms: true,
+        annotations: true,
+    };
+
+    fn to_json(self) -> Value {
+        json!({
+            "antialias": self.antialias,
+            "lcd_text": self.lcd_text,
+            "native_text": self.native_text,
+            "forms": self.forms,
+            "annotations": self.annotations,
+        })
+    }
+
+    fn from_json(value: &Value) -> Quality {
+        let default = Quality::default();
+        let flag = |name: &str, default| value[name].as_bool().unwrap_or(default);
+        Quality {
+            antialias: flag("antialias", default.antialias),
+            lcd_text: flag("lcd_text", default.lcd_text),
+            native_text: flag("native_text", default.native_text),
+            forms: flag("forms", default.forms),
+            annotations: flag("annotations", default.annotations),
+        }
+    }
+}
+
+static ISOLATION: OnceLock<Isolation> = OnceLock::new();
+/// Changed when the config file is, like `QUALITY`
+static MATTE: Mutex<[u8; 3]> = Mutex::new([255, 255, 255]);
+static QUALITY: Mutex<Quality> = Mutex::new(Quality::DEFAULT);
+static WORKER: Mutex<Option<Worker>> = Mutex::new(None);
+/// Changed with `i` while reading, so not set once
+static DARK: AtomicBool = AtomicBool::new(false);
+/// Toggled with `p`, like `DARK`
+static PRINT: AtomicBool = AtomicBool::new(false);
+
+/// Choose where pages are rendered, which is done once, before any are
+/// and after a sandbox has been found to work (see `sandbox::check`)
+pub fn isolate(isolation: Isolation) {
+    let _ = ISOLATION.set(isolation);
+}
+
+/// Choose what pages are rendered on and how, which can be changed again
+pub fn configure(matte: [u8; 3], quality: Quality) {
+    *MATTE.lock().unwrap_or_else(|e| e.into_inner()) = matte;
+    *QUALITY.lock().unwrap_or_else(|e| e.into_inner()) = quality;
+}
+
+/// The colour pages are rendered on, which shows through wherever they're
+/// transparent
+pub fn matte() -> [u8; 3] {
+    *MATTE.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+pub fn quality() -> Quality {
+    *QUALITY.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+/// Whether pages are rendered with their colours inverted, for reading in
+/// the dark
+pub fn dark() -> bool {
+    DARK.load(Ordering::Relaxed)
+}
+
+pub fn set_dark(dark: bool) {
+    DARK.store(dark, Ordering::Relaxed);
+}
+
+/// Whether pages are rendered as they'd be printed, in grey with pdfium's
+/// printing flags, and with their trim, bleed and art boxes drawn
+pub fn print_preview() -> bool {
+    PRINT.load(Ordering::Relaxed)
+}
+
+pub fn set_print_preview(print: bool) {
+    PRINT.store(print, Ordering::Relaxed);
+}
+
+fn isolation() -> Isolation {
+    *ISOLATION.get().unwrap_or(&Isolation::None)
+}
+
+/// A rendered page, with what the viewer needs to know about the document
+/// besides
+#[derive(Debug)]
+pub struct Rendered {
+    /// None if only the text was asked for
+    pub image: Option<DynamicImage>,
+    /// The page that was rendered, which is the last one if the page asked
+    /// for was past the end
+    pub page: usize,
+    pub length: usize,
+    pub text: String,
+    /// Every page's label, if they were asked for
+    pub labels: Vec<String>,
+    /// The document's title, empty if it doesn't have one
+    pub title: String,
+}
+
+struct Request {
+    file: String,
+    page: usize,
+    /// Turn landscape pages on their side
+    rotate: bool,
+    /// The box in pixels to render the page as large as fits in, or None
+    /// for just the text
+    size: Option<(u32, u32)>,
+    /// Just this part of the page, stretched to fill `size`, for showing a
+    /// poster a piece at a time. These are never turned on their side.
+    region: Option<Fractions>,
+    /// Whether to find the page labels as well
+    labels: bool,
+    matte: [u8; 3],
+    /// Let pdfium render it in grey
+    grayscale: bool,
+    /// Invert it, pictures aside
+    dark: bool,
+    /// Render it as it would be printed, with the page boxes drawn
+    print: bool,
+    quality: Quality,
+}
+
+impl Request {
+    fn to_json(&self) -> Value {
+        json!({
+            "file": self.file,
+            "page": self.page,
+            "rotate": self.rotate,
+            "width": self.size.map(|s| s.0),
+            "height": self.size.map(|s| s.1),
+            "region": self.region.map(|(l, t, r, b)| [l, t, r, b]),
+            "quality": self.quality.to_json(),
+            "labels": self.labels,
+            "matte": self.matte,
+            "grayscale": self.grayscale,
+            "dark": self.dark,
+            "print": self.print,
+        })
+    }
+
+    fn from_json(value: &Value) -> Result<Request> {
+        Ok(Request {
+            file: value["file"]
+                .as_str()
+                .ok_or_else(|| anyhow!("no file"))?
+                .to_string(),
+            page: value["page"].as_u64().unwrap_or(0) as usize,
+            rotate: value["rotate"].as_bool().unwrap_or(false),
+            size: match (value["width"].as_u64(), value["height"].as_u64()) {
+                (Some(w), Some(h)) => Some((w as u32, h as u32)),
+                _ => None,
+            },
+            region: match value["region"].as_array().map(|a| a.as_slice()) {
+                Some([l, t, r, b]) => {
+                    let [l, t, r, b] = [l, t, r, b].map(|f| f.as_f64().unwrap_or(0.0));
+                    Some((l, t, r, b))
+                }
+                _ => None,
+            },
+            labels: value["labels"].as_bool().unwrap_or(false),
+            matte: match value["matte"].as_array().map(|a| a.as_slice()) {
+                Some([r, g, b]) => [r, g, b].map(|c| c.as_u64().unwrap_or(255) as u8),
+                _ => [255, 255, 255],
+            },
+            grayscale: value["grayscale"].as_bool().unwrap_or(false),
+            dark: value["dark"].as_bool().unwrap_or(false),
+            print: value["print"].as_bool().unwrap_or(false),
+            quality: Quality::from_json(&value["quality"]),
+        })
+    }
+}
+
+/// Page `p` of `document` as an image as big as fits in `size` pixels, on
+/// a background of `matte`, with the rest of what `request` asks for
+fn image(
+    document: &PdfDocument,
+    p: usize,
+    size: (u32, u32),
+    request: &Request,
+) -> Result<DynamicImage> {
+    let page = document.pages().get(p as u16)?;
+    let (width, height) = (page.width().value as f64, page.height().value as f64);
+    // On its side, the page's width goes down the screen
+    let rotated = request.rotate && width > height && request.region.is_none();
+    let shown = match rotated {
+        true => (height, width),
+        false => (width, height),
+    };
+    let scale = (size.0 as f64 / shown.0)
+        .min(size.1 as f64 / shown.1)
+        .min((MAX_PIXELS / (width * height).max(1.0)).sqrt());
+    let matte = request.matte;
+    let quality = request.quality;
+    let mut render_config = PdfRenderConfig::new()
+        .set_target_height((height * scale).round().max(1.0) as Pixels)
+        .set_clear_color(PdfColor::new(matte[0], matte[1], matte[2], 255))
+        .use_grayscale_rendering(request.grayscale || request.print)
+        .use_print_quality(request.print)
+        .use_lcd_text_rendering(quality.lcd_text)
+        .disable_native_text_rendering(!quality.native_text)
+        .set_text_smoothing(quality.antialias)
+        .set_image_smoothing(quality.antialias)
+        .set_path_smoothing(quality.antialias)
+        .render_form_data(quality.forms)
+        .render_annotations(quality.annotations);
+
+    if rotated {
+        render_config = render_config.rotate_if_landscape(PdfBitmapRotation::Degrees90, true);
+    }
+    if let Some((l, t, r, b)) = request.region {
+        // Moved so the region's corner is the page's, and made bigger so it
+        // covers the whole page, which pdfium then fits to `size`. pdfium
+        // can't draw form fields when it's given a transformation, so those
+        // are left out of a tile.
+        let (w, h) = ((r - l).max(1e-6), (b - t).max(1e-6));
+        let pixels = size.0 as f64 * size.1 as f64;
+        let shrink = (MAX_PIXELS / pixels.max(1.0)).sqrt().min(1.0);
+        let tile = [size.0, size.1].map(|side| (side as f64 * shrink).max(1.0) as Pixels);
+        render_config = render_config
+            .set_target_size(tile[0], tile[1])
+            .translate(
+                PdfPoints::new((-l * width) as f32),
+                PdfPoints::new((-t * height) as f32),
+            )?
+            .scale((1.0 / w) as f32, (1.0 / h) as f32)?;
+    }
+
+    let mut image = page.render_with_config(&render_config)?.as_image();
+    // Where on the image a rectangle on the page is, turned 90° clockwise
+    // (as for search matches) if the page was
+    let origin = page
+        .boundaries()
+        .crop()
+        .or_else(|_| page.boundaries().media())
+        .map(|b| (b.bounds.left.value as f64, b.bounds.bottom.value as f64))
+        .unwrap_or_default();
+    let place = |bounds: PdfRect| -> Fractions {
+        let left = (bounds.left.value as f64 - origin.0) / width;
+        let right = (bounds.right.value as f64 - origin.0) / width;
+        let top = 1.0 - (bounds.top.value as f64 - origin.1) / height;
+        let bottom = 1.0 - (bounds.bottom.value as f64 - origin.1) / height;
+        match (rotated, request.region) {
+            (true, _) => (1.0 - bottom, left, 1.0 - top, right),
+            (false, Some((l, t, r, b))) => (
+                (left - l) / (r - l),
+                (top - t) / (b - t),
+                (right - l) / (r - l),
+                (bottom - t) / (b - t),
+            ),
+            (false, None) => (left, top, right, bottom),
+        }
+    };
+
+    if request.dark {
+        let pictures = page
+            .objects()
+            .iter()
+            .filter(|object| object.object_type() == PdfPageObjectType::Image)
+            .filter_map(|object| object.bounds().ok())
+            .map(place)
+            .collect::<Vec<_>>();
+        image = inverted(image, &pictures);
+    }
+    if request.print {
+        let boundaries = page.boundaries();
+        let boxes = [
+            (boundaries.bleed(), BLEED_BOX),
+            (boundaries.trim(), TRIM_BOX),
+            (boundaries.art(), ART_BOX),
+        ];
+        let boxes = boxes
+            .into_iter()
+            .filter_map(|(b, colour)| Some((place(b.ok()?.bounds), colour)))
+            .collect::<Vec<_>>();
+        image = framed(image, &boxes);
+    }
+    Ok(image)
+}
+
+/// Left, top, right and bottom of a rectangle, as fractions of the image
+pub type Fractions = (f64, f64, f64, f64);
+
+/// The colours the page boxes are drawn in for print preview: where the
+/// paper is cut, how far past that the ink goes, and the part that matters
+const TRIM_BOX: [u8; 3] = [220, 30, 30];
+const BLEED_BOX: [u8; 3] = [30, 80, 230];
+const ART_BOX: [u8; 3] = [20, 160, 60];
+
+/// `image` with the outlines of `boxes` drawn on it, each in its colour
+fn framed(image: DynamicImage, boxes: &[(Fractions, [u8; 3])]) -> DynamicImage {
+    let mut image = image.to_rgba8();
+    let (width, height) = image.dimensions();
+    let line = (width / 300).max(2) as i64;
+    for &((l, t, r, b), [red, green, blue]) in boxes {
+        let edge = |f: f64, size: u32| ((f * size as f64).round() as i64).clamp(0, size as i64 - 1);
+        let (l, r) = (edge(l, width), edge(r, width));
+        let (t, b) = (edge(t, height), edge(b, height));
+        for y in t..=b {
+            for x in l..=r {
+                let on_edge = x < l + line || x > r - line || y < t + line || y > b - line;
+                if on_edge {
+                    image.put_pixel(x as u32, y as u32, image::Rgba([red, green, blue, 255]));
+                }
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// `image` with light and dark swapped but the hues kept, so red text stays
+/// red, except in `pictures`, where photos would come out as negatives
+fn inverted(image: DynamicImage, pictures: &[Fractions]) -> DynamicImage {
+    let mut image = image.to_rgba8();
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    let pictures: Vec<_> = pictures
+        .iter()
+        .map(|(l, t, r, b)| (l * width, t * height, r * width, b * height))
+        .collect();
+    for (x, y, pixel) in image.enumerate_pixels_mut() {
+        let (x, y) = (x as f64 + 0.5, y as f64 + 0.5);
+        if pictures
+            .iter()
+            .any(|&(l, t, r, b)| x >= l && x < r && y >= t && y < b)
+        {
+            continue;
+        }
+        let [r, g, b, a] = pixel.0;
+        // Lightness in HSL is halfway between the brightest and darkest
+        // channels, so moving every channel by the same amount flips it
+        let shift = 255 - r.max(g).max(b) as i16 - r.min(g).min(b) as i16;
+        let [r, g, b] = [r, g, b].map(|c| (c as i16 + shift).clamp(0, 255) as u8);
+        pixel.0 = [r, g, b, a];
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// Render from `data` if given, or else by opening the file
+fn render_with(pdfium: &Pdfium, request: &Request, data: Option<&[u8]>) -> Result<Rendered> {
+    let document = match data {
+        Some(data) => pdfium.load_pdf_from_byte_slice(data, None)?,
+        None => pdfium.load_pdf_from_file(&request.file, None)?,
+    };
+    let length = document.pages().len() as usize;
+    let page = request.page.min(length.saturating_sub(1));
+    let image = match request.size {
+        Some(size) => Some(image(&document, page, size, request)?),
+        None => None,
+    };
+    let labels = match request.labels {
+        true => text::page_labels(&document),
+        false => vec![],
+    };
+    Ok(Rendered {
+        image,
+        page,
+        length,
+        text: text::page_text(&document, page),
+        labels,
+        title: Metadata::read(&document).title,
+    })
+}
+
+/// A worker process, or a connection to the daemon
+struct Worker {
+    /// None for the daemon, which isn't ours to stop
+    child: Option<Child>,
+    stdin: Box<dyn Write + Send>,
+    stdout: BufReader<Box<dyn Read + Send>>,
+    /// Whether it has to be sent documents rather than opening them
+    sandboxed: bool,
+    /// The document it was last sent: its name, when it was modified and
+    /// how big it was
+    sent: Option<(String, Option<SystemTime>, u64)>,
+}
+
+/// How a request to the worker went wrong
+enum Failure {
+    /// It couldn't render the page, but is fine
+    Error(String),
+    /// It's gone, most likely killed by pdfium crashing
+    Died,
+}
+
+impl Worker {
+    fn spawn(sandboxed: bool) -> Result<Worker> {
+        let mut command = Command::new(std::env::current_exe()?);
+        command.arg("--render-worker");
+        if sandboxed {
+            command.arg("--sandbox");
+            command = sandbox::wrap(command);
+        }
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        Ok(Worker {
+            stdin: Box::new(child.stdin.take().unwrap()),
+            stdout: BufReader::new(Box::new(child.stdout.take().unwrap())),
+            child: Some(child),
+            sandboxed,
+            sent: None,
+        })
+    }
+
+    /// Talk to the daemon, if one is running
+    fn connect() -> Option<Worker> {
+        let stream = UnixStream::connect(socket_path()).ok()?;
+        Some(Worker {
+            stdin: Box::new(stream.try_clone().ok()?),
+            stdout: BufReader::new(Box::new(stream)),
+            child: None,
+            sandboxed: false,
+            sent: None,
+        })
+    }
+
+    /// The document, if the worker needs sending it
+    fn document(&mut self, file: &str) -> Result<Option<Vec<u8>>, Failure> {
+        if !self.sandboxed {
+            return Ok(None);
+        }
+        let metadata = fs::metadata(file).map_err(|e| Failure::Error(e.to_string()))?;
+        if metadata.len() > MAX_DOCUMENT {
+            return Err(Failure::Error(
+                "the file is too big for the sandboxed renderer".to_string(),
+            ));
+        }
+        let version = Some((file.to_string(), metadata.modified().ok(), metadata.len()));
+        if self.sent == version {
+            return Ok(None);
+        }
+        let data = fs::read(file).map_err(|e| Failure::Error(e.to_string()))?;
+        self.sent = version;
+        Ok(Some(data))
+    }
+
+    /// Send `header`, followed by `file` if the worker needs it, and read
+    /// the reply, with the pixels after it if there are any
+    fn exchange(
+        &mut self,
+        file: Option<&str>,
+        mut header: Value,
+    ) -> Result<(Value, Option<DynamicImage>), Failure> {
+        let data = match file {
+            Some(file) => self.document(file)?,
+            None => None,
+        };
+        header["bytes"] = json!(data.as_ref().map(|d| d.len()));
+        writeln!(self.stdin, "{}", header).map_err(|_| Failure::Died)?;
+        if let Some(data) = &data {
+            self.stdin.write_all(data).map_err(|_| Failure::Died)?;
+        }
+        self.stdin.flush().map_err(|_| Failure::Died)?;
+
+        let mut line = String::new();
+        match self.stdout.read_line(&mut line) {
+            Ok(n) if n > 0 => {}
+            _ => return Err(Failure::Died),
+        }
+        let reply: Value = serde_json::from_str(&line).map_err(|_| Failure::Died)?;
+        if let Some(error) = reply["error"].as_str() {
+            return Err(Failure::Error(error.to_string()));
+        }
+
+        let number = |key: &str| reply[key].as_u64().unwrap_or(0);
+        let image = match reply["bytes"].as_u64() {
+            Some(bytes) => {
+                let (width, height) = (number("width"), number("height"));
+                // Not what a render could have made, so the worker has gone
+                // wrong (or been taken over), and isn't to be given the
+                // memory it asks for
+                if !sent_size(width, height, bytes) {
+                    return Err(Failure::Died);
+                }
+                let mut pixels = vec![0; bytes as usize];
+                self.stdout
+                    .read_exact(&mut pixels)
+                    .map_err(|_| Failure::Died)?;
+                let image = RgbaImage::from_raw(width as u32, height as u32, pixels)
+                    .ok_or(Failure::Died)?;
+                Some(DynamicImage::ImageRgba8(image))
+            }
+            None => None,
+        };
+        Ok((reply, image))
+    }
+
+    fn render(&mut self, request: &Request) -> Result<Rendered, Failure> {
+        let (reply, image) = self.exchange(Some(&request.file), request.to_json())?;
+        let number = |key: &str| reply[key].as_u64().unwrap_or(0);
+        Ok(Rendered {
+            image,
+            page: number("page") as usize,
+            length: number("length") as usize,
+            text: reply["text"].as_str().unwrap_or_default().to_string(),
+            labels: reply["labels"]
+                .as_array()
+                .map(|labels| {
+                    let label = |l: &Value| l.as_str().unwrap_or_default().to_string();
+                    labels.iter().map(label).collect()
+                })
+                .unwrap_or_default(),
+            title: reply["title"].as_str().unwrap_or_default().to_string(),
+        })
+    }
+
+    fn draw(&mut self, drawing: &Drawing) -> Result<RgbaImage, Failure> {
+        let header = json!({ "draw": drawing.to_json() });
+        match self.exchange(None, header)? {
+            (_, Some(image)) => Ok(image.to_rgba8()),
+            (_, None) => Err(Failure::Error(
+                "the daemon is too old to draw that, and needs starting again".to_string(),
+            )),
+        }
+    }
+
+    fn ask(&mut self, file: &str, query: &Query) -> Result<Value, Failure> {
+        let header = json!({ "file": file, "query": query.to_json() });
+        let (mut reply, _) = self.exchange(Some(file), header)?;
+        match reply.get_mut("data") {
+            Some(data) => Ok(data.take()),
+            // A daemon from before there were queries, which took it for a
+            // page to render
+            None => Err(Failure::Error(
+                "the daemon is too old to answer that, and needs starting again".to_string(),
+            )),
+        }
+    }
+}
+
+/// Whether an image of `width` by `height`, `bytes` long, is one the worker
+/// could have sent: RGBA, and no bigger than `MAX_PIXELS` but for each side
+/// being rounded up a pixel
+fn sent_size(width: u64, height: u64, bytes: u64) -> bool {
+    let most = MAX_PIXELS as u64;
+    if width > most || height > most {
+        return false;
+    }
+    let pixels = width * height;
+    pixels <= most + width + height + 1 && pixels * 4 == bytes
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        if let Some(child) = &mut self.child {
+            let _ = child.kill();
+            let _ = child.wait();
+        }
+    }
+}
+
+/// Do `job` on the worker, starting one if there isn't one yet, or `local`
+/// here if pdfium isn't kept apart. If the worker dies it's started again
+/// for the next job, and this one fails saying it crashed `doing` it.
+fn with_worker<T>(
+    doing: &str,
+    job: impl FnOnce(&mut Worker) -> Result<T, Failure>,
+    local: impl FnOnce(&Pdfium) -> Result<T>,
+) -> Result<T> {
+    let mut worker = WORKER.lock().unwrap();
+    if worker.is_none() {
+        *worker = match isolation() {
+            // The daemon only isolates pdfium from this process, not from
+            // the rest of the system
+            Isolation::Sandbox => Some(Worker::spawn(true)?),
+            Isolation::Process => Some(Worker::connect().map_or_else(|| Worker::spawn(false), Ok)?),
+            Isolation::None => None,
+        };
+    }
+    let result = match worker.as_mut() {
+        Some(worker) => job(worker),
+        None => return local(&bind_pdfium()?),
+    };
+    match result {
+        Ok(done) => Ok(done),
+        Err(Failure::Error(e)) => bail!(e),
+        Err(Failure::Died) => {
+            // Start a new one for the next job
+            *worker = None;
+            bail!("the renderer crashed {}", doing)
+        }
+    }
+}
+
+fn render(request: Request) -> Result<Rendered> {
+    with_worker(
+        &format!("on page {}", request.page + 1),
+        |worker| worker.render(&request),
+        |pdfium| render_with(pdfium, &request, None),
+    )
+}
+
+/// Answer `query` about `file` from `data` if given, or else by opening it
+fn answer_with(pdfium: &Pdfium, file: &str, query: &Query, data: Option<&[u8]>) -> Result<Value> {
+    let document = match data {
+        Some(data) => pdfium.load_pdf_from_byte_slice(data, None)?,
+        None => pdfium.load_pdf_from_file(file, None)?,
+    };
+    query.answer(&document)
+}
+
+/// Ask pdfium `query` about `file`, wherever pages are rendered
+pub fn ask(file: &str, query: &Query) -> Result<Value> {
+    let file = absolute(file);
+    with_worker(
+        &query.doing(),
+        |worker| worker.ask(&file, query),
+        |pdfium| answer_with(pdfium, &file, query, None),
+    )
+}
+
+/// Draw `drawing`, wherever pages are rendered
+pub fn draw(drawing: &Drawing) -> Result<RgbaImage> {
+    with_worker(
+        "drawing a label",
+        |worker| worker.draw(drawing),
+        |pdfium| stamp::draw(pdfium, drawing),
+    )
+}
+
+/// Files are sent by absolute path, since the daemon may be running
+/// somewhere else
+fn absolute(file: &str) -> String {
+    fs::canonicalize(file)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| file.to_string())
+}
+
+/// Render page `p` of `file` (or its last page, if that's past the end)
+pub fn page(file: &str, p: usize, rotate: bool, size: (u32, u32)) -> Result<Rendered> {
+    render(Request {
+        file: absolute(file),
+        page: p,
+        rotate,
+        size: Some(size),
+        region: None,
+        labels: false,
+        matte: matte(),
+        grayscale: graphics::tone() != graphics::Tone::Color,
+        dark: dark(),
+        print: print_preview(),
+        quality: quality(),
+    })
+}
+
+/// The part `region` of page `p` of `file`, as fractions of the page
+/// the right way up, rendered to fill `size`
+pub fn tile(file: &str, p: usize, region: Fractions, size: (u32, u32)) -> Result<Rendered> {
+    render(Request {
+        file: absolute(file),
+        page: p,
+        rotate: false,
+        size: Some(size),
+        region: Some(region),
+        labels: false,
+        matte: matte(),
+        grayscale: graphics::tone() != graphics::Tone::Color,
+        dark: dark(),
+        print: print_preview(),
+        quality: quality(),
+    })
+}
+
+/// The first page of `file` as big as fits in `size`, as it is whatever
+/// it's being read as, and the document's title, for the gallery
+pub fn thumbnail(file: &str, size: (u32, u32)) -> Result<Rendered> {
+    render(Request {
+        file: absolute(file),
+        page: 0,
+        rotate: false,
+        size: Some(size),
+        region: None,
+        labels: false,
+        matte: [255, 255, 255],
+        grayscale: false,
+        dark: false,
+        print: false,
+        quality: quality(),
+    })
+}
+
+/// Just the text of page `p` of `file`, and how many pages it has
+pub fn text(file: &str, p: usize) -> Result<Rendered> {
+    render(Request {
+        file: absolute(file),
+        page: p,
+        rotate: false,
+        size: None,
+        region: None,
+        labels: false,
+        matte: matte(),
+        grayscale: false,
+        dark: false,
+        print: false,
+        quality: quality(),
+    })
+}
+
+/// The label of every page in `file`, like "iv" or "A-3", empty for pages
+/// without one
+pub fn labels(file: &str) -> Result<Vec<String>> {
+    let rendered = render(Request {
+        file: absolute(file),
+        page: 0,
+        rotate: false,
+        size: None,
+        region: None,
+        labels: true,
+        matte: matte(),
+        grayscale: false,
+        dark: false,
+        print: false,
+        quality: quality(),
+    })?;
+    Ok(rendered.labels)
+}
+
+/// Answer requests on standard input until it closes, in a sandbox if
+/// `sandboxed`
+pub fn worker(sandboxed: bool) -> Result<()> {
+    let pdfium = bind_pdfium()?;
+    // Every page fails if the sandbox can't be set up, rather than being
+    // rendered without one
+    let refused = match sandboxed {
+        true => sandbox::enter()
+            .err()
+            .map(|e| format!("Couldn't sandbox the renderer: {}", e)),
+        false => None,
+    };
+    serve(&pdfium, stdin().lock(), stdout().lock(), sandboxed, refused)
+}
+
+/// Where the daemon listens: somewhere only this user can get at
+pub fn socket_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("termpdf").join("daemon.sock"),
+        _ => cache::cache_dir().join("daemon").join("daemon.sock"),
+    }
+}
+
+/// Keep pdfium loaded and render for any termpdf that connects, until
+/// killed
+pub fn daemon() -> Result<()> {
+    let path = socket_path();
+    if UnixStream::connect(&path).is_ok() {
+        bail!("A daemon is already listening on {}", path.display());
+    }
+    let dir = path.parent().unwrap_or(Path::new("."));
+    fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(dir)?;
+    // Made by someone else, or before, with a mode that lets others in:
+    // creating it again doesn't change that
+    let owner = fs::metadata(dir)?;
+    // SAFETY: getuid can't fail
+    if owner.uid() != unsafe { libc::getuid() } || owner.mode() & 0o777 != 0o700 {
+        bail!(
+            "{} has to be yours and only yours (mode 700) to run the daemon in",
+            dir.display()
+        );
+    }
+    // Left behind by a daemon that didn't get to clean up
+    let _ = fs::remove_file(&path);
+    // Made only for this user from the start, rather than made and then
+    // changed. Nothing else is running yet to be affected by the umask.
+    // SAFETY: umask can't fail
+    let umask = unsafe { libc::umask(0o177) };
+    let listener = UnixListener::bind(&path);
+    unsafe { libc::umask(umask) };
+    let listener = listener?;
+    eprintln!(
+        "termpdf: rendering for other termpdfs on {}",
+        path.display()
+    );
+
+    // Shared by every connection; pdfium-render serialises the calls
+    let pdfium: &'static Pdfium = Box::leak(Box::new(bind_pdfium()?));
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(_) => continue,
+        };
+        thread::spawn(move || {
+            if let Ok(reader) = stream.try_clone() {
+                let _ = serve(pdfium, BufReader::new(reader), stream, false, None);
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Answer requests read from `input` until it closes. `refused` is why
+/// every request should fail, if one should.
+fn serve(
+    pdfium: &Pdfium,
+    mut input: impl BufRead,
+    mut out: impl Write,
+    sandboxed: bool,
+    refused: Option<String>,
+) -> Result<()> {
+    let mut data: Option<Vec<u8>> = None;
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        let request = serde_json::from_str::<Value>(&line);
+        let mut too_big = false;
+        if let Some(bytes) = request.as_ref().ok().and_then(|r| r["bytes"].as_u64()) {
+            if bytes > MAX_DOCUMENT {
+                // Read past it, so the next request is where it should be
+                io::copy(&mut (&mut input).take(bytes), &mut io::sink())?;
+                too_big = true;
+            } else {
+                let mut document = vec![0; bytes as usize];
+                input.read_exact(&mut document)?;
+                data = Some(document);
+            }
+        }
+        let result = match &refused {
+            Some(why) => Err(anyhow!(why.clone())),
+            None if too_big => Err(anyhow!("the file is too big for the sandboxed renderer")),
+            None => request
+                .map_err(anyhow::Error::from)
+                .and_then(|value| reply(pdfium, &value, sandboxed, data.as_deref())),
+        };
+        match result {
+            Ok((reply, pixels)) => {
+                writeln!(out, "{}", reply)?;
+                if let Some(pixels) = &pixels {
+                    out.write_all(pixels.as_raw())?;
+                }
+            }
+            Err(e) => writeln!(out, "{}", json!({ "error": e.to_string() }))?,
+        }
+        out.flush()?;
+    }
+}
+
+/// The reply to `request`, a page to render, a query or a drawing, and
+/// the pixels to follow it if there's an image
+fn reply(
+    pdfium: &Pdfium,
+    request: &Value,
+    sandboxed: bool,
+    data: Option<&[u8]>,
+) -> Result<(Value, Option<RgbaImage>)> {
+    if !request["draw"].is_null() {
+        let image = stamp::draw(pdfium, &Drawing::from_json(&request["draw"])?)?;
+        let reply = json!({
+            "width": image.width(),
+            "height": image.height(),
+            "bytes": image.as_raw().len(),
+        });
+        return Ok((reply, Some(image)));
+    }
+    if sandboxed && data.is_none() {
+        bail!("the sandboxed renderer wasn't sent the document");
+    }
+    if !request["query"].is_null() {
+        let query = Query::from_json(&request["query"])?;
+        let file = request["file"].as_str().unwrap_or_default();
+        let data = answer_with(pdfium, file, &query, data)?;
+        return Ok((json!({ "data": data }), None));
+    }
+    let rendered = render_with(pdfium, &Request::from_json(request)?, data)?;
+    let mut reply = json!({
+        "page": rendered.page,
+        "length": rendered.length,
+        "text": rendered.text,
+        "title": rendered.title,
+    });
+    if !rendered.labels.is_empty() {
+        reply["labels"] = json!(rendered.labels);
+    }
+    let pixels = rendered.image.map(|image| image.to_rgba8());
+    if let Some(pixels) = &pixels {
+        reply["width"] = json!(pixels.width());
+        reply["height"] = json!(pixels.height());
+        reply["bytes"] = json!(pixels.as_raw().len());
+    }
+    Ok((reply, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sent_sizes() {
+        assert!(sent_size(1000, 1414, 1000 * 1414 * 4));
+        // Rounded up a pixel each way past the most a page is rendered with
+        assert!(sent_size(5001, 8001, 5001 * 8001 * 4));
+        assert!(!sent_size(1000, 1414, 1000 * 1414 * 4 + 1));
+        assert!(!sent_size(100_000, 100_000, 100_000 * 100_000 * 4));
+        assert!(!sent_size(u64::MAX, 2, u64::MAX));
+        assert!(!sent_size(1 << 40, 1, 1 << 42));
+    }
+}
@@ -0,0 +1,210 @@
+//! Pages marked for review, brought back on a simple doubling schedule:
+//! each page remembered waits twice as long before it's due again, and a
+//! page that wasn't goes back to being due the next day.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+use serde_json::{json, Value};
+
+use crate::state;
+
+const FILE: &str = "review.json";
+const DAY: u64 = 24 * 60 * 60;
+
+#[derive(Clone, Debug)]
+pub struct Item {
+    pub file: String,
+    /// Zero based page index
+    pub page: usize,
+    /// Days to wait after the next successful review
+    pub interval: u64,
+    /// When the page is next due, in seconds since the epoch
+    pub due: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Review {
+    pub items: Vec<Item>,
+}
+
+/// A review in progress: the due pages, and how far through them we are
+#[derive(Debug)]
+pub struct ReviewRun {
+    pub pages: Vec<(String, usize)>,
+    pub position: usize,
+}
+
+impl ReviewRun {
+    pub fn current(&self) -> Option<&(String, usize)> {
+        self.pages.get(self.position)
+    }
+
+    pub fn status(&self) -> String {
+        format!(
+            "review {}/{}  y: remembered  n: again  Esc: stop",
+            self.position + 1,
+            self.pages.len()
+        )
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+impl Review {
+    pub fn load() -> Result<Review> {
+        let items = match state::load(FILE)? {
+            Value::Array(list) => list
+                .iter()
+                .filter_map(|item| {
+                    Some(Item {
+                        file: item["file"].as_str()?.to_string(),
+                        page: item["page"].as_u64()? as usize,
+                        interval: item["interval"].as_u64().unwrap_or(1),
+                        due: item["due"].as_u64().unwrap_or(0),
+                    })
+                })
+                .collect(),
+            _ => vec![],
+        };
+        Ok(Review { items })
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let items: Vec<Value> = self
+            .items
+            .iter()
+            .map(|i| {
+                json!({
+                    "file": i.file,
+                    "page": i.page,
+                    "interval": i.interval,
+                    "due": i.due,
+                })
+            })
+            .collect();
+        state::save(FILE, &Value::from(items))
+    }
+
+    fn position(&self, file: &str, page: usize) -> Option<usize> {
+        let key = state::key(file);
+        self.items
+            .iter()
+            .position(|i| i.file == key && i.page == page)
+    }
+
+    /// Mark a page for review, due tomorrow. Returns false if it already was.
+    pub fn add(&mut self, file: &str, page: usize) -> bool {
+        if self.position(file, page).is_some() {
+            return false;
+        }
+        self.items.push(Item {
+            file: state::key(file),
+            page,
+            interval: 1,
+            due: now() + DAY,
+        });
+        true
+    }
+
+    pub fn remove(&mut self, file: &str, page: usize) -> bool {
+        match self.position(file, page) {
+            Some(i) => {
+                self.items.remove(i);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Pages due now, in every document that still exists, oldest first
+    pub fn due(&self) -> ReviewRun {
+        let now = now();
+        let mut items: Vec<&Item> = self
+            .items
+            .iter()
+            .filter(|i| i.due <= now && std::path::Path::new(&i.file).exists())
+            .collect();
+        items.sort_by_key(|i| i.due);
+        ReviewRun {
+            pages: items.iter().map(|i| (i.file.clone(), i.page)).collect(),
+            position: 0,
+        }
+    }
+
+    /// Reschedule a page after reviewing it
+    pub fn answer(&mut self, file: &str, page: usize, remembered: bool) {
+        if let Some(i) = self.position(file, page) {
+            let item = &mut self.items[i];
+            if remembered {
+                item.due = now() + item.interval * DAY;
+                item.interval *= 2;
+            } else {
+                item.interval = 1;
+                item.due = now() + DAY;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Whether `due` is `days` after some time between `before` and now
+    fn due_in(due: u64, days: u64, before: u64) -> bool {
+        (before..=now()).contains(&(due - days * DAY))
+    }
+
+    #[test]
+    fn intervals_double_until_forgotten() {
+        let mut review = Review::default();
+        let before = now();
+        assert!(review.add("no/such/file.pdf", 3));
+        assert!(!review.add("no/such/file.pdf", 3));
+        assert!(due_in(review.items[0].due, 1, before));
+
+        for (interval, days) in [(2, 1), (4, 2), (8, 4)] {
+            review.answer("no/such/file.pdf", 3, true);
+            assert_eq!(review.items[0].interval, interval);
+            assert!(due_in(review.items[0].due, days, before));
+        }
+        review.answer("no/such/file.pdf", 3, false);
+        assert_eq!(review.items[0].interval, 1);
+        assert!(due_in(review.items[0].due, 1, before));
+
+        assert!(review.remove("no/such/file.pdf", 3));
+        assert!(!review.remove("no/such/file.pdf", 3));
+    }
+
+    #[test]
+    fn due_pages_oldest_first() {
+        let dir = env!("CARGO_MANIFEST_DIR");
+        let (readme, manifest) = (format!("{}/README.md", dir), format!("{}/Cargo.toml", dir));
+        let item = |file: &str, page, due| Item {
+            file: state::key(file),
+            page,
+            interval: 1,
+            due,
+        };
+        let review = Review {
+            items: vec![
+                item(&readme, 0, 20),
+                item(&manifest, 1, 10),
+                // Not due yet
+                item(&readme, 2, now() + DAY),
+                // Gone
+                item("no/such/file.pdf", 3, 0),
+            ],
+        };
+        let run = review.due();
+        let pages: Vec<usize> = run.pages.iter().map(|(_, p)| *p).collect();
+        assert_eq!(pages, [1, 0]);
+        assert_eq!(run.current(), Some(&(state::key(&manifest), 1)));
+    }
+}
@@ -0,0 +1,232 @@
+//! Confining the render worker (see `render`) for untrusted files. In a
+//! sandbox the worker is sent the document over its pipe instead of opening
+//! it, so once pdfium is loaded it has no business opening files, starting
+//! programs or touching the network, and a malicious PDF that takes over
+//! pdfium can't either.
+
+use std::process::Command;
+
+use anyhow::Result;
+
+#[cfg(target_os = "linux")]
+mod seccomp {
+    use anyhow::{bail, Result};
+    use libc::*;
+
+    #[cfg(target_arch = "x86_64")]
+    const ARCH: u32 = 0xc000_003e;
+    #[cfg(target_arch = "aarch64")]
+    const ARCH: u32 = 0xc000_00b7;
+
+    /// All the worker is let do: reading and writing what it already has
+    /// open, managing its memory and running threads. Anything else,
+    /// including whatever later kernels add, is refused with EPERM. Signals
+    /// can be handled but not sent.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    const ALLOWED: &[c_long] = &[
+        SYS_read,
+        SYS_write,
+        SYS_readv,
+        SYS_writev,
+        SYS_ppoll,
+        #[cfg(target_arch = "x86_64")]
+        SYS_poll,
+        SYS_close,
+        SYS_mmap,
+        SYS_munmap,
+        SYS_mremap,
+        SYS_mprotect,
+        SYS_madvise,
+        SYS_brk,
+        SYS_futex,
+        SYS_sched_yield,
+        SYS_set_robust_list,
+        SYS_rseq,
+        SYS_sigaltstack,
+        SYS_rt_sigaction,
+        SYS_rt_sigprocmask,
+        SYS_rt_sigreturn,
+        SYS_clock_gettime,
+        SYS_getrandom,
+        SYS_exit,
+        SYS_exit_group,
+    ];
+
+    fn stmt(code: u32, k: u32) -> sock_filter {
+        sock_filter {
+            code: code as u16,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+
+    /// Skip the next instruction unless the loaded value is `k`
+    fn unless_eq(k: u32) -> sock_filter {
+        sock_filter {
+            code: (BPF_JMP | BPF_JEQ | BPF_K) as u16,
+            jt: 0,
+            jf: 1,
+            k,
+        }
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub fn enter() -> Result<()> {
+        let refuse = stmt(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | EPERM as u32);
+        let allow = stmt(BPF_RET | BPF_K, SECCOMP_RET_ALLOW);
+        let mut program = vec![
+            // Syscall numbers mean different things on other architectures
+            stmt(BPF_LD | BPF_W | BPF_ABS, 4),
+            sock_filter {
+                code: (BPF_JMP | BPF_JEQ | BPF_K) as u16,
+                jt: 1,
+                jf: 0,
+                k: ARCH,
+            },
+            stmt(BPF_RET | BPF_K, SECCOMP_RET_KILL_PROCESS),
+            stmt(BPF_LD | BPF_W | BPF_ABS, 0),
+        ];
+        // The x32 ABI numbers its syscalls from here on x86_64
+        #[cfg(target_arch = "x86_64")]
+        program.extend([
+            sock_filter {
+                code: (BPF_JMP | BPF_JGE | BPF_K) as u16,
+                jt: 0,
+                jf: 1,
+                k: 0x4000_0000,
+            },
+            refuse,
+        ]);
+        // Threads are still allowed, so say clone3 doesn't exist and let
+        // libc fall back to clone, whose flags can be looked at
+        program.extend([
+            unless_eq(SYS_clone3 as u32),
+            stmt(BPF_RET | BPF_K, SECCOMP_RET_ERRNO | ENOSYS as u32),
+        ]);
+        // A clone that isn't a thread is a new process, as fork would make
+        let thread = (CLONE_VM | CLONE_THREAD) as u32;
+        program.extend([
+            sock_filter {
+                code: (BPF_JMP | BPF_JEQ | BPF_K) as u16,
+                jt: 0,
+                jf: 5,
+                k: SYS_clone as u32,
+            },
+            // The low half of the flags, the first argument
+            stmt(BPF_LD | BPF_W | BPF_ABS, 16),
+            stmt(BPF_ALU | BPF_AND | BPF_K, thread),
+            unless_eq(thread),
+            allow,
+            refuse,
+        ]);
+        for &nr in ALLOWED {
+            program.extend([unless_eq(nr as u32), allow]);
+        }
+        program.push(refuse);
+
+        let filter = sock_fprog {
+            len: program.len() as c_ushort,
+            filter: program.as_mut_ptr(),
+        };
+        // SAFETY: `filter` points at `program`, which outlives both calls;
+        // the kernel copies it
+        unsafe {
+            if prctl(PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0 {
+                bail!("no_new_privs: {}", std::io::Error::last_os_error());
+            }
+            if prctl(
+                PR_SET_SECCOMP,
+                SECCOMP_MODE_FILTER,
+                &filter as *const sock_fprog,
+            ) != 0
+            {
+                bail!("seccomp: {}", std::io::Error::last_os_error());
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn enter() -> Result<()> {
+        bail!("seccomp sandboxing isn't supported on this architecture")
+    }
+}
+
+/// Lock down this process. Called by the worker once pdfium is loaded.
+pub fn enter() -> Result<()> {
+    #[cfg(target_os = "linux")]
+    return seccomp::enter();
+    // sandbox-exec has already done it, see `wrap`
+    #[cfg(target_os = "macos")]
+    return Ok(());
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    anyhow::bail!("sandboxing isn't supported on this system")
+}
+
+/// What macOS's sandbox-exec lets the worker do: read files and load code
+/// from them (pdfium has to be loaded), and look up system settings.
+/// Anything else, like writing, connecting anywhere, starting programs or
+/// talking to other processes, is refused.
+#[cfg(target_os = "macos")]
+const PROFILE: &str = "(version 1) (deny default) (allow file-read*) \
+    (allow file-map-executable) (allow sysctl-read) (allow signal (target self))";
+
+/// The command that starts a sandboxed worker running `program`
+pub fn wrap(program: Command) -> Command {
+    #[cfg(target_os = "macos")]
+    {
+        let mut command = Command::new("sandbox-exec");
+        command
+            .arg("-p")
+            .arg(PROFILE)
+            .arg(program.get_program())
+            .args(program.get_args());
+        command
+    }
+    #[cfg(not(target_os = "macos"))]
+    program
+}
+
+/// Whether `enter` can work here, so the viewer can refuse to start rather
+/// than every page failing
+pub fn check() -> Result<()> {
+    let supported = cfg!(any(
+        all(
+            target_os = "linux",
+            any(target_arch = "x86_64", target_arch = "aarch64")
+        ),
+        target_os = "macos"
+    ));
+    match supported {
+        true => Ok(()),
+        false => anyhow::bail!("sandboxed rendering isn't supported on this system"),
+    }
+}
+
+#[cfg(all(
+    test,
+    target_os = "linux",
+    any(target_arch = "x86_64", target_arch = "aarch64")
+))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_threads_and_io_on_what_is_open() {
+        // The filter is the thread's own, so the tests around it go on
+        // unconfined
+        let confined = std::thread::spawn(|| {
+            enter().unwrap();
+            let open = std::fs::File::open("Cargo.toml");
+            let pid = unsafe { libc::getpid() };
+            let fork = unsafe { libc::fork() };
+            let thread = std::thread::spawn(|| 1 + 1).join();
+            // As big as a page gets, grown like an image being built
+            let mut pixels = vec![1u8; 1 << 27];
+            pixels.resize(1 << 28, 2);
+            (open.is_err(), pid, fork, thread.ok(), pixels[1 << 27])
+        });
+        assert_eq!(confined.join().unwrap(), (true, -1, -1, Some(2), 2));
+    }
+}
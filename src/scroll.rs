@@ -0,0 +1,149 @@
+//! Continuous mode: the pages one after another down the window, scrolled
+//! through a little at a time rather than turned, for reading on across the
+//! page breaks. Each page is rendered as wide as the window, and only the
+//! ones in view and next to it are kept.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::ops::Range;
+
+use anyhow::Result;
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use termion::event::Key;
+
+pub enum ScrollResult {
+    Pending,
+    /// Scroll this many pixels down, or up if it's negative
+    Scroll(i64),
+    Close,
+}
+
+#[derive(Debug)]
+pub struct Scroll {
+    /// The page at the top of the view
+    pub page: usize,
+    /// How far down that page the view starts, in pixels
+    offset: u32,
+    /// The pages that can be scrolled through
+    range: Range<usize>,
+    /// The pixels the view is shown in
+    window: (u32, u32),
+    /// Pages rendered as wide as the window
+    rendered: HashMap<usize, DynamicImage>,
+}
+
+/// How much of the window a small step scrolls by
+const STEP: f64 = 0.1;
+/// How much of it a screen at a time does, leaving a little to read on from
+const SCREEN: f64 = 0.9;
+/// Pixels between one page and the next
+const GAP: u32 = 6;
+
+const BACKGROUND: Rgba<u8> = Rgba([60, 60, 60, 255]);
+
+impl Scroll {
+    /// From the top of `page`, one of `range`, in a view `window` pixels big
+    pub fn new(page: usize, range: Range<usize>, window: (u32, u32)) -> Scroll {
+        Scroll {
+            page,
+            offset: 0,
+            range,
+            window: (window.0.max(1), window.1.max(1)),
+            rendered: HashMap::new(),
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        "continuous: j k scroll  Space PgUp a screen  Esc pages"
+    }
+
+    /// The pixels wide the pages are rendered to fill
+    pub fn width(&self) -> u32 {
+        self.window.0
+    }
+
+    pub fn key(&self, key: Key) -> ScrollResult {
+        let height = self.window.1 as f64;
+        let by = match key {
+            Key::Char('j') | Key::Down => height * STEP,
+            Key::Char('k') | Key::Up => -height * STEP,
+            Key::Char(' ') | Key::PageDown | Key::Ctrl('f') => height * SCREEN,
+            Key::PageUp | Key::Ctrl('b') => -height * SCREEN,
+            Key::Esc | Key::Char('q') | Key::Char('c') => return ScrollResult::Close,
+            _ => return ScrollResult::Pending,
+        };
+        ScrollResult::Scroll(by.round() as i64)
+    }
+
+    /// Page `p` as wide as the window, rendered with `render` if it hasn't
+    /// been already
+    fn get<F>(&mut self, p: usize, render: &mut F) -> Result<&DynamicImage>
+    where
+        F: FnMut(usize) -> Result<DynamicImage>,
+    {
+        Ok(match self.rendered.entry(p) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(render(p)?),
+        })
+    }
+
+    /// How tall page `p` is, with the gap after it
+    fn height<F>(&mut self, p: usize, render: &mut F) -> Result<u32>
+    where
+        F: FnMut(usize) -> Result<DynamicImage>,
+    {
+        Ok(self.get(p, render)?.height() + GAP)
+    }
+
+    /// Move the view `by` pixels down (up, if it's negative), on to the
+    /// pages before or after as it crosses them, and no further than the
+    /// top of the first or the bottom of the last
+    pub fn scroll<F>(&mut self, by: i64, render: &mut F) -> Result<()>
+    where
+        F: FnMut(usize) -> Result<DynamicImage>,
+    {
+        let mut offset = self.offset as i64 + by;
+        while offset < 0 && self.page > self.range.start {
+            self.page -= 1;
+            offset += self.height(self.page, render)? as i64;
+        }
+        while offset >= self.height(self.page, render)? as i64 && self.page + 1 < self.range.end {
+            offset -= self.height(self.page, render)? as i64;
+            self.page += 1;
+        }
+        let last = self.height(self.page, render)? as i64 - GAP as i64 - self.window.1 as i64;
+        offset = match self.page + 1 >= self.range.end {
+            true => offset.min(last),
+            false => offset,
+        };
+        self.offset = offset.max(0) as u32;
+        Ok(())
+    }
+
+    /// What's in view: the page at the top and as many after it as fit,
+    /// rendered with `render` as they're first needed
+    pub fn draw<F>(&mut self, render: &mut F) -> Result<DynamicImage>
+    where
+        F: FnMut(usize) -> Result<DynamicImage>,
+    {
+        let (width, height) = self.window;
+        let mut view = RgbaImage::from_pixel(width, height, BACKGROUND);
+        let (mut p, mut skip, mut y) = (self.page, self.offset, 0);
+        while y < height && p < self.range.end {
+            let page = self.get(p, render)?;
+            let rows = page.height().saturating_sub(skip).min(height - y);
+            if rows > 0 {
+                let part = page.crop_imm(0, skip, page.width(), rows).to_rgba8();
+                let x = width.saturating_sub(part.width()) / 2;
+                imageops::replace(&mut view, &part, x as i64, y as i64);
+            }
+            y += rows + GAP;
+            skip = 0;
+            p += 1;
+        }
+        // Only what's in view, and a page either side to scroll on to
+        let near = self.page.saturating_sub(1)..p + 1;
+        self.rendered.retain(|p, _| near.contains(p));
+        Ok(DynamicImage::ImageRgba8(view))
+    }
+}
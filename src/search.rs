@@ -0,0 +1,183 @@
+//! Finding text on a page and marking where it is.
+//!
+//! Matches are kept as rectangles in fractions of the page image, like a
+//! `Selection`, so they can be drawn over the page at any size.
+
+use std::ops::Range;
+
+use anyhow::Result;
+use image::{DynamicImage, RgbaImage};
+use pdfium_render::prelude::*;
+
+use crate::query::Query;
+use crate::render;
+
+/// `(x, y, w, h)` as fractions of the page image
+pub type Rect = (f64, f64, f64, f64);
+
+/// A match: a rectangle for each of its characters
+pub type Match = Vec<Rect>;
+
+/// Where `query` is on page `p` of `file`, ignoring case. `size` is the
+/// page image as displayed, which may have been turned to fit the terminal.
+pub fn on_page(file: &str, p: usize, query: &str, size: (u32, u32)) -> Result<Vec<Match>> {
+    let query = Query::Matches {
+        page: p,
+        text: query.to_string(),
+        size,
+    };
+    Ok(serde_json::from_value(render::ask(file, &query)?)?)
+}
+
+/// `on_page`, for the renderer
+pub fn matches_in(
+    document: &PdfDocument,
+    p: usize,
+    query: &str,
+    size: (u32, u32),
+) -> Result<Vec<Match>> {
+    let page = document.pages().get(p as u16)?;
+    let (width, height) = (page.width().value as f64, page.height().value as f64);
+    let rotated = width > height && size.1 > size.0;
+
+    let text = page.text()?;
+    let mut chars: Vec<(char, Option<PdfRect>)> = vec![];
+    for c in text.chars().iter() {
+        let bounds = c.loose_bounds().ok();
+        for lower in c.unicode_char().unwrap_or(' ').to_lowercase() {
+            chars.push((lower, bounds));
+        }
+    }
+    let wanted: Vec<char> = query.to_lowercase().chars().collect();
+    if wanted.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut matches = vec![];
+    let mut i = 0;
+    while i + wanted.len() <= chars.len() {
+        let found = chars[i..i + wanted.len()]
+            .iter()
+            .zip(&wanted)
+            .all(|((c, _), w)| c == w || (c.is_whitespace() && w.is_whitespace()));
+        if !found {
+            i += 1;
+            continue;
+        }
+        let mut rects = vec![];
+        for (_, bounds) in &chars[i..i + wanted.len()] {
+            let bounds = match bounds {
+                Some(b) => b,
+                None => continue,
+            };
+            // PDF coordinates go up from the bottom of the page
+            let left = bounds.left.value as f64 / width;
+            let right = bounds.right.value as f64 / width;
+            let top = 1.0 - bounds.top.value as f64 / height;
+            let bottom = 1.0 - bounds.bottom.value as f64 / height;
+            // Turned 90° clockwise, the bottom of the page is on the left
+            rects.push(match rotated {
+                true => (1.0 - bottom, left, bottom - top, right - left),
+                false => (left, top, right - left, bottom - top),
+            });
+        }
+        matches.push(rects);
+        i += wanted.len();
+    }
+    Ok(matches)
+}
+
+/// Text with its runs of whitespace, line breaks included, made single
+/// spaces, in lower case
+fn normalise(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// The next page of `pages` after `from` (before it, going back) with
+/// `query` on it, going round from the end to the start, and ending on
+/// `from` itself if it's the only one
+pub fn next_page(
+    file: &str,
+    pages: Range<usize>,
+    from: usize,
+    query: &str,
+    forward: bool,
+) -> Result<Option<usize>> {
+    let len = pages.len();
+    let order = (1..=len)
+        .map(|k| {
+            let offset = match forward {
+                true => from - pages.start + k,
+                false => from - pages.start + len - k,
+            };
+            pages.start + offset % len
+        })
+        .collect();
+    let query = Query::Find {
+        pages: order,
+        text: query.to_string(),
+    };
+    Ok(serde_json::from_value(render::ask(file, &query)?)?)
+}
+
+/// The first of `pages` with `query` on it, for the renderer
+pub fn first_in(document: &PdfDocument, pages: &[usize], query: &str) -> Result<Option<usize>> {
+    let wanted = normalise(query);
+    for &p in pages {
+        let page = document.pages().get(p as u16)?;
+        if normalise(&page.text()?.all()).contains(&wanted) {
+            return Ok(Some(p));
+        }
+    }
+    Ok(None)
+}
+
+/// The page with `matches` marked in yellow, like a highlighter pen: the
+/// yellow is multiplied in, so the text stays black
+pub fn highlight(page: &DynamicImage, matches: &[Match]) -> DynamicImage {
+    let mut image: RgbaImage = page.to_rgba8();
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    for &(x, y, w, h) in matches.iter().flatten() {
+        let x0 = (x * width).floor().max(0.0) as u32;
+        let y0 = (y * height).floor().max(0.0) as u32;
+        let x1 = ((x + w) * width).ceil().min(width) as u32;
+        let y1 = ((y + h) * height).ceil().min(height) as u32;
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let pixel = image.get_pixel_mut(px, py);
+                pixel.0[2] = (pixel.0[2] as u32 * 60 / 255) as u8;
+                pixel.0[1] = (pixel.0[1] as u32 * 235 / 255) as u8;
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// A red frame round each match, to catch the eye
+pub fn outline(page: &DynamicImage, matches: &[Match]) -> DynamicImage {
+    let mut image: RgbaImage = page.to_rgba8();
+    let (width, height) = (image.width() as f64, image.height() as f64);
+    // Thick enough to see on a page scaled down to the terminal
+    let thickness = (image.width() / 200).max(3) as f64;
+    for rects in matches {
+        let x0 = rects.iter().map(|r| r.0).fold(f64::MAX, f64::min) * width - thickness;
+        let y0 = rects.iter().map(|r| r.1).fold(f64::MAX, f64::min) * height - thickness;
+        let x1 = rects.iter().map(|r| r.0 + r.2).fold(0.0, f64::max) * width + thickness;
+        let y1 = rects.iter().map(|r| r.1 + r.3).fold(0.0, f64::max) * height + thickness;
+        let (x0, y0) = (x0.max(0.0) as u32, y0.max(0.0) as u32);
+        let (x1, y1) = (x1.min(width) as u32, y1.min(height) as u32);
+        let t = thickness as u32;
+        for py in y0..y1 {
+            for px in x0..x1 {
+                let edge = px < x0 + t || px + t >= x1 || py < y0 + t || py + t >= y1;
+                if edge {
+                    image.put_pixel(px, py, image::Rgba([220, 30, 30, 255]));
+                }
+            }
+        }
+    }
+    DynamicImage::ImageRgba8(image)
+}
@@ -0,0 +1,135 @@
+//! Picking a rectangle of the page with the keyboard.
+//!
+//! The rectangle is kept as fractions of the page image, so it means the same
+//! part of the page whatever resolution the page is rendered at.
+
+use image::{DynamicImage, GenericImageView, Rgba, RgbaImage};
+use termion::event::Key;
+
+/// What to do with the part of the page that's picked
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Purpose {
+    Screenshot,
+    /// Convert an equation to LaTeX
+    Latex,
+    /// Look a word up in the dictionary
+    Lookup,
+}
+
+/// What the user asked for when they finished picking
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Save,
+    Copy,
+}
+
+pub enum SelectResult {
+    Pending,
+    Cancel,
+    Done(Action),
+}
+
+#[derive(Clone, Debug)]
+pub struct Selection {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+    pub purpose: Purpose,
+}
+
+const STEP: f64 = 0.02;
+const MIN: f64 = 0.02;
+
+impl Selection {
+    pub fn new(purpose: Purpose) -> Selection {
+        match purpose {
+            // About the size of a word
+            Purpose::Lookup => Selection {
+                x: 0.42,
+                y: 0.48,
+                w: 0.16,
+                h: 0.04,
+                purpose,
+            },
+            _ => Selection {
+                x: 0.25,
+                y: 0.35,
+                w: 0.5,
+                h: 0.3,
+                purpose,
+            },
+        }
+    }
+
+    pub fn help(&self) -> &'static str {
+        match self.purpose {
+            Purpose::Screenshot => "select: hjkl move  HJKL resize  Enter save  y copy  Esc cancel",
+            Purpose::Latex => "select equation: hjkl move  HJKL resize  Enter convert  Esc cancel",
+            Purpose::Lookup => "select word: hjkl move  HJKL resize  Enter look up  Esc cancel",
+        }
+    }
+
+    pub fn key(&mut self, key: Key) -> SelectResult {
+        match key {
+            Key::Char('h') | Key::Left => self.x -= STEP,
+            Key::Char('l') | Key::Right => self.x += STEP,
+            Key::Char('k') | Key::Up => self.y -= STEP,
+            Key::Char('j') | Key::Down => self.y += STEP,
+            Key::Char('H') => self.w -= STEP,
+            Key::Char('L') => self.w += STEP,
+            Key::Char('K') => self.h -= STEP,
+            Key::Char('J') => self.h += STEP,
+            Key::Char('\n') => return SelectResult::Done(Action::Save),
+            Key::Char('y') => return SelectResult::Done(Action::Copy),
+            Key::Esc | Key::Char('q') => return SelectResult::Cancel,
+            _ => {}
+        }
+        self.w = self.w.clamp(MIN, 1.0);
+        self.h = self.h.clamp(MIN, 1.0);
+        self.x = self.x.clamp(0.0, 1.0 - self.w);
+        self.y = self.y.clamp(0.0, 1.0 - self.h);
+        SelectResult::Pending
+    }
+
+    /// The selection in pixels of an image of `size`, as (x, y, w, h)
+    pub fn pixels(&self, size: (u32, u32)) -> (u32, u32, u32, u32) {
+        let (width, height) = (size.0 as f64, size.1 as f64);
+        let x = (self.x * width).round() as u32;
+        let y = (self.y * height).round() as u32;
+        let w = ((self.w * width).round() as u32).clamp(1, size.0.saturating_sub(x).max(1));
+        let h = ((self.h * height).round() as u32).clamp(1, size.1.saturating_sub(y).max(1));
+        (x, y, w, h)
+    }
+
+    /// The page with everything outside the selection dimmed and the
+    /// selection outlined
+    pub fn draw(&self, page: &DynamicImage) -> DynamicImage {
+        let mut image: RgbaImage = page.to_rgba8();
+        let (x, y, w, h) = self.pixels(image.dimensions());
+        let border = (image.width() / 400).max(2);
+        let outline = Rgba([220, 40, 40, 255]);
+
+        for (px, py, pixel) in image.enumerate_pixels_mut() {
+            let inside = px >= x && px < x + w && py >= y && py < y + h;
+            let on_border = inside
+                && (px < x + border
+                    || px >= x + w - border.min(w)
+                    || py < y + border
+                    || py >= y + h - border.min(h));
+            if on_border {
+                *pixel = outline;
+            } else if !inside {
+                for c in pixel.0.iter_mut().take(3) {
+                    *c /= 2;
+                }
+            }
+        }
+        DynamicImage::ImageRgba8(image)
+    }
+
+    pub fn crop(&self, page: &DynamicImage) -> DynamicImage {
+        let (x, y, w, h) = self.pixels(page.dimensions());
+        page.crop_imm(x, y, w, h)
+    }
+}
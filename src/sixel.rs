@@ -0,0 +1,164 @@
+//! Encoding images as sixels, for xterm, foot, mlterm and the other
+//! terminals that only draw those. Sixels are drawn a pixel per pixel, so the
+//! image has to be scaled to the size it's shown at first.
+//!
+//! The palette is a colour cube (6×6×6, unless `sixel_colors` is fewer than
+//! 256) plus a ramp of greys, because pages are mostly black text on white
+//! and anti-aliased edges need the greys.
+
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::OnceLock;
+
+use image::RgbaImage;
+
+use crate::dither::{self, Colours, Dither};
+
+/// A colour cube with `levels` steps of each of red, green and blue, and
+/// `greys` steps from black to white
+#[derive(Clone, Copy, Debug)]
+struct Palette {
+    levels: usize,
+    greys: usize,
+}
+
+/// Fewest greys a palette has, which anti-aliased text needs more than
+/// it needs colours
+const MIN_GREYS: usize = 8;
+const MAX_GREYS: usize = 32;
+
+impl Palette {
+    /// The biggest palette of at most `colours` entries (from 16, which is
+    /// 2×2×2 and 8 greys, to 256, which is 6×6×6 and 32)
+    fn new(colours: usize) -> Palette {
+        let colours = colours.clamp(16, 256);
+        let levels = (2..=6)
+            .rev()
+            .find(|l| l * l * l + MIN_GREYS <= colours)
+            .unwrap_or(2);
+        let greys = (colours - levels * levels * levels).min(MAX_GREYS);
+        Palette { levels, greys }
+    }
+
+    fn cube(&self) -> usize {
+        self.levels * self.levels * self.levels
+    }
+
+    fn len(&self) -> usize {
+        self.cube() + self.greys
+    }
+}
+
+impl Colours for Palette {
+    fn index(&self, rgb: [i32; 3]) -> usize {
+        let rgb = rgb.map(|c| c.clamp(0, 255));
+        let [r, g, b] = rgb.map(|c| c as usize);
+        if dither::greyish(rgb) {
+            let grey = (r + g + b) / 3;
+            self.cube() + (grey * (self.greys - 1) + 127) / 255
+        } else {
+            let top = self.levels - 1;
+            let level = |c: usize| (c * top + 127) / 255;
+            (level(r) * self.levels + level(g)) * self.levels + level(b)
+        }
+    }
+
+    fn rgb(&self, index: usize) -> [i32; 3] {
+        if index >= self.cube() {
+            let grey = ((index - self.cube()) * 255 / (self.greys - 1)) as i32;
+            [grey; 3]
+        } else {
+            let top = self.levels - 1;
+            let level = |l: usize| (l * 255 / top) as i32;
+            let n = self.levels;
+            [
+                level(index / (n * n)),
+                level(index / n % n),
+                level(index % n),
+            ]
+        }
+    }
+
+    fn step(&self) -> i32 {
+        255 / (self.levels as i32 - 1)
+    }
+}
+
+static OPTIONS: OnceLock<(Palette, Dither)> = OnceLock::new();
+
+/// Choose the palette size and dithering, before any sixels are made
+pub fn configure(colours: usize, dither: Dither) {
+    let _ = OPTIONS.set((Palette::new(colours), dither));
+}
+
+fn options() -> (Palette, Dither) {
+    *OPTIONS.get().unwrap_or(&(Palette::new(256), Dither::Auto))
+}
+
+/// Add `count` copies of `sixel`, run-length encoded
+fn push_run(out: &mut String, sixel: u8, count: usize) {
+    let c = (sixel + 63) as char;
+    match count {
+        0 => {}
+        1..=3 => (0..count).for_each(|_| out.push(c)),
+        _ => {
+            let _ = write!(out, "!{}{}", count, c);
+        }
+    }
+}
+
+/// The escape sequence that draws `image` at the cursor
+pub fn encode(image: &RgbaImage) -> String {
+    let (width, height) = image.dimensions();
+    let (palette, dither) = options();
+    let pixels: Vec<[i32; 3]> = image
+        .pixels()
+        .map(|p| [p.0[0] as i32, p.0[1] as i32, p.0[2] as i32])
+        .collect();
+    let indices = dither::indices(&pixels, width as usize, &palette, dither);
+
+    // Square pixels, and the size up front so the terminal can make room
+    let mut out = format!("\x1bP0;1;0q\"1;1;{};{}", width, height);
+    let mut used = vec![false; palette.len()];
+    indices.iter().for_each(|&i| used[i] = true);
+    for (i, _) in used.iter().enumerate().filter(|(_, &u)| u) {
+        // Sixel colours are in percent
+        let [r, g, b] = palette.rgb(i).map(|c| c * 100 / 255);
+        let _ = write!(out, "#{};2;{};{};{}", i, r, g, b);
+    }
+
+    let width = width as usize;
+    for top in (0..height as usize).step_by(6) {
+        // Each colour in this band of six rows, with a bit for each row it's
+        // in, column by column
+        let mut bands: BTreeMap<usize, Vec<u8>> = BTreeMap::new();
+        for dy in 0..6.min(height as usize - top) {
+            let row = &indices[(top + dy) * width..(top + dy + 1) * width];
+            for (x, &i) in row.iter().enumerate() {
+                bands.entry(i).or_insert_with(|| vec![0; width])[x] |= 1 << dy;
+            }
+        }
+        for (n, (i, sixels)) in bands.iter().enumerate() {
+            if n > 0 {
+                // Back to the start of the band for the next colour
+                out.push('$');
+            }
+            let _ = write!(out, "#{}", i);
+            let mut run = (sixels[0], 0);
+            for &sixel in sixels {
+                if sixel != run.0 {
+                    push_run(&mut out, run.0, run.1);
+                    run = (sixel, 0);
+                }
+                run.1 += 1;
+            }
+            // Nothing to draw to the end of the line
+            if run.0 != 0 {
+                push_run(&mut out, run.0, run.1);
+            }
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
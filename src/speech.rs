@@ -0,0 +1,42 @@
+//! Screen-reader mode: the text of each page turned to, written out as plain
+//! lines on the status line, one after another, for a terminal screen reader
+//! to speak as it arrives. The page itself is drawn as usual.
+
+use anyhow::Result;
+
+use crate::term;
+
+/// `text` as lines no wider than the window, so none of them wraps, with
+/// the blank ones left out
+fn lines(text: &str, width: usize) -> Vec<String> {
+    let mut out = vec![];
+    for line in text.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let mut current = String::new();
+        for word in line.split_whitespace() {
+            let len = current.chars().count();
+            if len > 0 && len + 1 + word.chars().count() > width {
+                out.push(std::mem::take(&mut current));
+            }
+            if !current.is_empty() {
+                current.push(' ');
+            }
+            current.push_str(word);
+        }
+        out.push(current);
+    }
+    out
+}
+
+/// Write out the text of page `p`, which is `text`
+pub fn say(p: usize, text: &str) -> Result<()> {
+    let (cols, _) = termion::terminal_size()?;
+    let lines = lines(text, cols.saturating_sub(1).max(1) as usize);
+    if lines.is_empty() {
+        return term::status_line(&format!("Page {} has no text", p + 1));
+    }
+    term::status_line(&format!("Page {}", p + 1))?;
+    for line in lines {
+        term::status_line(&line)?;
+    }
+    Ok(())
+}
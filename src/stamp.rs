@@ -0,0 +1,253 @@
+//! Writing on pictures of pages: where an exported one came from, so an
+//! excerpt passed on still says which document and page it's from, and the
+//! `watermark` across every page shown or exported.
+
+use std::env;
+use std::sync::{Mutex, OnceLock};
+
+use anyhow::{bail, Result};
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::render;
+
+static WATERMARK: OnceLock<Option<String>> = OnceLock::new();
+
+/// The last watermark drawn, to lay over the next page of the same size
+/// without setting it again
+static LAYER: Mutex<Option<((u32, u32), RgbaImage)>> = Mutex::new(None);
+
+/// Text set by pdfium on a page of its own and rendered, which is done by
+/// `render`'s worker like everything else pdfium does
+pub enum Drawing {
+    /// `text` from corner to corner of an image of `size`
+    Watermark { size: (u32, u32), text: String },
+    /// `label` on a strip `width` by `height` pixels, in type `size` high
+    Label {
+        width: u32,
+        height: u32,
+        size: f32,
+        label: String,
+    },
+    /// `text` on a badge `size` pixels high
+    Badge { text: String, size: f32 },
+}
+
+impl Drawing {
+    pub fn to_json(&self) -> Value {
+        match self {
+            Drawing::Watermark { size, text } => {
+                json!({ "kind": "watermark", "width": size.0, "height": size.1, "text": text })
+            }
+            Drawing::Label {
+                width,
+                height,
+                size,
+                label,
+            } => json!({
+                "kind": "label",
+                "width": width,
+                "height": height,
+                "size": size,
+                "text": label,
+            }),
+            Drawing::Badge { text, size } => json!({ "kind": "badge", "size": size, "text": text }),
+        }
+    }
+
+    pub fn from_json(value: &Value) -> Result<Drawing> {
+        let number = |key: &str| value[key].as_u64().unwrap_or(1) as u32;
+        let size = value["size"].as_f64().unwrap_or(12.0) as f32;
+        let text = value["text"].as_str().unwrap_or_default().to_string();
+        Ok(match value["kind"].as_str().unwrap_or_default() {
+            "watermark" => Drawing::Watermark {
+                size: (number("width"), number("height")),
+                text,
+            },
+            "label" => Drawing::Label {
+                width: number("width"),
+                height: number("height"),
+                size,
+                label: text,
+            },
+            "badge" => Drawing::Badge { text, size },
+            kind => bail!("no such drawing as {:?}", kind),
+        })
+    }
+}
+
+/// Take the watermark from the config, with `{user}` filled in
+pub fn configure(config: &Config) {
+    let user = env::var("USER")
+        .or_else(|_| env::var("LOGNAME"))
+        .unwrap_or_default();
+    let text = config
+        .watermark
+        .as_ref()
+        .map(|text| text.replace("{user}", &user));
+    let _ = WATERMARK.set(text);
+}
+
+/// `image` with the watermark across it, if there is one
+pub fn watermarked(image: DynamicImage) -> DynamicImage {
+    let text = match WATERMARK.get() {
+        Some(Some(text)) => text,
+        _ => return image,
+    };
+    let size = (image.width(), image.height());
+    let mut layer = LAYER.lock().unwrap_or_else(|e| e.into_inner());
+    if layer.as_ref().map(|(s, _)| *s) != Some(size) {
+        let drawing = Drawing::Watermark {
+            size,
+            text: text.clone(),
+        };
+        match render::draw(&drawing) {
+            Ok(drawn) => *layer = Some((size, drawn)),
+            // A page without it is better than no page
+            Err(_) => return image,
+        }
+    }
+    let mut image = image.to_rgba8();
+    if let Some((_, drawn)) = layer.as_ref() {
+        imageops::overlay(&mut image, drawn, 0, 0);
+    }
+    DynamicImage::ImageRgba8(image)
+}
+
+/// `text` in faint grey from corner to corner of a transparent image of
+/// `size`
+fn watermark(pdfium: &Pdfium, (width, height): (u32, u32), text: &str) -> Result<RgbaImage> {
+    let mut document = pdfium.create_new_pdf()?;
+    let font = document.fonts_mut().helvetica_bold();
+    let mut page = document
+        .pages_mut()
+        .create_page_at_end(PdfPagePaperSize::Custom(
+            PdfPoints::new(width as f32),
+            PdfPoints::new(height as f32),
+        ))?;
+
+    // Sized to run along most of the diagonal, going by how wide it is at 100pt
+    let (w, h) = (width as f32, height as f32);
+    let diagonal = (w * w + h * h).sqrt();
+    let measure = PdfPageTextObject::new(&document, text, font, PdfPoints::new(100.0))?;
+    let size = 100.0 * diagonal * 0.7 / measure.width()?.value.max(1.0);
+    let size = size.min(h / 4.0);
+
+    let mut object = PdfPageTextObject::new(&document, text, font, PdfPoints::new(size))?;
+    let length = object.width()?.value;
+    object.translate(PdfPoints::new(-length / 2.0), PdfPoints::new(-size / 3.0))?;
+    object.rotate_counter_clockwise_degrees(h.atan2(w).to_degrees())?;
+    object.translate(PdfPoints::new(w / 2.0), PdfPoints::new(h / 2.0))?;
+    page.objects_mut().add_text_object(object)?;
+
+    // Rendered black on white, then made grey and see-through in
+    // proportion to how dark it came out, which keeps the edges smooth
+    let config = PdfRenderConfig::new().set_target_width(width as Pixels);
+    let mut layer = page.render_with_config(&config)?.as_image().to_rgba8();
+    for pixel in layer.pixels_mut() {
+        let [r, g, b, _] = pixel.0;
+        let dark = 255 - ((r as u32 + g as u32 + b as u32) / 3) as u8;
+        *pixel = Rgba([128, 128, 128, dark / 4]);
+    }
+    Ok(layer)
+}
+
+/// `image` with a strip underneath saying `label`. The text is set by
+/// pdfium on a page of its own, the size of the strip, which is rendered a
+/// pixel to a point.
+pub fn stamp(image: &DynamicImage, label: &str) -> Result<DynamicImage> {
+    let width = image.width();
+    // Readable on a small excerpt, not overwhelming on a whole page
+    let size = (width as f32 / 40.0).clamp(10.0, 28.0);
+    let height = (size * 1.8).ceil() as u32;
+    let strip = render::draw(&Drawing::Label {
+        width,
+        height,
+        size,
+        label: label.to_string(),
+    })?;
+
+    let mut stamped = RgbaImage::from_pixel(width, image.height() + height, Rgba([255; 4]));
+    imageops::overlay(&mut stamped, &image.to_rgba8(), 0, 0);
+    imageops::overlay(&mut stamped, &strip, 0, image.height() as i64);
+    Ok(DynamicImage::ImageRgba8(stamped))
+}
+
+/// `label` on a strip on its own, as `stamp` puts under a page
+fn label(
+    pdfium: &Pdfium,
+    (width, height): (u32, u32),
+    size: f32,
+    label: &str,
+) -> Result<RgbaImage> {
+    let mut document = pdfium.create_new_pdf()?;
+    let font = document.fonts_mut().helvetica();
+    let mut page = document
+        .pages_mut()
+        .create_page_at_end(PdfPagePaperSize::Custom(
+            PdfPoints::new(width as f32),
+            PdfPoints::new(height as f32),
+        ))?;
+    page.objects_mut().create_text_object(
+        PdfPoints::new(size * 0.6),
+        PdfPoints::new(size * 0.6),
+        label,
+        font,
+        PdfPoints::new(size),
+    )?;
+    let strip = page
+        .render_with_config(&PdfRenderConfig::new().set_target_width(width as Pixels))?
+        .as_image();
+    Ok(strip.to_rgba8())
+}
+
+/// `text` in black on yellow, `size` pixels high, like a sticky label, for
+/// marking things on a page
+pub fn badge(text: &str, size: f32) -> Result<RgbaImage> {
+    render::draw(&Drawing::Badge {
+        text: text.to_string(),
+        size,
+    })
+}
+
+fn draw_badge(pdfium: &Pdfium, text: &str, size: f32) -> Result<RgbaImage> {
+    let mut document = pdfium.create_new_pdf()?;
+    let font = document.fonts_mut().helvetica_bold();
+    let measure = PdfPageTextObject::new(&document, text, font, PdfPoints::new(size))?;
+    let width = (measure.width()?.value + size * 0.6).ceil();
+    let height = (size * 1.3).ceil();
+    let mut page = document
+        .pages_mut()
+        .create_page_at_end(PdfPagePaperSize::Custom(
+            PdfPoints::new(width),
+            PdfPoints::new(height),
+        ))?;
+    page.objects_mut().create_text_object(
+        PdfPoints::new(size * 0.3),
+        PdfPoints::new(size * 0.35),
+        text,
+        font,
+        PdfPoints::new(size),
+    )?;
+    let config = PdfRenderConfig::new()
+        .set_target_width(width as Pixels)
+        .set_clear_color(PdfColor::new(255, 220, 0, 255));
+    let badge = page.render_with_config(&config)?.as_image().to_rgba8();
+    Ok(badge)
+}
+
+/// Draw `drawing`, for the renderer
+pub fn draw(pdfium: &Pdfium, drawing: &Drawing) -> Result<RgbaImage> {
+    match drawing {
+        Drawing::Watermark { size, text } => watermark(pdfium, *size, text),
+        Drawing::Label {
+            width,
+            height,
+            size,
+            label: text,
+        } => label(pdfium, (*width, *height), *size, text),
+        Drawing::Badge { text, size } => draw_badge(pdfium, text, *size),
+    }
+}
@@ -0,0 +1,45 @@
+//! Things termpdf remembers between sessions, kept as small JSON files in
+//! `$XDG_STATE_HOME/termpdf` (usually `~/.local/state/termpdf`).
+
+use std::env;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde_json::Value;
+
+use crate::atomic;
+use crate::config::home_dir;
+
+pub fn state_dir() -> PathBuf {
+    match env::var_os("XDG_STATE_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("termpdf"),
+        _ => home_dir().join(".local").join("state").join("termpdf"),
+    }
+}
+
+/// Documents are remembered by absolute path, so the same file opened from
+/// different directories is the same document.
+pub fn key(file: &str) -> String {
+    let path = Path::new(file);
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Read a state file, treating a missing one as empty
+pub fn load(name: &str) -> Result<Value> {
+    let path = state_dir().join(name);
+    match std::fs::read_to_string(&path) {
+        Ok(contents) => serde_json::from_str(&contents)
+            .with_context(|| format!("Couldn't parse {}", path.display())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Value::Null),
+        Err(e) => Err(e).with_context(|| format!("Couldn't read {}", path.display())),
+    }
+}
+
+pub fn save(name: &str, value: &Value) -> Result<()> {
+    let dir = state_dir();
+    std::fs::create_dir_all(&dir).with_context(|| format!("Couldn't create {}", dir.display()))?;
+    atomic::write(dir.join(name), serde_json::to_string_pretty(value)?, 0)
+}
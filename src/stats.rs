@@ -0,0 +1,165 @@
+//! `termpdf stats FILE... [--json]`: what's in each document, for keeping
+//! track of a large collection of them from scripts.
+
+use std::collections::HashSet;
+use std::fs;
+
+use anyhow::Result;
+use pdfium_render::prelude::*;
+use serde_json::{json, Value};
+
+use crate::bind_pdfium;
+
+#[derive(Debug, Default)]
+struct Stats {
+    pages: usize,
+    bytes: u64,
+    producer: String,
+    creator: String,
+    encrypted: bool,
+    /// Needs a password to open, so nothing else could be read
+    locked: bool,
+    /// Different fonts the text is set in
+    fonts: usize,
+    /// How many of them are embedded, going by the `ABCDEF+` prefix of a
+    /// subset, which is how nearly all embedded fonts come
+    embedded_fonts: usize,
+    images: usize,
+}
+
+fn read(pdfium: &Pdfium, file: &str) -> Result<Stats> {
+    let bytes = fs::metadata(file)?.len();
+    let document = match pdfium.load_pdf_from_file(file, None) {
+        Ok(document) => document,
+        // Nothing else can be read without the password
+        Err(PdfiumError::PdfiumLibraryInternalError(PdfiumInternalError::PasswordError)) => {
+            return Ok(Stats {
+                bytes,
+                encrypted: true,
+                locked: true,
+                ..Stats::default()
+            })
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let metadata = document.metadata();
+    let get = |tag| {
+        metadata
+            .get(tag)
+            .map(|t| t.value().trim().to_string())
+            .unwrap_or_default()
+    };
+    let mut fonts = HashSet::new();
+    let mut images = 0;
+    for page in document.pages().iter() {
+        images += page
+            .objects()
+            .iter()
+            .filter(|object| object.object_type() == PdfPageObjectType::Image)
+            .count();
+        if let Ok(text) = page.text() {
+            for c in text.chars().iter() {
+                let name = c.font_name();
+                if !name.is_empty() {
+                    fonts.insert(name);
+                }
+            }
+        }
+    }
+    let subset = |name: &&String| {
+        name.split_once('+')
+            .is_some_and(|(tag, _)| tag.len() == 6 && tag.chars().all(|c| c.is_ascii_uppercase()))
+    };
+    Ok(Stats {
+        pages: document.pages().len() as usize,
+        bytes,
+        producer: get(PdfDocumentMetadataTagType::Producer),
+        creator: get(PdfDocumentMetadataTagType::Creator),
+        encrypted: document
+            .permissions()
+            .security_handler_revision()
+            .map(|r| r != PdfSecurityHandlerRevision::Unprotected)
+            .unwrap_or(false),
+        locked: false,
+        embedded_fonts: fonts.iter().filter(subset).count(),
+        fonts: fonts.len(),
+        images,
+    })
+}
+
+/// A number of bytes the way people read them
+pub fn size(bytes: u64) -> String {
+    match bytes {
+        b if b >= 1 << 20 => format!("{:.1} MB", b as f64 / (1 << 20) as f64),
+        b if b >= 1 << 10 => format!("{:.0} KB", b as f64 / (1 << 10) as f64),
+        b => format!("{} bytes", b),
+    }
+}
+
+/// `n` of `thing`, like "1 page" or "3 pages"
+pub fn count(n: usize, thing: &str) -> String {
+    match n {
+        1 => format!("1 {}", thing),
+        n => format!("{} {}s", n, thing),
+    }
+}
+
+fn line(file: &str, stats: &Stats) -> String {
+    if stats.locked {
+        return format!("{}: {}, needs a password", file, size(stats.bytes));
+    }
+    let mut parts = vec![count(stats.pages, "page"), size(stats.bytes)];
+    if !stats.producer.is_empty() {
+        parts.push(stats.producer.clone());
+    }
+    if stats.encrypted {
+        parts.push("encrypted".to_string());
+    }
+    parts.push(format!(
+        "{} ({} embedded), {}",
+        count(stats.fonts, "font"),
+        stats.embedded_fonts,
+        count(stats.images, "image")
+    ));
+    format!("{}: {}", file, parts.join(", "))
+}
+
+fn to_json(file: &str, stats: &Stats) -> Value {
+    json!({
+        "file": file,
+        "pages": stats.pages,
+        "bytes": stats.bytes,
+        "producer": stats.producer,
+        "creator": stats.creator,
+        "encrypted": stats.encrypted,
+        "locked": stats.locked,
+        "fonts": stats.fonts,
+        "embedded_fonts": stats.embedded_fonts,
+        "images": stats.images,
+    })
+}
+
+/// Report on each of `files`, as JSON if `as_json`. Returns whether they
+/// could all be read.
+pub fn run(files: &[String], as_json: bool) -> Result<bool> {
+    let pdfium = bind_pdfium()?;
+    let mut ok = true;
+    let mut all = vec![];
+    for file in files {
+        match read(&pdfium, file) {
+            Ok(stats) if as_json => all.push(to_json(file, &stats)),
+            Ok(stats) => println!("{}", line(file, &stats)),
+            Err(e) => {
+                ok = false;
+                match as_json {
+                    true => all.push(json!({ "file": file, "error": e.to_string() })),
+                    false => eprintln!("{}: {}", file, e),
+                }
+            }
+        }
+    }
+    if as_json {
+        println!("{}", Value::Array(all));
+    }
+    Ok(ok)
+}
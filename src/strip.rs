@@ -0,0 +1,199 @@
+//! The thumbnail strip: in a window wide enough to spare the room, the
+//! pages either side of the one shown, small, down the right, with that one
+//! picked out, to see whereabouts in the document it is.
+//!
+//! Thumbnails are rendered in the background as pages come into the strip,
+//! and the strip is drawn again as they arrive.
+
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::Sender;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+use crate::{filter, render, Msg};
+
+/// Between thumbnails, and around the edge
+const GAP: u32 = 8;
+/// How much taller than wide a thumbnail's space is, as in the gallery
+const THUMB_ASPECT: f64 = 1.414;
+
+const BACKGROUND: Rgba<u8> = Rgba([48, 48, 48, 255]);
+const CURRENT: Rgba<u8> = Rgba([40, 90, 220, 255]);
+const MISSING: Rgba<u8> = Rgba([90, 90, 90, 255]);
+
+/// Whether the strip needs drawing again, the page having been drawn over
+/// it or a thumbnail having arrived
+static STALE: AtomicBool = AtomicBool::new(true);
+
+pub fn invalidate() {
+    STALE.store(true, Ordering::Relaxed);
+}
+
+/// Whether the strip needs drawing again, which it won't after this until
+/// something else says so
+pub fn take_stale() -> bool {
+    STALE.swap(false, Ordering::Relaxed)
+}
+
+/// A page rendered small for the strip
+#[derive(Debug)]
+pub struct Thumb {
+    file: String,
+    /// Which of the strip's `forget`s it was sent for after
+    generation: usize,
+    page: usize,
+    image: DynamicImage,
+}
+
+#[derive(Debug, Default)]
+pub struct Strip {
+    file: String,
+    generation: usize,
+    /// The size thumbnails are rendered to fit
+    size: (u32, u32),
+    thumbs: HashMap<usize, DynamicImage>,
+    /// Pages sent to be rendered that haven't come back
+    asked: HashSet<usize>,
+}
+
+/// The space each thumbnail has in a strip `size` pixels big, and how many
+/// there's room for
+fn layout((width, height): (u32, u32)) -> ((u32, u32), usize) {
+    let thumb_w = width.saturating_sub(GAP * 2).max(1);
+    let thumb_h = (thumb_w as f64 * THUMB_ASPECT) as u32;
+    let count = (height.saturating_sub(GAP) / (thumb_h + GAP)).max(1);
+    // Shrunk to fit a single one that's too tall for the window
+    let thumb_h = thumb_h.min(height.saturating_sub(GAP * 2).max(1));
+    ((thumb_w, thumb_h), count as usize)
+}
+
+/// The pages in the strip: `count` of them with `current` in the middle,
+/// or as near as `range` allows
+fn pages(current: usize, range: &Range<usize>, count: usize) -> Range<usize> {
+    let start = current
+        .saturating_sub(count / 2)
+        .min(range.end.saturating_sub(count))
+        .max(range.start);
+    start..(start + count).min(range.end)
+}
+
+impl Strip {
+    /// Forget the thumbnails, which no longer look like the pages
+    pub fn forget(&mut self) {
+        self.generation += 1;
+        self.thumbs.clear();
+        self.asked.clear();
+    }
+
+    /// Send for the thumbnails of `file` a strip `size` pixels big around
+    /// `current` hasn't got, to come back on `tx`
+    pub fn prefetch(
+        &mut self,
+        file: &str,
+        size: (u32, u32),
+        current: usize,
+        range: &Range<usize>,
+        tx: &Sender<Msg>,
+    ) {
+        let (thumb, count) = layout(size);
+        if self.file != file || self.size != thumb {
+            self.forget();
+            self.file = file.to_string();
+            self.size = thumb;
+        }
+        let missing: Vec<usize> = pages(current, range, count)
+            .filter(|p| !self.thumbs.contains_key(p) && !self.asked.contains(p))
+            .collect();
+        if missing.is_empty() {
+            return;
+        }
+        self.asked.extend(&missing);
+        render_thumbs(
+            file.to_string(),
+            self.generation,
+            missing,
+            thumb,
+            tx.clone(),
+        );
+    }
+
+    /// A thumbnail sent for with `prefetch` has arrived
+    pub fn insert(&mut self, thumb: Thumb) {
+        if thumb.file == self.file
+            && thumb.generation == self.generation
+            && self.asked.remove(&thumb.page)
+        {
+            self.thumbs.insert(thumb.page, thumb.image);
+            invalidate();
+        }
+    }
+
+    /// The strip, `size` pixels big, around `current`
+    pub fn draw(&self, size: (u32, u32), current: usize, range: &Range<usize>) -> DynamicImage {
+        let (cell, count) = layout(size);
+        let mut canvas = RgbaImage::from_pixel(size.0.max(1), size.1.max(1), BACKGROUND);
+        for (i, p) in pages(current, range, count).enumerate() {
+            let (x, y) = (GAP, GAP + i as u32 * (cell.1 + GAP));
+            let thumb = match self.thumbs.get(&p) {
+                Some(image) => filter::filtered(image.clone()).to_rgba8(),
+                None => RgbaImage::from_pixel(cell.0, cell.1, MISSING),
+            };
+            let (w, h) = (thumb.width().min(cell.0), thumb.height().min(cell.1));
+            // Centred in its space
+            let (x, y) = (x + (cell.0 - w) / 2, y + (cell.1 - h) / 2);
+            imageops::overlay(&mut canvas, &thumb, x as i64, y as i64);
+            if p == current {
+                frame(&mut canvas, (x, y, w, h));
+            }
+        }
+        DynamicImage::ImageRgba8(canvas)
+    }
+}
+
+/// A border just outside `x, y, w, h`, in the gap around it
+fn frame(canvas: &mut RgbaImage, (x, y, w, h): (u32, u32, u32, u32)) {
+    let border = GAP / 2;
+    let (left, top) = (x.saturating_sub(border), y.saturating_sub(border));
+    let right = (x + w + border).min(canvas.width());
+    let bottom = (y + h + border).min(canvas.height());
+    for py in top..bottom {
+        for px in left..right {
+            if px < x || px >= x + w || py < y || py >= y + h {
+                canvas.put_pixel(px, py, CURRENT);
+            }
+        }
+    }
+}
+
+/// Render `pages` of `file` to fit in `size`, one after another, sending
+/// each as `Msg::Thumbnail` when it's done. One that can't be rendered is
+/// left out, and stays a blank in the strip.
+fn render_thumbs(
+    file: String,
+    generation: usize,
+    pages: Vec<usize>,
+    size: (u32, u32),
+    tx: Sender<Msg>,
+) {
+    std::thread::spawn(move || {
+        for page in pages {
+            let image = match render::page(&file, page, false, size) {
+                Ok(rendered) => rendered.image,
+                Err(_) => None,
+            };
+            if let Some(image) = image {
+                let thumb = Thumb {
+                    file: file.clone(),
+                    generation,
+                    page,
+                    image,
+                };
+                if tx.send(Msg::Thumbnail(Box::new(thumb))).is_err() {
+                    return;
+                }
+            }
+        }
+    });
+}
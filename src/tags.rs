@@ -0,0 +1,84 @@
+//! Tags attached to documents, like `toread` or `thesis`.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use anyhow::Result;
+use serde_json::Value;
+
+use crate::state;
+
+const FILE: &str = "tags.json";
+
+#[derive(Debug, Default)]
+pub struct Tags {
+    map: BTreeMap<String, BTreeSet<String>>,
+}
+
+impl Tags {
+    pub fn load() -> Result<Tags> {
+        let mut tags = Tags::default();
+        if let Value::Object(files) = state::load(FILE)? {
+            for (file, list) in files {
+                let set = list
+                    .as_array()
+                    .map(|l| {
+                        l.iter()
+                            .filter_map(|t| t.as_str().map(String::from))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                tags.map.insert(file, set);
+            }
+        }
+        Ok(tags)
+    }
+
+    pub fn save(&self) -> Result<()> {
+        let value: serde_json::Map<String, Value> = self
+            .map
+            .iter()
+            .filter(|(_, set)| !set.is_empty())
+            .map(|(file, set)| (file.clone(), set.iter().cloned().collect()))
+            .collect();
+        state::save(FILE, &Value::Object(value))
+    }
+
+    pub fn get(&self, file: &str) -> Vec<String> {
+        self.map
+            .get(&state::key(file))
+            .map(|s| s.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn has(&self, file: &str, tag: &str) -> bool {
+        self.map
+            .get(&state::key(file))
+            .map(|s| s.contains(tag))
+            .unwrap_or(false)
+    }
+
+    pub fn add(&mut self, file: &str, tag: &str) {
+        self.map
+            .entry(state::key(file))
+            .or_default()
+            .insert(tag.to_string());
+    }
+
+    pub fn remove(&mut self, file: &str, tag: &str) {
+        if let Some(set) = self.map.get_mut(&state::key(file)) {
+            set.remove(tag);
+        }
+    }
+
+    /// Carry tags over to a file's new name. `old` no longer exists, so it
+    /// has to be given as the absolute path it had.
+    pub fn rename(&mut self, old: &str, new: &str) {
+        if let Some(set) = self.map.remove(old) {
+            self.map.insert(state::key(new), set);
+        }
+    }
+
+    pub fn forget(&mut self, key: &str) {
+        self.map.remove(key);
+    }
+}
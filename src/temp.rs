@@ -0,0 +1,94 @@
+//! Temporary files for handing data to other programs. They go in a
+//! directory of their own with a random name that only this user can get
+//! into, made afresh each time, so nobody else on the machine can guess the
+//! name and put something there first. The directory goes when it's dropped.
+
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::hash::{BuildHasher, Hasher};
+use std::io::{self, Write};
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+
+use anyhow::Result;
+
+pub struct TempDir {
+    path: PathBuf,
+}
+
+/// 64 random bits, from the seed the standard library gets from the system
+/// for its hash maps
+fn random() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u32(std::process::id());
+    hasher.finish()
+}
+
+impl TempDir {
+    /// A new directory in the system's temporary one, named `prefix` and a
+    /// random suffix
+    pub fn new(prefix: &str) -> Result<TempDir> {
+        TempDir::new_in(&env::temp_dir(), prefix)
+    }
+
+    /// A new directory like `new`'s, in `parent`
+    pub fn new_in(parent: &Path, prefix: &str) -> Result<TempDir> {
+        let mut tries = 0;
+        loop {
+            let path = parent.join(format!("{}-{:016x}", prefix, random()));
+            match fs::DirBuilder::new().mode(0o700).create(&path) {
+                Ok(()) => return Ok(TempDir { path }),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists && tries < 8 => tries += 1,
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Write `data` to a new file called `name` in the directory
+    pub fn write(&self, name: &str, data: &[u8]) -> Result<PathBuf> {
+        let path = self.path.join(name);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(0o600)
+            .open(&path)?;
+        file.write_all(data)?;
+        Ok(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn directories_are_private_and_go_when_dropped() {
+        let (a, b) = (
+            TempDir::new("termpdf-test").unwrap(),
+            TempDir::new("termpdf-test").unwrap(),
+        );
+        assert_ne!(a.path, b.path);
+        let mode = fs::metadata(&a.path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+
+        let file = a.write("data.txt", b"hello").unwrap();
+        assert_eq!(fs::read(&file).unwrap(), b"hello");
+        assert!(a.write("data.txt", b"again").is_err());
+
+        let path = a.path.clone();
+        drop(a);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,79 @@
+//! Low level terminal handling that isn't specific to drawing pages.
+
+use std::io::{self, stdout, Write};
+use std::os::unix::io::AsRawFd;
+
+use anyhow::Result;
+
+/// Confine scrolling to every row but the last, which is kept for the status
+/// line. Anything that scrolls (a stray newline after an image, say) then
+/// can't push the page off the top of the screen or drag the status line up
+/// with it.
+pub fn set_scroll_region(rows: u16) -> Result<()> {
+    let mut stdout = stdout();
+    write!(stdout, "\x1b[1;{}r", rows.saturating_sub(1).max(1))?;
+    stdout.flush()?;
+    Ok(())
+}
+
+pub fn reset_scroll_region() -> Result<()> {
+    let mut stdout = stdout();
+    write!(stdout, "\x1b[r")?;
+    stdout.flush()?;
+    Ok(())
+}
+
+/// Write `text` on the bottom row. The text never reaches the last column,
+/// because writing there makes some terminals wrap and scroll the screen.
+pub fn status_line(text: &str) -> Result<()> {
+    let (cols, rows) = termion::terminal_size()?;
+    let line: String = text.chars().take(cols.saturating_sub(1) as usize).collect();
+
+    write_sequence(
+        format!(
+            "{}{}{}",
+            termion::cursor::Goto(1, rows),
+            termion::clear::CurrentLine,
+            line
+        )
+        .as_bytes(),
+    )
+}
+
+/// Send `data` to the terminal in one go, with nothing else written in the
+/// middle of it. Images are megabytes of escape sequence, and a terminal that
+/// gets half of one followed by the status line shows garbage. A terminal
+/// that's slow to read (over ssh, say) can leave stdout non-blocking after
+/// a full pipe, so this waits for room rather than giving up part way.
+pub fn write_sequence(data: &[u8]) -> Result<()> {
+    let stdout = stdout();
+    // Held until the end, so other threads printing have to wait
+    let mut lock = stdout.lock();
+    lock.flush()?;
+    let fd = lock.as_raw_fd();
+    let mut rest = data;
+    while !rest.is_empty() {
+        // SAFETY: `rest` is valid for `rest.len()` bytes, and fd is stdout,
+        // which is open for as long as `lock` is held
+        let written = unsafe { libc::write(fd, rest.as_ptr().cast(), rest.len()) };
+        if written >= 0 {
+            rest = &rest[written as usize..];
+            continue;
+        }
+        let error = io::Error::last_os_error();
+        match error.kind() {
+            io::ErrorKind::Interrupted => {}
+            io::ErrorKind::WouldBlock => {
+                let mut poll = libc::pollfd {
+                    fd,
+                    events: libc::POLLOUT,
+                    revents: 0,
+                };
+                // SAFETY: one pollfd, which lives across the call
+                unsafe { libc::poll(&mut poll, 1, -1) };
+            }
+            _ => return Err(error.into()),
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,291 @@
+//! Getting the text out of a document, for handing to other programs.
+
+use std::ops::Range;
+
+use anyhow::{bail, Result};
+use pdfium_render::prelude::*;
+
+use crate::query::Query;
+use crate::render;
+use crate::search::Rect;
+
+/// Parse a page range as typed after a command: nothing for the current
+/// page, `N`, `N-M` (either end may be left off) or `%` for every page.
+/// Pages are numbered from 1; the range returned is of page indices.
+pub fn parse_range(arg: &str, current: usize, length: usize) -> Result<Range<usize>> {
+    let arg = arg.trim();
+    let page = |s: &str, default: usize| -> Result<usize> {
+        if s.is_empty() {
+            return Ok(default);
+        }
+        match s.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= length => Ok(n),
+            _ => bail!("No page {} (there are {})", s, length),
+        }
+    };
+    let (first, last) = match arg {
+        "" => (current + 1, current + 1),
+        "%" => (1, length),
+        _ => match arg.split_once('-') {
+            Some((a, b)) => (page(a.trim(), 1)?, page(b.trim(), length)?),
+            None => {
+                let n = page(arg, current + 1)?;
+                (n, n)
+            }
+        },
+    };
+    if first > last {
+        bail!("Backwards page range {}", arg);
+    }
+    Ok(first - 1..last)
+}
+
+/// The text of `pages` of `file`, with a form feed between pages
+pub fn extract(file: &str, pages: Range<usize>) -> Result<String> {
+    let answer = render::ask(file, &Query::Text { pages })?;
+    Ok(answer.as_str().unwrap_or_default().to_string())
+}
+
+/// `extract`, for the renderer
+pub fn extract_in(document: &PdfDocument, pages: Range<usize>) -> Result<String> {
+    let mut text = String::new();
+    for p in pages {
+        let page = document.pages().get(p as u16)?;
+        if !text.is_empty() {
+            text.push('\x0c');
+        }
+        text.push_str(&page.text()?.all());
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+/// The text in part of a page, given as fractions `(x, y, w, h)` of the
+/// page image as displayed, which is `size` pixels and may have been turned
+/// to fit the terminal
+pub fn in_region(file: &str, p: usize, rect: Rect, size: (u32, u32)) -> Result<String> {
+    let answer = render::ask(
+        file,
+        &Query::Region {
+            page: p,
+            rect,
+            size,
+        },
+    )?;
+    Ok(answer.as_str().unwrap_or_default().to_string())
+}
+
+/// `in_region`, for the renderer
+pub fn region_in(
+    document: &PdfDocument,
+    p: usize,
+    (x, y, w, h): Rect,
+    size: (u32, u32),
+) -> Result<String> {
+    let page = document.pages().get(p as u16)?;
+    let (width, height) = (page.width().value as f64, page.height().value as f64);
+
+    // Landscape pages are turned 90° clockwise when the terminal is tall,
+    // so undo that: across the screen is up the page
+    let rotated = width > height && size.1 > size.0;
+    let (left, top, right, bottom) = if rotated {
+        (y, 1.0 - (x + w), y + h, 1.0 - x)
+    } else {
+        (x, y, x + w, y + h)
+    };
+
+    // PDF coordinates go up from the bottom of the page
+    let rect = PdfRect::new_from_values(
+        ((1.0 - bottom) * height) as f32,
+        (left * width) as f32,
+        ((1.0 - top) * height) as f32,
+        (right * width) as f32,
+    );
+    let text = page.text()?.inside_rect(rect);
+    Ok(text.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// The words on page `p`, with whitespace tidied up
+pub fn page_text(document: &PdfDocument, p: usize) -> String {
+    document
+        .pages()
+        .get(p as u16)
+        .ok()
+        .and_then(|page| page.text().ok().map(|t| t.all()))
+        .map(|text| text.split_whitespace().collect::<Vec<_>>().join(" "))
+        .unwrap_or_default()
+}
+
+/// The label of every page, empty where there isn't one
+pub fn page_labels(document: &PdfDocument) -> Vec<String> {
+    document
+        .pages()
+        .iter()
+        .map(|page| page.label().unwrap_or_default().to_string())
+        .collect()
+}
+
+/// The value of a roman numeral like "xiv" or "XIV"
+fn roman(s: &str) -> Option<usize> {
+    let digit = |c: char| match c.to_ascii_lowercase() {
+        'i' => Some(1),
+        'v' => Some(5),
+        'x' => Some(10),
+        'l' => Some(50),
+        'c' => Some(100),
+        'd' => Some(500),
+        'm' => Some(1000),
+        _ => None,
+    };
+    let digits: Vec<usize> = s.chars().map(digit).collect::<Option<_>>()?;
+    let mut value: isize = 0;
+    for (i, &d) in digits.iter().enumerate() {
+        match digits.get(i + 1) {
+            Some(&next) if next > d => value -= d as isize,
+            _ => value += d as isize,
+        }
+    }
+    (value > 0).then_some(value as usize)
+}
+
+/// The page labelled `wanted`. Failing an exact match, case and the way a
+/// roman numeral is written are ignored, so `:iv` finds "IV" (or "iiii").
+pub fn find_label(labels: &[String], wanted: &str) -> Option<usize> {
+    let wanted = wanted.trim();
+    if let Some(p) = labels.iter().position(|l| l == wanted) {
+        return Some(p);
+    }
+    let lower = wanted.to_lowercase();
+    if let Some(p) = labels.iter().position(|l| l.to_lowercase() == lower) {
+        return Some(p);
+    }
+    let value = roman(wanted)?;
+    labels.iter().position(|l| roman(l) == Some(value))
+}
+
+/// Runs of three words, which are distinctive enough to recognise a page by
+fn shingles(text: &str) -> Vec<String> {
+    let words: Vec<&str> = text.split_whitespace().take(200).collect();
+    words.windows(3).map(|w| w.join(" ")).collect()
+}
+
+/// How far from the old position to look for the page that was being read
+const ANCHOR_SEARCH: usize = 10;
+
+/// The page near `near` (of `length`, which was `old_length`) that reads
+/// most like `anchor`, if one is close enough. Recompiling a LaTeX document
+/// often adds or removes pages before the one being read, which would
+/// otherwise leave the reader somewhere else. As many pages as the document
+/// gained or lost are most likely to have come or gone before it, so the
+/// pages that far on (or back) are looked at as well as the ones nearby.
+pub fn find_anchor(
+    length: usize,
+    old_length: usize,
+    anchor: &str,
+    near: usize,
+    page_text: impl Fn(usize) -> String,
+) -> Option<usize> {
+    let wanted = shingles(anchor);
+    if wanted.is_empty() || length == 0 {
+        return None;
+    }
+    let last_page = length - 1;
+    let shifted = (near + length).saturating_sub(old_length).min(last_page);
+    let around = |p: usize| p.saturating_sub(ANCHOR_SEARCH)..=(p + ANCHOR_SEARCH).min(last_page);
+    let mut pages: Vec<usize> = around(near).chain(around(shifted)).collect();
+    pages.sort_unstable();
+    pages.dedup();
+    let distance = |p: usize| p.abs_diff(near).min(p.abs_diff(shifted));
+
+    let mut best: Option<(usize, f64)> = None;
+    for p in pages {
+        let text = page_text(p);
+        let found = wanted.iter().filter(|s| text.contains(s.as_str())).count();
+        let score = found as f64 / wanted.len() as f64;
+        // Ties go to the page nearest where it was, or where it moved to
+        let better = match best {
+            None => true,
+            Some((q, s)) => score > s || (score == s && distance(p) < distance(q)),
+        };
+        if better {
+            best = Some((p, score));
+        }
+    }
+    best.filter(|(_, score)| *score >= 0.5).map(|(p, _)| p)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(parse_range("", 4, 10).unwrap(), 4..5);
+        assert_eq!(parse_range("%", 4, 10).unwrap(), 0..10);
+        assert_eq!(parse_range("3", 4, 10).unwrap(), 2..3);
+        assert_eq!(parse_range("3-5", 4, 10).unwrap(), 2..5);
+        assert_eq!(parse_range(" 2 - 4 ", 4, 10).unwrap(), 1..4);
+    }
+
+    #[test]
+    fn open_ended_ranges() {
+        assert_eq!(parse_range("-3", 4, 10).unwrap(), 0..3);
+        assert_eq!(parse_range("8-", 4, 10).unwrap(), 7..10);
+    }
+
+    #[test]
+    fn bad_ranges() {
+        for arg in ["0", "11", "5-3", "x", "1-11"] {
+            assert!(parse_range(arg, 4, 10).is_err(), "{}", arg);
+        }
+    }
+
+    #[test]
+    fn roman_numerals() {
+        assert_eq!(roman("xiv"), Some(14));
+        assert_eq!(roman("XIV"), Some(14));
+        assert_eq!(roman("iiii"), Some(4));
+        assert_eq!(roman("mcmxc"), Some(1990));
+        assert_eq!(roman(""), None);
+        assert_eq!(roman("abc"), None);
+    }
+
+    #[test]
+    fn labels() {
+        let labels: Vec<String> = ["i", "ii", "iii", "iv", "1", "2"]
+            .iter()
+            .map(|s| s.to_string())
+            .collect();
+        assert_eq!(find_label(&labels, "2"), Some(5));
+        assert_eq!(find_label(&labels, "IV"), Some(3));
+        assert_eq!(find_label(&labels, "iiii"), Some(3));
+        assert_eq!(find_label(&labels, "A-1"), None);
+    }
+
+    #[test]
+    fn exact_labels_come_first() {
+        let labels = vec!["A".to_string(), "a".to_string()];
+        assert_eq!(find_label(&labels, "a"), Some(1));
+        assert_eq!(find_label(&labels, "A"), Some(0));
+    }
+
+    const ANCHOR: &str = "the quick brown fox jumps over the lazy dog";
+
+    #[test]
+    fn anchor_found_where_pages_came_before_it() {
+        // Two pages were added, so page 5 is most likely page 7 now
+        let page_text = |p| match p {
+            7 => ANCHOR.to_string(),
+            _ => "nothing like it at all".to_string(),
+        };
+        assert_eq!(find_anchor(20, 18, ANCHOR, 5, page_text), Some(7));
+    }
+
+    #[test]
+    fn anchor_not_found() {
+        let page_text = |_| "nothing like it at all".to_string();
+        assert_eq!(find_anchor(20, 18, ANCHOR, 5, page_text), None);
+        assert_eq!(find_anchor(20, 18, "", 5, |_| ANCHOR.to_string()), None);
+        assert_eq!(find_anchor(0, 18, ANCHOR, 5, |_| ANCHOR.to_string()), None);
+    }
+}
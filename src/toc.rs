@@ -0,0 +1,168 @@
+//! The table of contents: the document's outline drawn over the page as a
+//! list, indented by level, to pick a section from and go to it.
+
+use std::io::{stdout, Write};
+use std::sync::mpsc::Receiver;
+
+use anyhow::Result;
+use termion::event::Key;
+
+use crate::outline::Entry;
+use crate::{term, Msg};
+
+pub struct Toc {
+    entries: Vec<Entry>,
+    /// What the entries are narrowed down to, matched ignoring case
+    filter: String,
+    /// Whether keys are going into the filter
+    typing: bool,
+    /// The entries that match the filter, as indices into `entries`
+    shown: Vec<usize>,
+    /// Which of those is picked out
+    cursor: usize,
+    top: usize,
+}
+
+impl Toc {
+    /// The outline, with the entry for the part `page` is in picked out
+    pub fn new(entries: Vec<Entry>, page: usize) -> Toc {
+        let cursor = entries.iter().rposition(|e| e.page <= page).unwrap_or(0);
+        let mut toc = Toc {
+            shown: (0..entries.len()).collect(),
+            entries,
+            filter: String::new(),
+            typing: false,
+            cursor,
+            top: 0,
+        };
+        toc.scroll();
+        toc
+    }
+
+    fn height() -> usize {
+        termion::terminal_size()
+            .map(|(_, rows)| rows.saturating_sub(1) as usize)
+            .unwrap_or(24)
+            .max(1)
+    }
+
+    /// Narrow the entries down to the ones matching the filter, keeping the
+    /// one picked out if it still matches
+    fn narrow(&mut self) {
+        let picked = self.shown.get(self.cursor).copied();
+        let filter = self.filter.to_lowercase();
+        self.shown = (0..self.entries.len())
+            .filter(|&i| self.entries[i].title.to_lowercase().contains(&filter))
+            .collect();
+        self.cursor = picked
+            .and_then(|picked| self.shown.iter().position(|&i| i == picked))
+            .unwrap_or(0);
+        self.scroll();
+    }
+
+    /// Keep the entry picked out in view
+    fn scroll(&mut self) {
+        let height = Toc::height();
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + height {
+            self.top = self.cursor + 1 - height;
+        }
+    }
+
+    pub fn draw(&self) -> Result<()> {
+        let (cols, _) = termion::terminal_size()?;
+        let width = cols.saturating_sub(1) as usize;
+        let mut stdout = stdout();
+        write!(stdout, "{}", termion::clear::All)?;
+        let rows = self.shown.iter().skip(self.top).take(Toc::height());
+        for (row, &i) in rows.enumerate() {
+            let entry = &self.entries[i];
+            let indent = "  ".repeat(entry.level.saturating_sub(1));
+            let line = format!("{}{}", indent, entry.title);
+            let number = format!("  {}", entry.page + 1);
+            let room = width.saturating_sub(number.chars().count());
+            let mut line: String = line.chars().take(room).collect();
+            line.push_str(&" ".repeat(room - line.chars().count()));
+            line.push_str(&number);
+            let (on, off) = match self.top + row == self.cursor {
+                true => (
+                    termion::style::Invert.to_string(),
+                    termion::style::Reset.to_string(),
+                ),
+                false => (String::new(), String::new()),
+            };
+            write!(
+                stdout,
+                "{}{}{}{}",
+                termion::cursor::Goto(1, row as u16 + 1),
+                on,
+                line,
+                off
+            )?;
+        }
+        stdout.flush()?;
+        term::status_line(&match self.typing {
+            true => format!("/{}", self.filter),
+            false if self.shown.is_empty() => format!("Nothing matches {}", self.filter),
+            false => format!(
+                "Contents  {}/{}  j/k to move, / to filter, Enter to go, q to close",
+                self.cursor + 1,
+                self.shown.len()
+            ),
+        })
+    }
+
+    /// Handle keys until an entry is picked, giving its page, or the table
+    /// is closed. Anything else that arrives meanwhile is handed back to be
+    /// dealt with afterwards.
+    pub fn run(&mut self, rx: &Receiver<Msg>, deferred: &mut Vec<Msg>) -> Result<Option<usize>> {
+        self.draw()?;
+        for msg in rx {
+            let key = match msg {
+                Msg::Key(key) => key,
+                other => {
+                    deferred.push(other);
+                    continue;
+                }
+            };
+            let last = self.shown.len().saturating_sub(1);
+            match (self.typing, key) {
+                (true, Key::Char('\n')) => self.typing = false,
+                (true, Key::Esc) => {
+                    self.typing = false;
+                    self.filter.clear();
+                    self.narrow();
+                }
+                (true, Key::Backspace) => {
+                    self.filter.pop();
+                    self.narrow();
+                }
+                (true, Key::Char(c)) => {
+                    self.filter.push(c);
+                    self.narrow();
+                }
+                (true, _) => continue,
+                (false, Key::Char('\n')) => {
+                    return Ok(self.shown.get(self.cursor).map(|&i| self.entries[i].page))
+                }
+                (false, Key::Char('q') | Key::Esc | Key::Char('\t')) => return Ok(None),
+                (false, Key::Char('/')) => self.typing = true,
+                (false, Key::Char('j') | Key::Down) => self.cursor = (self.cursor + 1).min(last),
+                (false, Key::Char('k') | Key::Up) => self.cursor = self.cursor.saturating_sub(1),
+                (false, Key::Char(' ') | Key::PageDown | Key::Ctrl('f')) => {
+                    self.cursor = (self.cursor + Toc::height()).min(last)
+                }
+                (false, Key::Char('b') | Key::PageUp | Key::Ctrl('b')) => {
+                    self.cursor = self.cursor.saturating_sub(Toc::height())
+                }
+                (false, Key::Char('g') | Key::Home) => self.cursor = 0,
+                (false, Key::Char('G') | Key::End) => self.cursor = last,
+                _ => continue,
+            }
+            self.scroll();
+            self.draw()?;
+        }
+        Ok(None)
+    }
+}
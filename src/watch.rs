@@ -0,0 +1,357 @@
+//! Reloading the document when it changes on disk, and the settings when
+//! the config file does. Where changes can't be watched for, as on NFS or
+//! FUSE file systems, whose changes from elsewhere never raise events, the
+//! document is looked at every so often instead.
+
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+use anyhow::{anyhow, Result};
+use notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
+
+use crate::{config, Msg};
+
+/// What's done when a document changes on disk
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AutoRefresh {
+    /// Reload it
+    On,
+    /// Say it's changed on the status line, and leave reloading it to `r`
+    Notify,
+    /// Nothing
+    Off,
+}
+
+impl AutoRefresh {
+    pub fn parse(s: &str) -> Option<AutoRefresh> {
+        match s {
+            "on" | "true" | "yes" => Some(AutoRefresh::On),
+            "notify" => Some(AutoRefresh::Notify),
+            "off" | "false" | "no" => Some(AutoRefresh::Off),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AutoRefresh::On => "on",
+            AutoRefresh::Notify => "notify",
+            AutoRefresh::Off => "off",
+        }
+    }
+}
+
+/// Watches the directory of the file being read, rather than the file
+/// itself, so files that are replaced rather than rewritten (as LaTeX tools
+/// and most editors do) keep being noticed.
+pub struct Watcher {
+    /// None if the file system can't be watched at all
+    debouncer: Option<Debouncer<RecommendedWatcher>>,
+    file: Arc<Mutex<PathBuf>>,
+    dir: Option<PathBuf>,
+    /// The file looked at every `interval` instead, and when it last changed
+    polled: Arc<Mutex<Option<(PathBuf, Stamp)>>>,
+    /// The directory of it, while it's looked at rather than watched
+    polled_dir: Option<PathBuf>,
+    /// Zero to never look
+    interval: Duration,
+    /// Whether the thread that looks has been started
+    polling: bool,
+    tx: Sender<Msg>,
+    /// Watching the config file's directory, if there is one
+    _config: Option<Debouncer<RecommendedWatcher>>,
+}
+
+/// How long to wait for a file that's being written before reloading it
+/// anyway
+const PATIENCE: Duration = Duration::from_secs(30);
+
+/// When a file was last changed, and how long it was then
+type Stamp = Option<(SystemTime, u64)>;
+
+fn stamp(file: &Path) -> Stamp {
+    let metadata = std::fs::metadata(file).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Whether `dir` is on a file system that's shared over the network or
+/// served by FUSE, whose changes made elsewhere raise no events
+#[cfg(target_os = "linux")]
+fn remote(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    // NFS, SMB, CIFS, SMB2, FUSE, 9p, Coda, AFS and Ceph
+    const REMOTE: &[i64] = &[
+        0x6969,
+        0x517b,
+        0xff53_4d42,
+        0xfe53_4d42,
+        0x6573_5546,
+        0x0102_1997,
+        0x7375_7245,
+        0x5346_414f,
+        0x00c3_6400,
+    ];
+    let path = match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    // SAFETY: statfs only writes to `fs`, which is big enough for it
+    let mut fs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut fs) } != 0 {
+        return false;
+    }
+    REMOTE.contains(&(fs.f_type as i64))
+}
+
+#[cfg(target_os = "macos")]
+fn remote(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+    const REMOTE: &[&str] = &["nfs", "smbfs", "afpfs", "webdav", "macfuse", "osxfuse"];
+    let path = match std::ffi::CString::new(dir.as_os_str().as_bytes()) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    // SAFETY: statfs only writes to `fs`, which is big enough for it
+    let mut fs: libc::statfs = unsafe { std::mem::zeroed() };
+    if unsafe { libc::statfs(path.as_ptr(), &mut fs) } != 0 {
+        return false;
+    }
+    // SAFETY: statfs leaves the name nul-terminated
+    let name = unsafe { std::ffi::CStr::from_ptr(fs.f_fstypename.as_ptr()) };
+    REMOTE.contains(&&*name.to_string_lossy())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn remote(_dir: &Path) -> bool {
+    false
+}
+
+/// Look at the file in `polled` every `interval`, and send `Msg::Changed`
+/// when it's changed
+fn poll(polled: Arc<Mutex<Option<(PathBuf, Stamp)>>>, interval: Duration, tx: Sender<Msg>) {
+    loop {
+        thread::sleep(interval);
+        let file = match &*polled.lock().unwrap() {
+            Some((file, last)) if stamp(file) != *last => file.clone(),
+            _ => continue,
+        };
+        wait_until_written(&file, &tx);
+        if let Some((f, last)) = &mut *polled.lock().unwrap() {
+            if *f == file {
+                *last = stamp(&file);
+            }
+        }
+        if tx.send(Msg::Changed).is_err() {
+            return;
+        }
+    }
+}
+
+/// Why `file` looks like it's still being written (by a download, or
+/// latexmk), if it does
+fn being_written(file: &Path) -> Option<&'static str> {
+    let mut f = match File::open(file) {
+        Ok(f) => f,
+        Err(_) => return Some("missing"),
+    };
+    let metadata = match f.metadata() {
+        Ok(m) => m,
+        Err(_) => return Some("missing"),
+    };
+    if metadata.len() == 0 {
+        return Some("empty");
+    }
+    let age = metadata
+        .modified()
+        .ok()
+        .and_then(|m| SystemTime::now().duration_since(m).ok());
+    if age.is_some_and(|age| age < Duration::from_millis(500)) {
+        return Some("still changing");
+    }
+
+    // Writers that lock the file hold an exclusive lock while they write
+    // SAFETY: flock only looks at the descriptor, which `f` keeps open
+    if unsafe { libc::flock(f.as_raw_fd(), libc::LOCK_SH | libc::LOCK_NB) } != 0 {
+        return Some("locked");
+    }
+
+    // A complete PDF ends with %%EOF, give or take some whitespace
+    let tail = metadata.len().min(1024);
+    let mut end = vec![0; tail as usize];
+    if f.seek(SeekFrom::End(-(tail as i64))).is_err() || f.read_exact(&mut end).is_err() {
+        return Some("unreadable");
+    }
+    if !end.windows(5).any(|w| w == b"%%EOF") {
+        return Some("incomplete");
+    }
+    None
+}
+
+/// Wait for `file` to be written completely, saying so if it takes a while
+fn wait_until_written(file: &Path, tx: &Sender<Msg>) {
+    let start = SystemTime::now();
+    let mut told = false;
+    while let Some(why) = being_written(file) {
+        if start.elapsed().map_or(true, |e| e > PATIENCE) {
+            return;
+        }
+        if !told {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let _ = tx.send(Msg::Notice(format!(
+                "Waiting for {} to be written ({})",
+                name, why
+            )));
+            told = true;
+        }
+        thread::sleep(Duration::from_millis(250));
+    }
+}
+
+/// Send `Msg::ReloadConfig` whenever the config file is written, in a
+/// directory watcher of its own so it's kept whichever document is open
+fn watch_config(tx: Sender<Msg>) -> Result<Debouncer<RecommendedWatcher>> {
+    let file = config::path();
+    let dir = config::config_dir();
+    let watched = file.clone();
+    let mut debouncer = new_debouncer(
+        Duration::from_millis(500),
+        None,
+        move |res: DebounceEventResult| {
+            if res.is_ok_and(|events| events.iter().any(|e| e.path == watched)) {
+                let _ = tx.send(Msg::ReloadConfig);
+            }
+        },
+    )
+    .map_err(|e| anyhow!("Couldn't create file watcher: {:?}", e.kind))?;
+    debouncer
+        .watcher()
+        .watch(&dir, RecursiveMode::NonRecursive)
+        .map_err(|e| anyhow!("Couldn't watch {}: {:?}", dir.display(), e.kind))?;
+    Ok(debouncer)
+}
+
+fn absolute(file: &str) -> PathBuf {
+    let path = Path::new(file);
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+impl Watcher {
+    /// Watching for changes, or looking for them every `interval` where it
+    /// can't
+    pub fn new(tx: Sender<Msg>, interval: Duration) -> Watcher {
+        let tx_config = tx.clone();
+        let tx_file = tx.clone();
+        let file = Arc::new(Mutex::new(PathBuf::new()));
+        let watched = file.clone();
+        let debouncer = new_debouncer(
+            Duration::from_secs(2),
+            None,
+            move |res: DebounceEventResult| {
+                let events = match res {
+                    Ok(events) => events,
+                    Err(_) => return,
+                };
+                let file = watched.lock().unwrap().clone();
+                if events.iter().any(|e| e.path == file) {
+                    // Loading a half written file would fail, or show a
+                    // truncated document
+                    wait_until_written(&file, &tx_file);
+                    let _ = tx_file.send(Msg::Changed);
+                }
+            },
+        )
+        .ok();
+
+        Watcher {
+            debouncer,
+            file,
+            dir: None,
+            polled: Arc::new(Mutex::new(None)),
+            polled_dir: None,
+            interval,
+            polling: false,
+            tx,
+            _config: watch_config(tx_config).ok(),
+        }
+    }
+
+    /// Start watching `file` instead of whatever was watched before, saying
+    /// so if it has to be looked at every so often instead
+    pub fn watch(&mut self, file: &str) -> Result<Option<String>> {
+        let file = absolute(file);
+        let dir = match file.parent() {
+            Some(d) => d.to_path_buf(),
+            None => return Ok(None),
+        };
+        *self.file.lock().unwrap() = file.clone();
+
+        if self.dir.as_ref() == Some(&dir) {
+            self.poll(None);
+            return Ok(None);
+        }
+        if self.polled_dir.as_ref() == Some(&dir) {
+            self.poll(Some(file));
+            return Ok(None);
+        }
+        if let Some(old) = self.dir.take() {
+            if let Some(debouncer) = &mut self.debouncer {
+                let _ = debouncer.watcher().unwatch(&old);
+            }
+        }
+        self.polled_dir = None;
+        let remote = !self.interval.is_zero() && remote(&dir);
+        let watched = match &mut self.debouncer {
+            // Events would only come for changes made here
+            _ if remote => return Ok(self.poll_dir(dir, file, None)),
+            Some(debouncer) => debouncer
+                .watcher()
+                .watch(&dir, RecursiveMode::NonRecursive)
+                .map_err(|e| anyhow!("Couldn't watch {}: {:?}", dir.display(), e.kind)),
+            None => Err(anyhow!("Couldn't create file watcher")),
+        };
+        match watched {
+            Ok(()) => {
+                self.dir = Some(dir);
+                self.poll(None);
+                Ok(None)
+            }
+            Err(e) if self.interval.is_zero() => Err(e),
+            Err(e) => {
+                let why = format!(
+                    "{}, so looking for changes every {}s",
+                    e,
+                    self.interval.as_secs_f64()
+                );
+                Ok(self.poll_dir(dir, file, Some(why)))
+            }
+        }
+    }
+
+    fn poll_dir(&mut self, dir: PathBuf, file: PathBuf, why: Option<String>) -> Option<String> {
+        self.polled_dir = Some(dir);
+        self.poll(Some(file));
+        why
+    }
+
+    /// Look at `file` every `interval` from now on, or nothing for None
+    fn poll(&mut self, file: Option<PathBuf>) {
+        let mut polled = self.polled.lock().unwrap();
+        match (file, &*polled) {
+            // Already looking, and knowing when it last changed
+            (Some(file), Some((f, _))) if *f == file => {}
+            (file, _) => *polled = file.map(|f| (f.clone(), stamp(&f))),
+        }
+        if polled.is_some() && !self.polling {
+            let (polled, interval, tx) = (self.polled.clone(), self.interval, self.tx.clone());
+            thread::spawn(move || poll(polled, interval, tx));
+            self.polling = true;
+        }
+    }
+}